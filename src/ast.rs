@@ -1,3 +1,8 @@
+//! The syntax tree of a module.
+//!
+//! `pub` is parsed on every item, but nothing enforces it yet: besides modules, the `pub_kw` of an item is only
+//! printed by the print_ast backend.
+
 use crate::span::{BoxedSpan, Span};
 
 #[derive(Debug)]
@@ -6,7 +11,10 @@ pub struct AstModule {
 	pub fns: Vec<FnDef>,
 	pub trait_defs: Vec<TraitDef>,
 	pub trait_impls: Vec<TraitImpl>,
-	pub impls: Vec<Impl>
+	pub impls: Vec<Impl>,
+	pub structs: Vec<StructDef>,
+	pub enums: Vec<EnumDef>,
+	pub consts: Vec<ConstDef>,
 }
 
 // impl AstModule {
@@ -23,6 +31,7 @@ pub struct Mod {
 
 #[derive(Debug)]
 pub struct FnDef {
+	#[allow(dead_code)]
 	pub pub_kw: Option<Span<()>>,
 	pub name: Span<String>,
 	pub generics: Option<Generics>,
@@ -32,8 +41,26 @@ pub struct FnDef {
 	pub body: Span<Block>,
 }
 
+/// A `const`, whose uses are replaced by its value, or a `static`, a single value all its uses refer to
+#[derive(Debug)]
+pub struct ConstDef {
+	#[allow(dead_code)]
+	pub pub_kw: Option<Span<()>>,
+	pub kind: ConstKind,
+	pub name: Span<String>,
+	pub type_: Span<Type>,
+	pub value: BoxedSpan<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstKind {
+	Const,
+	Static,
+}
+
 #[derive(Debug)]
 pub struct FnSignatureDef {
+	#[allow(dead_code)]
 	pub pub_kw: Option<Span<()>>,
 	pub name: Span<String>,
 	pub generics: Option<Generics>,
@@ -44,6 +71,7 @@ pub struct FnSignatureDef {
 
 #[derive(Debug)]
 pub struct TraitDef {
+	#[allow(dead_code)]
 	pub pub_kw: Option<Span<()>>,
 	pub name: Span<String>,
 	pub generics: Option<Generics>,
@@ -53,6 +81,40 @@ pub struct TraitDef {
 	pub types: Vec<(Span<TypeInTrait>, Vec<Span<Trait>>)>
 }
 
+#[derive(Debug)]
+pub struct StructDef {
+	#[allow(dead_code)]
+	pub pub_kw: Option<Span<()>>,
+	pub name: Span<String>,
+	pub generics: Option<Generics>,
+	pub where_clause: Option<WhereClause>,
+	pub fields: Fields,
+}
+
+#[derive(Debug)]
+pub struct EnumDef {
+	#[allow(dead_code)]
+	pub pub_kw: Option<Span<()>>,
+	pub name: Span<String>,
+	pub generics: Option<Generics>,
+	pub where_clause: Option<WhereClause>,
+	pub variants: Vec<Variant>,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+	pub name: Span<String>,
+	pub fields: Fields,
+}
+
+/// The fields of a struct or of an enum variant
+#[derive(Debug)]
+pub enum Fields {
+	Unit,
+	Tuple(Vec<Span<Type>>),
+	Named(Vec<FnArg>),
+}
+
 #[derive(Debug)]
 pub struct TraitImpl {
 	pub generics: Option<Generics>,
@@ -73,7 +135,7 @@ pub struct Impl {
 }
 
 impl Impl {
-	pub fn to_trait_impl(self, trait_: Span<Trait>) -> TraitImpl {
+	pub fn into_trait_impl(self, trait_: Span<Trait>) -> TraitImpl {
 		TraitImpl {
 		    generics: self.generics,
 		    trait_,
@@ -86,24 +148,32 @@ impl Impl {
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub enum Type {
+	#[default]
 	Empty,
-	// Array(Box<Self>),
-	// Tuple(Vec<Span<Self>>),
+	/// `!`, the type of what never has a value
+	Never,
+	Tuple(Vec<Span<Type>>),
+	Array(Box<Span<Type>>, Span<ArrayLen>),
+	/// A function type, like `fn(int) -> int`
+	Fn(Vec<Span<Type>>, Box<Span<Type>>),
 	Other {
 		name: Span<String>,
+		// There are no generic types to apply yet
+		#[allow(dead_code)]
 		generics: Vec<Span<Type>>,
 	},
 }
 
-impl Default for Type {
-	fn default() -> Self {
-		Self::Empty
-	}
+#[derive(Debug, Clone)]
+pub enum ArrayLen {
+	Literal(usize),
+	/// A generic, like the `N` in `[T; N]`, or a constant
+	Named(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Trait {
 	pub name: Span<String>,
 	pub generics: Vec<Span<Type>>,
@@ -112,6 +182,8 @@ pub struct Trait {
 #[derive(Debug)]
 pub struct TypeInTrait {
 	pub name: Span<String>,
+	// Generic associated types are parsed, but not supported by the checker
+	#[allow(dead_code)]
 	pub generics: Vec<Span<String>>,
 }
 
@@ -119,15 +191,51 @@ pub type Generics = Vec<Span<String>>;
 pub type WhereClause = Vec<TypeBound>;
 pub type TypeBound = (Span<Type>, Vec<Span<Trait>>);
 pub type FnArg = (Span<String>, Span<Type>);
+pub type Label = Span<String>;
 
 #[derive(Debug)]
 pub enum Statement {
 	Returning(Expr),
 	NonReturning(Expr),
+	/// The pattern, its type, its value, and the block of a `let ... else`, run when the pattern doesn't match
+	Let(Span<Pattern>, Option<Span<Type>>, Option<BoxedSpan<Expr>>, Option<Block>),
+}
+
+#[derive(Debug)]
+pub enum Pattern {
+	Wildcard,
+	/// A name, which is a unit struct or variant if there's one with that name
+	Binding {
+		mutable: Option<Span<()>>,
+		name: Span<String>,
+		/// The pattern after `@`, that the value has to match too
+		subpattern: Option<Box<Span<Pattern>>>,
+	},
+	/// A literal, and whether it's negated, like `-1`
+	Literal(Literal, bool),
+	Tuple(Vec<Span<Pattern>>),
+	Array(Vec<Span<Pattern>>),
+	/// A unit struct or variant, like `Shape::Empty`
+	Path(Vec<Span<String>>),
+	/// A tuple struct or variant, like `Some(x)`
+	TupleStruct(Vec<Span<String>>, Vec<Span<Pattern>>),
+	/// A struct or a struct variant, like `Point { x, y: 0, .. }`, and whether it has `..`
+	Struct(Vec<Span<String>>, Vec<(Span<String>, Span<Pattern>)>, bool),
+	/// Alternatives, like `1 | 2`
+	Or(Vec<Span<Pattern>>),
+}
+
+#[derive(Debug)]
+pub struct MatchArm {
+	pub pattern: Span<Pattern>,
+	pub guard: Option<BoxedSpan<Expr>>,
+	pub body: BoxedSpan<Expr>,
 }
 
 pub type Block = Vec<BoxedSpan<Statement>>;
 
+// The spans of the operators are kept for diagnostics, none of which point at an operator yet
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum Expr {
 	Literal(Literal),
@@ -136,20 +244,61 @@ pub enum Expr {
 	Mul(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
 	Div(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
 	Pow(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Eq(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Ne(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Lt(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Le(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Gt(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Ge(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	And(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	Or(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
 	Not(BoxedSpan<Expr>),
+	Neg(BoxedSpan<Expr>),
+	Cast(BoxedSpan<Expr>, Span<Type>),
+	Tuple(Vec<BoxedSpan<Expr>>),
+	Array(Vec<BoxedSpan<Expr>>),
+	Index(BoxedSpan<Expr>, BoxedSpan<Expr>),
+	/// A field of a struct, or an element of a tuple, like `.0`
+	Field(BoxedSpan<Expr>, Span<String>),
+	/// A struct or an enum variant with named fields, like `Point { x: 1, y: 2 }`
+	Struct(Vec<Span<String>>, Vec<(Span<String>, BoxedSpan<Expr>)>),
+	Assign(BoxedSpan<Expr>, Span<()>, BoxedSpan<Expr>),
+	/// A block used as an expression, like the body of a match arm
+	Block(Block),
 	FnCall(BoxedSpan<Expr>, Vec<BoxedSpan<Expr>>),
+	/// A closure like `|a, b: int| a + b`, with its return type if written
+	Closure(Vec<(Span<Pattern>, Option<Span<Type>>)>, Option<Span<Type>>, BoxedSpan<Expr>),
+	/// A call of a method on a receiver, like `v.len()`
+	MethodCall(BoxedSpan<Expr>, Span<String>, Vec<BoxedSpan<Expr>>),
+	/// `value?`, which gives back the output of the value or returns early
+	Try(BoxedSpan<Expr>),
 	If(
 		BoxedSpan<Expr>,
 		Block,
 		Vec<(BoxedSpan<Expr>, Block)>,
 		Option<Block>,
 	),
+	Match(BoxedSpan<Expr>, Vec<MatchArm>),
+	/// `let PATTERN = value` as the condition of `if` and `while`, true when the value matches.
+	/// The variables of the pattern are bound in the block the condition guards.
+	Let(Span<Pattern>, BoxedSpan<Expr>),
+	While(Option<Label>, BoxedSpan<Expr>, Block),
+	Loop(Option<Label>, Block),
+	For(Option<Label>, Span<Pattern>, BoxedSpan<Expr>, Block),
+	Break(Option<Label>, Option<BoxedSpan<Expr>>),
+	Continue(Option<Label>),
+	/// `return`, which leaves the function with the value
+	Return(Option<BoxedSpan<Expr>>),
 	Ident(Vec<Span<String>>),
 }
 
 #[derive(Debug)]
 pub enum Literal {
-	Float(f64),
-	Int(i64),
+	/// The value and the type suffix, if any
+	Float(f64, Option<Span<String>>),
+	/// The value, which may not fit in its type, and the type suffix, if any
+	Int(u128, Option<Span<String>>),
 	String(String),
+	Char(char),
+	Bool(bool),
 }
@@ -1,14 +1,31 @@
+use std::{convert::TryFrom, rc::Rc};
+
 use crate::{
-	ast::Expr,
+	ast::{self, Expr},
+	checker::{
+		consts::ConstValue,
+		db::{TypeDB, TypeId},
+		types::{FloatType, IntType, Primitive},
+	},
 	error::{ErrorCode, NonLocatedError, Return},
+	hir::{BodyId, Const},
+	mir::{Body, Callee, Mir, Operand, Rvalue, Terminator, RETURN_PLACE},
+	modules::ModuleTree,
+	mono::InstanceId,
 	options::CodegenOptions,
 	span::Span,
 };
 
+mod gc;
+
+use gc::{Heap, Object};
+
+use super::{Site, BACKTRACE_VAR, MAX_CALL_DEPTH, PANIC_EXIT_CODE, STACK_OVERFLOW};
+
 pub struct Codegen;
 
 impl super::Backend for Codegen {
-	fn eval_expr(&self, expr: Span<Expr>, options: CodegenOptions) -> Return<String> {
+	fn eval_expr(&self, _expr: Span<Expr>, options: CodegenOptions) -> Return<String> {
 		if options.lib {
 			// Error (No main)
 			NonLocatedError::new(
@@ -20,11 +37,757 @@ impl super::Backend for Codegen {
 		} else {
 			// Execute it
 
-			Ok(format!(""))
+			Ok(String::new())
+		}
+	}
+
+	fn codegen(&self, _module: &ModuleTree, mir: &Mir, options: CodegenOptions) -> Return<String> {
+		if options.lib {
+			NonLocatedError::new(
+				ErrorCode::NoMainError,
+				"Can't interpret library as it has no entry point".to_string(),
+			)
+			.display()?;
+			unreachable!()
+		}
+		let db = mir.db;
+		let main = match db.main_fn() {
+			Some(main) => main,
+			None => {
+				NonLocatedError::new(
+					ErrorCode::NoMainError,
+					"`main` function not found in crate".to_string(),
+				)
+				.display()?;
+				unreachable!()
+			}
+		};
+		let mut interpreter = Interpreter::new(mir, &options);
+		// The body of an instance has its id
+		let main = mir.mono.instances.iter().position(|instance| instance.fn_ == main).unwrap();
+		let result = interpreter.call(main, Vec::new());
+		if options.gc_stats {
+			eprintln!("{}", interpreter.heap.stats);
+		}
+		match result {
+			Ok(Value::Empty) => (),
+			Ok(result) => println!("{}", result.show(db, &interpreter.heap)),
+			Err(Unwind::Panic(message)) => {
+				report_panic(&message, &interpreter.backtrace);
+				std::process::exit(PANIC_EXIT_CODE)
+			}
+		}
+		Ok(String::new())
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum Value {
+	Empty,
+	/// Any integer fits in an `i128`, the type gives its range
+	Int(i128, IntType),
+	/// `f32`s are kept rounded to their precision
+	Float(f64, FloatType),
+	Str(Rc<str>),
+	Char(char),
+	Bool(bool),
+	/// Tuples and arrays can't be modified, so their elements are shared
+	Tuple(Rc<[Value]>),
+	Array(Rc<[Value]>),
+	Adt(Rc<Adt>),
+	/// An instance of a function used as a value
+	Fn(InstanceId),
+	Closure(Rc<Closure>),
+	/// A `Ref`, as the index of its value in the heap
+	Ref(usize),
+	/// A `Vec`, as the index of its elements in the heap
+	Vec(usize),
+	/// A `String`, as the index of its text in the heap
+	String(usize),
+}
+
+/// A value of a struct or of an enum
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Adt {
+	type_: TypeId,
+	variant: usize,
+	fields: Vec<Value>,
+}
+
+/// A closure, with the values of the variables it captures
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub struct Closure {
+	body: BodyId,
+	captures: Vec<Value>,
+}
+
+impl Value {
+	fn adt(type_: TypeId, variant: usize, fields: Vec<Value>) -> Self {
+		Self::Adt(Rc::new(Adt {
+			type_,
+			variant,
+			fields,
+		}))
+	}
+
+	/// How the value is printed, the names of the variants and fields come from the db
+	fn show(&self, db: &TypeDB, heap: &Heap) -> String {
+		match self {
+			Self::Empty => "()".to_string(),
+			Self::Int(..) | Self::Float(..) | Self::Str(_) | Self::Char(_) | Self::Bool(_) => display(self),
+			Self::Tuple(values) if values.len() == 1 => format!("({},)", values[0].show(db, heap)),
+			Self::Tuple(values) => format!("({})", join(values, db, heap)),
+			Self::Array(values) => format!("[{}]", join(values, db, heap)),
+			Self::Adt(adt) => {
+				let (name, fields) = db.types[adt.type_].variants[adt.variant];
+				match fields {
+					_ if adt.fields.is_empty() => name.to_string(),
+					ast::Fields::Named(names) => {
+						let fields: Vec<String> = names
+							.iter()
+							.zip(&adt.fields)
+							.map(|((name, _), value)| format!("{}: {}", name, value.show(db, heap)))
+							.collect();
+						format!("{} {{ {} }}", name, fields.join(", "))
+					}
+					_ => format!("{}({})", name, join(&adt.fields, db, heap)),
+				}
+			}
+			Self::Fn(_) | Self::Closure(_) => "<fn>".to_string(),
+			Self::Ref(_) => "<ref>".to_string(),
+			Self::Vec(index) => format!("[{}]", join(heap.get(*index).values(), db, heap)),
+			Self::String(index) => match heap.get(*index) {
+				Object::String(s) => s.clone(),
+				x => unreachable!("Not a string: {:?}", x),
+			},
+		}
+	}
+}
+
+fn join(values: &[Value], db: &TypeDB, heap: &Heap) -> String {
+	values
+		.iter()
+		.map(|v| v.show(db, heap))
+		.collect::<Vec<String>>()
+		.join(", ")
+}
+
+/// A function that was running when the program panicked, and the call it was making
+struct TraceFrame {
+	name: String,
+	/// Whether it's from a library allowed to use builtins, whose calls aren't shown as the location
+	in_lib: bool,
+	location: String,
+}
+
+/// Print the message of a panic, where it happened and, if asked for, the Lama call stack
+fn report_panic(message: &str, backtrace: &[TraceFrame]) {
+	// Like with `#[track_caller]`, a panic inside the libraries is reported where they were called from
+	match backtrace.iter().find(|frame| !frame.in_lib) {
+		Some(frame) => eprintln!("thread 'main' panicked at '{}', {}", message, frame.location),
+		None => eprintln!("thread 'main' panicked at '{}'", message),
+	}
+	if std::env::var(BACKTRACE_VAR).is_ok_and(|value| value != "0") {
+		eprintln!("stack backtrace:");
+		for (i, frame) in backtrace.iter().enumerate() {
+			eprintln!("{:>4}: {}", i, frame.name);
+			eprintln!("             at {}", frame.location);
+		}
+	} else {
+		eprintln!(
+			"note: run with `{}=1` environment variable to display a backtrace",
+			BACKTRACE_VAR
+		);
+	}
+}
+
+/// Stops the program, going up the calls until it ends
+enum Unwind {
+	/// The program panicked with the message
+	Panic(String),
+}
+
+type Eval = Result<Value, Unwind>;
+
+struct Frame<'m> {
+	body: &'m Body,
+	locals: Vec<Value>,
+	/// The call it is making, or the builtin it is running
+	site: Option<Site<'m>>,
+}
+
+struct Interpreter<'a, 'b, 'm> {
+	db: &'b TypeDB<'a>,
+	mir: &'m Mir<'a, 'b>,
+	frames: Vec<Frame<'m>>,
+	heap: Heap,
+	/// The value of each `const` and `static`, by their id
+	globals: Vec<Value>,
+	/// Whether integer overflow wraps around instead of panicking
+	release: bool,
+	/// Whether the filesystem builtins fail instead of accessing it
+	sandbox: bool,
+	/// The call stack when the program panicked, from the innermost function
+	backtrace: Vec<TraceFrame>,
+}
+
+impl<'a, 'b, 'm> Interpreter<'a, 'b, 'm> {
+	fn new(mir: &'m Mir<'a, 'b>, options: &CodegenOptions) -> Self {
+		Self {
+			db: mir.db,
+			mir,
+			frames: Vec::new(),
+			heap: Heap::new(options.heap_limit, options.gc_stress),
+			globals: (0..mir.db.consts.len())
+				.map(|id| global_value(mir.db.const_value(id).expect("constants are evaluated when checking")))
+				.collect(),
+			release: options.release,
+			sandbox: options.sandbox,
+			backtrace: Vec::new(),
+		}
+	}
+
+	/// Move an object to the heap, collecting garbage first if it's full
+	fn alloc(&mut self, object: Object) -> Result<usize, Unwind> {
+		if self.heap.is_full() {
+			let locals = self.frames.iter().flat_map(|frame| frame.locals.iter());
+			self.heap.collect(locals.chain(object.values()));
+		}
+		self.heap.alloc(object).map_err(|limit| {
+			Unwind::Panic(format!("out of memory: the heap limit of {} objects was reached", limit))
+		})
+	}
+
+	fn vec_mut(&mut self, index: usize) -> &mut Vec<Value> {
+		match self.heap.get_mut(index) {
+			Object::Vec(values) => values,
+			x => unreachable!("Not a vec: {:?}", x),
+		}
+	}
+
+	fn string_mut(&mut self, index: usize) -> &mut String {
+		match self.heap.get_mut(index) {
+			Object::String(s) => s,
+			x => unreachable!("Not a string: {:?}", x),
+		}
+	}
+
+	/// Run a builtin, the ones of `Ref`, `Vec` and `String` work on the heap
+	fn builtin(&mut self, name: &str, args: Vec<Value>) -> Eval {
+		match name.split_once('_') {
+			Some(("REF" | "VEC" | "STRING", _)) => (),
+			Some(("IO" | "FS", _)) => return Ok(host(name, &args, self.sandbox)),
+			_ => return builtin(name, &args, self.release),
+		}
+		let usize = |n: usize| Value::Int(n as i128, IntType::Usize);
+		Ok(match (name, &args[..]) {
+			("REF_NEW", [value]) => Value::Ref(self.alloc(Object::Ref(value.clone()))?),
+			("REF_GET", [Value::Ref(index)]) => self.heap.get(*index).values()[0].clone(),
+			("REF_SET", [Value::Ref(index), value]) => {
+				*self.heap.get_mut(*index) = Object::Ref(value.clone());
+				Value::Empty
+			}
+			("VEC_NEW", []) => Value::Vec(self.alloc(Object::Vec(Vec::new()))?),
+			("VEC_LEN", [Value::Vec(index)]) => usize(self.heap.get(*index).values().len()),
+			("VEC_INDEX", [Value::Vec(vec), Value::Int(index, _)]) => {
+				let values = self.heap.get(*vec).values();
+				values[check_index(values.len(), *index)?].clone()
+			}
+			("VEC_SET", [Value::Vec(vec), Value::Int(index, _), value]) => {
+				let values = self.vec_mut(*vec);
+				let index = check_index(values.len(), *index)?;
+				values[index] = value.clone();
+				Value::Empty
+			}
+			("VEC_PUSH", [Value::Vec(vec), value]) => {
+				self.vec_mut(*vec).push(value.clone());
+				Value::Empty
+			}
+			("VEC_INSERT", [Value::Vec(vec), Value::Int(index, _), value]) => {
+				let values = self.vec_mut(*vec);
+				if *index > values.len() as i128 {
+					return Err(Unwind::Panic(format!(
+						"insertion index (is {}) should be <= len (is {})",
+						index,
+						values.len()
+					)));
+				}
+				values.insert(*index as usize, value.clone());
+				Value::Empty
+			}
+			("VEC_REMOVE", [Value::Vec(vec), Value::Int(index, _)]) => {
+				let values = self.vec_mut(*vec);
+				if *index >= values.len() as i128 {
+					return Err(Unwind::Panic(format!(
+						"removal index (is {}) should be < len (is {})",
+						index,
+						values.len()
+					)));
+				}
+				values.remove(*index as usize)
+			}
+			("VEC_CLEAR", [Value::Vec(vec)]) => {
+				self.vec_mut(*vec).clear();
+				Value::Empty
+			}
+			("STRING_NEW", []) => Value::String(self.alloc(Object::String(String::new()))?),
+			("STRING_FROM", [Value::Str(s)]) => Value::String(self.alloc(Object::String(s.to_string()))?),
+			("STRING_PUSH", [Value::String(string), Value::Char(c)]) => {
+				self.string_mut(*string).push(*c);
+				Value::Empty
+			}
+			("STRING_PUSH_STR", [Value::String(string), Value::Str(s)]) => {
+				self.string_mut(*string).push_str(s);
+				Value::Empty
+			}
+			("STRING_LEN", [Value::String(string)]) => usize(self.string_mut(*string).chars().count()),
+			("STRING_AS_STR", [Value::String(string)]) => Value::Str(self.string_mut(*string).as_str().into()),
+			("STRING_SLICE", [Value::String(string), Value::Int(start, _), Value::Int(end, _)]) => {
+				let s = self.string_mut(*string);
+				let len = s.chars().count() as i128;
+				if *end > len {
+					return Err(Unwind::Panic(format!("char index {} is out of bounds of `{}`", end, s)));
+				}
+				if start > end {
+					return Err(Unwind::Panic(format!("slice index starts at {} but ends at {}", start, end)));
+				}
+				let slice: String = s.chars().skip(*start as usize).take((end - start) as usize).collect();
+				Value::Str(slice.into())
+			}
+			_ => unreachable!("Unknown builtin `{}` for {:?}", name, args),
+		})
+	}
+
+	fn frame(&mut self) -> &mut Frame<'m> {
+		self.frames.last_mut().unwrap()
+	}
+
+	/// Keep the call stack of a panic, before unwinding takes the frames down
+	fn trace(&mut self, unwind: Unwind) -> Unwind {
+		let db = self.db;
+		self.backtrace = self
+			.frames
+			.iter()
+			.rev()
+			.map(|frame| {
+				let fn_ = frame.body.fn_;
+				TraceFrame {
+					name: match frame.body.closure {
+						true => format!("{}::{{closure}}", db.fn_path(fn_)),
+						false => db.fn_path(fn_),
+					},
+					in_lib: !frame.body.closure && db.namespace(&db.fns[fn_].module).allow_builtins,
+					location: frame.site.map_or(String::new(), |site| site.to_string()),
+				}
+			})
+			.collect();
+		unwind
+	}
+
+	/// Run a body with the arguments, followed by the captured variables for a closure
+	fn call(&mut self, id: BodyId, args: Vec<Value>) -> Eval {
+		if self.frames.len() == MAX_CALL_DEPTH {
+			return Err(self.trace(Unwind::Panic(STACK_OVERFLOW.to_string())));
+		}
+		let body = &self.mir.bodies[id];
+		let mut locals = vec![Value::Empty; body.locals.len()];
+		for (local, arg) in locals[RETURN_PLACE + 1..].iter_mut().zip(args) {
+			*local = arg;
+		}
+		self.frames.push(Frame {
+			body,
+			locals,
+			site: None,
+		});
+		let result = self.run(body);
+		let mut frame = self.frames.pop().unwrap();
+		result.map(|()| std::mem::replace(&mut frame.locals[RETURN_PLACE], Value::Empty))
+	}
+
+	/// Run the blocks of the body from the first one, until it returns
+	fn run(&mut self, body: &'m Body) -> Result<(), Unwind> {
+		let mut block = 0;
+		loop {
+			let current = &body.blocks[block];
+			for statement in &current.statements {
+				let value = self.rvalue(&statement.value);
+				self.frame().locals[statement.place] = value;
+			}
+			block = match &current.terminator {
+				Terminator::Goto(target) => *target,
+				Terminator::Switch(operand, targets, otherwise) => {
+					let value = self.operand(operand);
+					let found = targets.iter().find(|(c, _)| const_value(c) == value);
+					found.map_or(*otherwise, |(_, target)| *target)
+				}
+				Terminator::SwitchVariant(operand, targets, otherwise) => {
+					let variant = match self.operand(operand) {
+						Value::Adt(adt) => adt.variant,
+						x => unreachable!("Not an enum: {:?}", x),
+					};
+					let found = targets.iter().find(|(v, _)| *v == variant);
+					found.map_or(*otherwise, |(_, target)| *target)
+				}
+				Terminator::Call {
+					callee,
+					args,
+					dest,
+					target,
+					span,
+				} => {
+					let args = args.iter().map(|arg| self.operand(arg)).collect();
+					self.frame().site = Some(Site::of(span));
+					let value = self.call_callee(callee, args)?;
+					self.frame().locals[*dest] = value;
+					*target
+				}
+				Terminator::Return => return Ok(()),
+				Terminator::Unreachable => unreachable!("Reached unreachable code"),
+			};
 		}
 	}
 
-	fn codegen(&self, module: crate::modules::ModuleTree, options: CodegenOptions) -> Return<String> {
-        todo!()
-    }
+	fn call_callee(&mut self, callee: &Callee, args: Vec<Value>) -> Eval {
+		match callee {
+			Callee::Instance(id) => self.call(*id, args),
+			Callee::Value(callee) => match self.operand(callee) {
+				Value::Fn(id) => self.call(id, args),
+				Value::Closure(closure) => {
+					let mut args = args;
+					args.extend(closure.captures.iter().cloned());
+					self.call(closure.body, args)
+				}
+				x => unreachable!("Not a function: {:?}", x),
+			},
+			Callee::Builtin(name) => self.builtin(name, args).map_err(|unwind| self.trace(unwind)),
+			Callee::Fn(..) | Callee::Method(..) => unreachable!("calls are to instances once the MIR is instantiated"),
+		}
+	}
+
+	fn operand(&self, operand: &Operand) -> Value {
+		match operand {
+			Operand::Local(id) => self.frames.last().unwrap().locals[*id].clone(),
+			Operand::Const(c) => const_value(c),
+		}
+	}
+
+	fn operands(&self, operands: &[Operand]) -> Vec<Value> {
+		operands.iter().map(|o| self.operand(o)).collect()
+	}
+
+	fn rvalue(&self, value: &Rvalue) -> Value {
+		match value {
+			Rvalue::Use(operand) => self.operand(operand),
+			Rvalue::Instance(id) => Value::Fn(*id),
+			Rvalue::Fn(..) => unreachable!("functions are instances once the MIR is instantiated"),
+			Rvalue::Global(id) => self.globals[*id].clone(),
+			Rvalue::Tuple(operands) => Value::Tuple(self.operands(operands).into()),
+			Rvalue::Array(operands) => Value::Array(self.operands(operands).into()),
+			Rvalue::Adt(id, variant, operands) => Value::adt(*id, *variant, self.operands(operands)),
+			Rvalue::Field(operand, index) => match self.operand(operand) {
+				Value::Tuple(values) | Value::Array(values) => values[*index].clone(),
+				Value::Adt(adt) => adt.fields[*index].clone(),
+				x => unreachable!("No field {} on {:?}", index, x),
+			},
+			Rvalue::Cast(operand, target) => cast(self.operand(operand), *target),
+			Rvalue::Closure(body, captures) => Value::Closure(Rc::new(Closure {
+				body: *body,
+				captures: self.operands(captures),
+			})),
+		}
+	}
+}
+
+/// Convert a primitive value with `as`
+fn cast(value: Value, target: Primitive) -> Value {
+	use Value::*;
+	match (value, target) {
+		(Int(i, _), Primitive::Int(t)) => Int(t.wrap(i), t),
+		(Int(i, _), Primitive::Float(t)) => Float(t.round(i as f64), t),
+		// Saturates, with NaN becoming 0
+		(Float(x, _), Primitive::Int(t)) => Int((x as i128).clamp(t.min(), t.max()), t),
+		(Float(x, _), Primitive::Float(t)) => Float(t.round(x), t),
+		(Bool(b), Primitive::Int(t)) => Int(b as i128, t),
+		(Char(c), Primitive::Int(t)) => Int(t.wrap(c as i128), t),
+		(Int(i, _), Primitive::Char) => Char(char::from(i as u8)),
+		(value, _) => value,
+	}
+}
+
+/// Run a builtin, which uses the arguments of the function it's in.
+/// Their names are the operation and the type they work on, like `ADD_U8`
+fn builtin(name: &str, args: &[Value], release: bool) -> Eval {
+	use Value::*;
+	let op = name.split_once('_').map_or(name, |(op, _)| op);
+	Ok(match (op, args) {
+		("PANIC", [Str(message)]) => return Err(Unwind::Panic(message.to_string())),
+		(_, [Int(a, t), Int(b, _)]) if matches!(op, "ADD" | "SUB" | "MUL" | "DIV" | "POW") => {
+			Int(int_operation(op, *a, *b, *t, release)?, *t)
+		}
+		("ADD", [Float(a, t), Float(b, _)]) => Float(t.round(a + b), *t),
+		("SUB", [Float(a, t), Float(b, _)]) => Float(t.round(a - b), *t),
+		("MUL", [Float(a, t), Float(b, _)]) => Float(t.round(a * b), *t),
+		("DIV", [Float(a, t), Float(b, _)]) => Float(t.round(a / b), *t),
+		("POW", [Float(a, t), Float(b, _)]) => Float(t.round(a.powf(*b)), *t),
+		("NOT", [Int(a, t)]) => Int(t.wrap(!a), *t),
+		("NOT", [Bool(a)]) => Bool(!a),
+		("NEG", [Int(a, t)]) => Int(int_operation("NEG", *a, 0, *t, release)?, *t),
+		("NEG", [Float(a, t)]) => Float(-a, *t),
+		("ABS", [Int(a, t)]) if *a < 0 => Int(int_operation("NEG", *a, 0, *t, release)?, *t),
+		("ABS", [Int(a, t)]) => Int(*a, *t),
+		("ABS", [Float(a, t)]) => Float(a.abs(), *t),
+		// These are exact, or correctly rounded like `sqrt`, so every backend gives the same result
+		("SQRT", [Float(a, t)]) => Float(t.round(a.sqrt()), *t),
+		("FLOOR", [Float(a, t)]) => Float(a.floor(), *t),
+		("CEIL", [Float(a, t)]) => Float(a.ceil(), *t),
+		("ROUND", [Float(a, t)]) => Float(a.round(), *t),
+		("TRUNC", [Float(a, t)]) => Float(a.trunc(), *t),
+		("FREXP", [Float(a, _)]) => {
+			let (mantissa, exp) = frexp(*a);
+			Tuple(Rc::new([Float(mantissa, FloatType::F64), Int(exp as i128, IntType::I32)]))
+		}
+		("LDEXP", [Float(a, _), Int(exp, _)]) => Float(ldexp(*a, *exp as i32), FloatType::F64),
+		("CHECKED", [Int(a, t), Int(b, _)]) => {
+			let op = name.split('_').nth(1).unwrap();
+			match int_operation(op, *a, *b, *t, false) {
+				Ok(result) => Tuple(Rc::new([Bool(true), Int(result, *t)])),
+				Err(_) => Tuple(Rc::new([Bool(false), Int(0, *t)])),
+			}
+		}
+		("INDEX", [Array(values), Int(index, _)]) => values[check_index(values.len(), *index)?].clone(),
+		("REPLACE", [Array(values), Int(index, _), value]) => {
+			let i = check_index(values.len(), *index)?;
+			let mut values = values.to_vec();
+			values[i] = value.clone();
+			Array(values.into())
+		}
+		("HASH", [Str(s)]) => Int(fnv1a(s.as_bytes()) as i128, IntType::U64),
+		("LEN", [Array(values)]) => Int(values.len() as i128, IntType::Usize),
+		("CONCAT", [Str(a), Str(b)]) => Str(format!("{}{}", a, b).into()),
+		("CHARS", [Str(s)]) => Int(s.chars().count() as i128, IntType::Usize),
+		("PREFIX", [Str(s), Int(count, _)]) => Str(s.chars().take(*count as usize).collect::<std::string::String>().into()),
+		("DISPLAY", [value]) => Str(display(value).into()),
+		("DEBUG", [Str(s)]) => Str(format!("{:?}", s).into()),
+		("DEBUG", [Char(c)]) => Str(format!("{:?}", c).into()),
+		("DEBUG", [Float(x, FloatType::F32)]) => Str(format!("{:?}", *x as f32).into()),
+		("DEBUG", [Float(x, FloatType::F64)]) => Str(format!("{:?}", x).into()),
+		("DEBUG", [value]) => Str(display(value).into()),
+		// The f32 converts to the same f64, which has the same digits
+		("FIXED", [Float(x, _), Int(precision, _)]) => Str(format!("{:.*}", *precision as usize, x).into()),
+		("EQ", [a, b]) => Bool(a == b),
+		("LT", [a, b]) => Bool(a < b),
+		("LE", [a, b]) => Bool(a <= b),
+		("GT", [a, b]) => Bool(a > b),
+		("GE", [a, b]) => Bool(a >= b),
+		_ => unreachable!("Unknown builtin `{}` for {:?}", name, args),
+	})
+}
+
+/// The text of a primitive value, like `{}` shows it
+fn display(value: &Value) -> String {
+	match value {
+		Value::Int(i, _) => i.to_string(),
+		Value::Float(x, FloatType::F32) => (*x as f32).to_string(),
+		Value::Float(x, FloatType::F64) => x.to_string(),
+		Value::Str(s) => s.to_string(),
+		Value::Char(c) => c.to_string(),
+		Value::Bool(b) => b.to_string(),
+		_ => unreachable!("Not a primitive: {:?}", value),
+	}
+}
+
+/// Run a builtin that uses the console or the filesystem.
+/// The ones that can fail give back whether they worked, and their output or their error
+fn host(name: &str, args: &[Value], sandbox: bool) -> Value {
+	use std::io::{BufRead, Write};
+	let result = |result: std::io::Result<String>| match result {
+		Ok(output) => Value::Tuple(Rc::new([Value::Bool(true), Value::Str(output.into())])),
+		Err(e) => Value::Tuple(Rc::new([Value::Bool(false), Value::Str(io_error(e).into())])),
+	};
+	match (name, args) {
+		("IO_PRINT", [Value::Str(s)]) => {
+			print!("{}", s);
+			// Text without a newline, like a prompt, has to show before reading the input
+			std::io::stdout().flush().ok();
+			Value::Empty
+		}
+		("IO_EPRINTLN", [Value::Str(s)]) => {
+			eprintln!("{}", s);
+			Value::Empty
+		}
+		("IO_READ_LINE", []) => {
+			let mut line = String::new();
+			result(std::io::stdin().lock().read_line(&mut line).map(|_| line))
+		}
+		("FS_READ" | "FS_WRITE", _) if sandbox => result(Err(std::io::Error::new(
+			std::io::ErrorKind::PermissionDenied,
+			"filesystem access is denied by --sandbox",
+		))),
+		("FS_READ", [Value::Str(path)]) => result(std::fs::read_to_string(&**path)),
+		("FS_WRITE", [Value::Str(path), Value::Str(contents)]) => {
+			result(std::fs::write(&**path, &**contents).map(|_| String::new()))
+		}
+		_ => unreachable!("Unknown builtin `{}` for {:?}", name, args),
+	}
+}
+
+/// The message of an I/O error, which the js backend gives for the same errors
+fn io_error(e: std::io::Error) -> String {
+	match e.kind() {
+		std::io::ErrorKind::NotFound => "file not found".to_string(),
+		std::io::ErrorKind::PermissionDenied => match e.into_inner() {
+			Some(message) => message.to_string(),
+			None => "permission denied".to_string(),
+		},
+		_ => e.to_string(),
+	}
+}
+
+/// The index as a `usize`, or a panic if it's out of bounds
+fn check_index(len: usize, index: i128) -> Result<usize, Unwind> {
+	match usize::try_from(index) {
+		Ok(i) if i < len => Ok(i),
+		_ => Err(Unwind::Panic(format!(
+			"index out of bounds: the len is {} but the index is {}",
+			len, index
+		))),
+	}
+}
+
+/// The 64 bits FNV-1a hash of the bytes, which the js backend computes the same way
+fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+		(hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+	})
+}
+
+/// Do integer arithmetic, which panics if it overflows the type, unless in release mode where it wraps around
+fn int_operation(op: &str, a: i128, b: i128, t: IntType, release: bool) -> Result<i128, Unwind> {
+	let (exact, wrapped, name) = match op {
+		"ADD" => (a.checked_add(b), a.wrapping_add(b), "add"),
+		"SUB" => (a.checked_sub(b), a.wrapping_sub(b), "subtract"),
+		"MUL" => (a.checked_mul(b), a.wrapping_mul(b), "multiply"),
+		"NEG" => (a.checked_neg(), a.wrapping_neg(), "negate"),
+		"DIV" if b == 0 => return Err(Unwind::Panic("attempt to divide by zero".to_string())),
+		// Dividing the minimum by -1 overflows even in release mode
+		"DIV" => (a.checked_div(b), a.wrapping_div(b), "divide"),
+		"POW" if b < 0 => return Err(Unwind::Panic("attempt to raise to a negative power".to_string())),
+		"POW" => (checked_pow(a, b), wrapping_pow(a, b), "multiply"),
+		_ => unreachable!("Unknown integer operation `{}`", op),
+	};
+	match exact {
+		Some(result) if t.contains(result) => Ok(result),
+		// The low bits of the result are right even if i128 overflowed
+		_ if release && op != "DIV" => Ok(t.wrap(wrapped)),
+		_ => Err(Unwind::Panic(format!("attempt to {} with overflow", name))),
+	}
+}
+
+/// Split a float into a mantissa in `[0.5, 1)` and a power of two, or give back zero, infinity and NaN with 0
+fn frexp(x: f64) -> (f64, i32) {
+	if x == 0.0 || !x.is_finite() {
+		return (x, 0);
+	}
+	let bits = x.to_bits();
+	let exp = ((bits >> 52) & 0x7ff) as i32;
+	if exp == 0 {
+		// Subnormals are scaled up to be normal first
+		let (mantissa, exp) = frexp(x * 2f64.powi(64));
+		return (mantissa, exp - 64);
+	}
+	let mantissa = f64::from_bits((bits & !(0x7ff << 52)) | (1022 << 52));
+	(mantissa, exp - 1022)
+}
+
+/// Multiply by a power of two, rounding only once if the result is subnormal, like C's `scalbn`
+fn ldexp(mut x: f64, mut exp: i32) -> f64 {
+	if exp > 1023 {
+		x *= 2f64.powi(1023);
+		exp -= 1023;
+		if exp > 1023 {
+			x *= 2f64.powi(1023);
+			exp = (exp - 1023).min(1023);
+		}
+	} else if exp < -1022 {
+		// Scaling to just above the subnormals keeps the rounding to the last multiplication
+		x *= 2f64.powi(-1022 + 53);
+		exp += 1022 - 53;
+		if exp < -1022 {
+			x *= 2f64.powi(-1022 + 53);
+			exp = (exp + 1022 - 53).max(-1022);
+		}
+	}
+	x * 2f64.powi(exp)
+}
+
+fn checked_pow(base: i128, exp: i128) -> Option<i128> {
+	match (base, exp) {
+		(_, 0) => Some(1),
+		(0 | 1, _) => Some(base),
+		(-1, _) => Some(if exp % 2 == 0 { 1 } else { -1 }),
+		_ => base.checked_pow(u32::try_from(exp).ok()?),
+	}
+}
+
+fn wrapping_pow(mut base: i128, mut exp: i128) -> i128 {
+	let mut result: i128 = 1;
+	while exp > 0 {
+		if exp & 1 == 1 {
+			result = result.wrapping_mul(base);
+		}
+		base = base.wrapping_mul(base);
+		exp >>= 1;
+	}
+	result
+}
+
+/// The value of a constant of the MIR
+fn const_value(c: &Const) -> Value {
+	match c {
+		Const::Empty => Value::Empty,
+		Const::Int(i, t) => Value::Int(*i, *t),
+		Const::Float(x, t) => Value::Float(*x, *t),
+		Const::Str(s) => Value::Str(s.clone()),
+		Const::Char(c) => Value::Char(*c),
+		Const::Bool(b) => Value::Bool(*b),
+	}
+}
+
+fn global_value(value: ConstValue) -> Value {
+	match value {
+		ConstValue::Const(c) => const_value(&c),
+		ConstValue::Tuple(values) => Value::Tuple(values.into_iter().map(global_value).collect()),
+		ConstValue::Array(values) => Value::Array(values.into_iter().map(global_value).collect()),
+		ConstValue::Adt(id, variant, fields) => Value::adt(id, variant, fields.into_iter().map(global_value).collect()),
+	}
+}
+
+/// The constant a value is, if it's of a primitive type or `()`
+fn to_const(value: Value) -> Option<Const> {
+	match value {
+		Value::Empty => Some(Const::Empty),
+		Value::Int(i, t) => Some(Const::Int(i, t)),
+		Value::Float(x, t) => Some(Const::Float(x, t)),
+		Value::Str(s) => Some(Const::Str(s)),
+		Value::Char(c) => Some(Const::Char(c)),
+		Value::Bool(b) => Some(Const::Bool(b)),
+		_ => None,
+	}
+}
+
+/// Run a builtin on constants at compile time, like the interpreter would, or give back the message it panics with.
+/// There is no result for the ones using the heap or the host
+pub fn fold_builtin(name: &str, args: &[Const], release: bool) -> Option<Result<Const, String>> {
+	match name.split_once('_') {
+		Some(("REF" | "VEC" | "STRING" | "IO" | "FS", _)) => None,
+		_ => match builtin(name, &args.iter().map(const_value).collect::<Vec<Value>>(), release) {
+			Ok(value) => to_const(value).map(Ok),
+			Err(Unwind::Panic(message)) => Some(Err(message)),
+		},
+	}
+}
+
+/// Convert a constant with `as` at compile time
+pub fn fold_cast(c: &Const, target: Primitive) -> Const {
+	to_const(cast(const_value(c), target)).expect("casts give back primitive values")
 }
@@ -0,0 +1,170 @@
+//! The heap of the values that can change, like the ones `Ref`s and `Vec`s hold.
+//! Unreachable objects are freed by a mark and sweep garbage collector
+
+use std::fmt::Display;
+
+use super::Value;
+
+/// How many objects can be live before the first collection
+const INITIAL_THRESHOLD: usize = 1024;
+
+#[derive(Default)]
+pub struct Stats {
+	collections: usize,
+	allocated: usize,
+	freed: usize,
+	/// The most objects that were live at once
+	peak: usize,
+}
+
+impl Display for Stats {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"gc: {} collections, {} objects allocated, {} freed, at most {} live",
+			self.collections, self.allocated, self.freed, self.peak
+		)
+	}
+}
+
+/// What a `Ref`, a `Vec` or a `String` points to
+#[derive(Debug)]
+pub enum Object {
+	Ref(Value),
+	Vec(Vec<Value>),
+	String(String),
+}
+
+impl Object {
+	/// The values the object holds
+	pub fn values(&self) -> &[Value] {
+		match self {
+			Self::Ref(value) => std::slice::from_ref(value),
+			Self::Vec(values) => values,
+			Self::String(_) => &[],
+		}
+	}
+}
+
+pub struct Heap {
+	/// The objects values point to, `None` for the free cells
+	cells: Vec<Option<Object>>,
+	free: Vec<usize>,
+	live: usize,
+	/// Garbage is collected before an allocation once this many objects are live
+	threshold: usize,
+	limit: Option<usize>,
+	/// Collect garbage before every allocation
+	stress: bool,
+	pub stats: Stats,
+}
+
+impl Heap {
+	pub fn new(limit: Option<usize>, stress: bool) -> Self {
+		Self {
+			cells: Vec::new(),
+			free: Vec::new(),
+			live: 0,
+			threshold: INITIAL_THRESHOLD,
+			limit,
+			stress,
+			stats: Stats::default(),
+		}
+	}
+
+	/// Whether garbage has to be collected before the next allocation
+	pub fn is_full(&self) -> bool {
+		self.stress || self.live >= self.threshold || self.at_limit()
+	}
+
+	fn at_limit(&self) -> bool {
+		self.limit.is_some_and(|limit| self.live >= limit)
+	}
+
+	/// Move the object to the heap, giving back its index, or the heap limit if it's reached
+	pub fn alloc(&mut self, object: Object) -> Result<usize, usize> {
+		if let Some(limit) = self.limit.filter(|_| self.at_limit()) {
+			return Err(limit);
+		}
+		let index = match self.free.pop() {
+			Some(index) => {
+				self.cells[index] = Some(object);
+				index
+			}
+			None => {
+				self.cells.push(Some(object));
+				self.cells.len() - 1
+			}
+		};
+		self.live += 1;
+		self.stats.allocated += 1;
+		self.stats.peak = self.stats.peak.max(self.live);
+		Ok(index)
+	}
+
+	pub fn get(&self, index: usize) -> &Object {
+		self.cells[index].as_ref().expect("Reference to a freed object")
+	}
+
+	pub fn get_mut(&mut self, index: usize) -> &mut Object {
+		self.cells[index].as_mut().expect("Reference to a freed object")
+	}
+
+	/// Free the objects that can't be reached from the roots
+	pub fn collect<'v>(&mut self, roots: impl Iterator<Item = &'v Value>) {
+		let mut marked = vec![false; self.cells.len()];
+		let mut pending = Vec::new();
+		for root in roots {
+			mark(root, &mut marked, &mut pending);
+		}
+		while let Some(index) = pending.pop() {
+			for value in self.get(index).values() {
+				mark(value, &mut marked, &mut pending);
+			}
+		}
+		for (index, cell) in self.cells.iter_mut().enumerate() {
+			if cell.is_some() && !marked[index] {
+				*cell = None;
+				self.free.push(index);
+				self.live -= 1;
+				self.stats.freed += 1;
+			}
+		}
+		self.threshold = INITIAL_THRESHOLD.max(self.live * 2);
+		self.stats.collections += 1;
+	}
+}
+
+/// Mark the objects the value points to, adding the ones not seen yet to `pending` to look inside them
+fn mark(value: &Value, marked: &mut [bool], pending: &mut Vec<usize>) {
+	match value {
+		Value::Ref(index) | Value::Vec(index) | Value::String(index) => {
+			if !marked[*index] {
+				marked[*index] = true;
+				pending.push(*index);
+			}
+		}
+		Value::Tuple(values) | Value::Array(values) => {
+			for value in values.iter() {
+				mark(value, marked, pending);
+			}
+		}
+		Value::Adt(adt) => {
+			for value in &adt.fields {
+				mark(value, marked, pending);
+			}
+		}
+		Value::Closure(closure) => {
+			for value in &closure.captures {
+				mark(value, marked, pending);
+			}
+		}
+		Value::Empty
+		| Value::Int(..)
+		| Value::Float(..)
+		| Value::Str(_)
+		| Value::Char(_)
+		| Value::Bool(_)
+		| Value::Fn(_) => (),
+	}
+}
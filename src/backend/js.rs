@@ -1,20 +1,335 @@
-use std::path::PathBuf;
+use crate::{
+	ast::{Expr, Fields},
+	checker::{
+		consts::ConstValue,
+		db::TypeDB,
+		types::{FloatType, IntType, Type},
+	},
+	error::{ErrorCode, NonLocatedError, Return},
+	hir::{BodyId, Const},
+	mir::{Body, Callee, Mir, Operand, Rvalue, Terminator, RETURN_PLACE},
+	modules::ModuleTree,
+	options::CodegenOptions,
+	span::Span,
+};
 
-use crate::{ast::Expr, error::Return, options::CodegenOptions, span::Span};
+use super::{Site, PANIC_EXIT_CODE};
+
+const RUNTIME: &str = include_str!("js/runtime.js");
 
 pub struct Codegen;
 
 impl super::Backend for Codegen {
-	fn eval_expr(&self, expr: Span<Expr>, options: CodegenOptions) -> Return<String> {
-		if options.lib {
-			// Generate all items
+	fn eval_expr(&self, _expr: Span<Expr>, _options: CodegenOptions) -> Return<String> {
+		Ok(String::new())
+	}
+
+	fn codegen(&self, _module: &ModuleTree, mir: &Mir, options: CodegenOptions) -> Return<String> {
+		let db = mir.db;
+		let mut out = format!(
+			"\"use strict\";\nconst $RELEASE = {};\nconst $PANIC_EXIT_CODE = {};\n{}",
+			options.release, PANIC_EXIT_CODE, RUNTIME
+		);
+		out.push('\n');
+		for type_ in &db.types {
+			let variants = type_
+				.variants
+				.iter()
+				.map(|(name, fields)| {
+					let names = match fields {
+						Fields::Named(fields) => format!(
+							"[{}]",
+							fields
+								.iter()
+								.map(|(name, _)| js_string(name))
+								.collect::<Vec<String>>()
+								.join(", ")
+						),
+						_ => "null".to_string(),
+					};
+					format!("[{}, {}]", js_string(name), names)
+				})
+				.collect::<Vec<String>>()
+				.join(", ");
+			out += &format!("$types[{}] = [{}];\n", js_string(&type_.path.join("::")), variants);
+		}
+		for id in 0..db.consts.len() {
+			out += &format!("const $global{} = {};\n", id, global_value(db, &db.const_value(id)?));
+		}
+		// Instances are named by their symbol, which is a valid js identifier
+		let symbols: Vec<String> = (0..mir.mono.instances.len()).map(|id| mir.mono.symbol(db, id)).collect();
+		for id in 0..mir.bodies.len() {
+			out += &BodyCompiler::new(db, &mir.bodies, &symbols, id).compile();
+		}
+		if !options.lib {
+			match db.main_fn() {
+				Some(main) => {
+					let main = mir.mono.instances.iter().position(|instance| instance.fn_ == main).unwrap();
+					out += &format!("$main({});\n", symbols[main]);
+				}
+				None => {
+					NonLocatedError::new(
+						ErrorCode::NoMainError,
+						"`main` function not found in crate".to_string(),
+					)
+					.display()?;
+					unreachable!()
+				}
+			}
+		}
+		Ok(out)
+	}
+}
+
+/// The name of the function that creates the closures of a body, given the variables they capture
+fn closure_name(id: BodyId) -> String {
+	format!("$closure{}", id)
+}
+
+fn float(x: f64, type_: FloatType) -> String {
+	match type_ {
+		FloatType::F64 => number(x),
+		FloatType::F32 => format!("new $Num({}, \"f32\")", number(type_.round(x))),
+	}
+}
+
+/// A js number, which spells infinities differently from Rust
+fn number(x: f64) -> String {
+	match x {
+		f64::INFINITY => "Infinity".to_string(),
+		f64::NEG_INFINITY => "-Infinity".to_string(),
+		x => format!("{:?}", x),
+	}
+}
+
+/// The js value of a constant
+fn const_value(c: &Const) -> String {
+	match c {
+		Const::Empty => "undefined".to_string(),
+		Const::Int(i, IntType::I64) => format!("{}n", i),
+		Const::Int(i, t) => format!("new $Num({}n, \"{}\")", i, t.name()),
+		Const::Float(x, t) => float(*x, *t),
+		Const::Str(s) => js_string(s),
+		Const::Char(c) => format!("new $Char({})", js_string(&c.to_string())),
+		Const::Bool(b) => b.to_string(),
+	}
+}
+
+fn global_value(db: &TypeDB, value: &ConstValue) -> String {
+	let list = |values: &[ConstValue]| values.iter().map(|v| global_value(db, v)).collect::<Vec<String>>().join(", ");
+	match value {
+		ConstValue::Const(c) => const_value(c),
+		ConstValue::Tuple(values) => format!("new $Tuple([{}])", list(values)),
+		ConstValue::Array(values) => format!("[{}]", list(values)),
+		ConstValue::Adt(id, variant, fields) => format!(
+			"new $Adt({}, {}, [{}])",
+			js_string(&db.types[*id].path.join("::")),
+			variant,
+			list(fields)
+		),
+	}
+}
+
+fn js_string(s: &str) -> String {
+	let mut r = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => r += "\\\"",
+			'\\' => r += "\\\\",
+			'\n' => r += "\\n",
+			'\r' => r += "\\r",
+			'\t' => r += "\\t",
+			c if (c as u32) < 0x20 => r += &format!("\\u{:04x}", c as u32),
+			c => r.push(c),
+		}
+	}
+	r.push('"');
+	r
+}
+
+/// Compiles a body into a js function.
+/// Its blocks are the cases of a `switch` on the one to run next, in a loop
+struct BodyCompiler<'a, 'b, 'm> {
+	db: &'b TypeDB<'a>,
+	/// The name of the function of each instance, whose body has its id
+	symbols: &'m [String],
+	id: BodyId,
+	body: &'m Body,
+	out: String,
+	indent: usize,
+}
+
+impl<'a, 'b, 'm> BodyCompiler<'a, 'b, 'm> {
+	fn new(db: &'b TypeDB<'a>, bodies: &'m [Body], symbols: &'m [String], id: BodyId) -> Self {
+		Self {
+			db,
+			symbols,
+			id,
+			body: &bodies[id],
+			out: String::new(),
+			indent: 0,
+		}
+	}
+
+	fn compile(mut self) -> String {
+		let body = self.body;
+		let local = |i: usize| format!("_{}", i);
+		let args: Vec<String> = (1..=body.args).map(local).collect();
+		let captures: Vec<String> = (body.args + 1..=body.args + body.captures).map(local).collect();
+		let name = self.db.fn_path(body.fn_);
+		// A closure is made by a function given the variables it captures
+		let (name, in_lib) = match body.closure {
+			true => {
+				self.line(format!("function {}({}) {{", closure_name(self.id), captures.join(", ")));
+				self.indent += 1;
+				self.line(format!("return ({}) => {{", args.join(", ")));
+				(format!("{}::{{closure}}", name), false)
+			}
+			false => {
+				self.line(format!("function {}({}) {{", self.symbols[self.id], args.join(", ")));
+				let entry = &self.db.fns[body.fn_];
+				(name, self.db.namespace(&entry.module).allow_builtins)
+			}
+		};
+		self.indent += 1;
+		let locals: Vec<String> = (0..body.locals.len())
+			.filter(|i| *i == RETURN_PLACE || *i > body.args + body.captures)
+			.map(local)
+			.collect();
+		self.line(format!("let {};", locals.join(", ")));
+		self.line(format!("const $frame = $enter({}, {});", js_string(&name), in_lib));
+		self.line("try {".to_string());
+		self.indent += 1;
+		if body.blocks.len() == 1 {
+			self.block(0);
 		} else {
-			// Generate only what is use from run
+			self.line("let $bb = 0;".to_string());
+			self.line("for (;;) switch ($bb) {".to_string());
+			for id in 0..body.blocks.len() {
+				self.line(format!("case {}:", id));
+				self.indent += 1;
+				self.block(id);
+				self.indent -= 1;
+			}
+			self.line("}".to_string());
 		}
-		Ok(format!(""))
+		self.indent -= 1;
+		self.line("} catch (e) {".to_string());
+		self.line("\tthrow $unwind(e);".to_string());
+		self.line("} finally {".to_string());
+		self.line("\t$stack.pop();".to_string());
+		self.line("}".to_string());
+		self.indent -= 1;
+		if body.closure {
+			self.line("};".to_string());
+			self.indent -= 1;
+		}
+		self.line("}".to_string());
+		self.out
+	}
+
+	fn line(&mut self, line: String) {
+		for _ in 0..self.indent {
+			self.out.push('\t');
+		}
+		self.out += &line;
+		self.out.push('\n');
 	}
 
-	fn codegen(&self, module: crate::modules::ModuleTree, options: CodegenOptions) -> Return<String> {
-        todo!()
-    }
+	fn block(&mut self, id: usize) {
+		let block = &self.body.blocks[id];
+		for statement in &block.statements {
+			let value = self.rvalue(&statement.value);
+			self.line(format!("_{} = {};", statement.place, value));
+		}
+		match &block.terminator {
+			Terminator::Goto(target) => self.jump(*target),
+			Terminator::Switch(operand, targets, otherwise) => {
+				let value = self.operand(operand);
+				for (c, target) in targets {
+					let test = match c {
+						Const::Bool(true) => value.clone(),
+						Const::Bool(false) => format!("!{}", value),
+						c => format!("$same({}, {})", value, const_value(c)),
+					};
+					self.line(format!("if ({}) {{ $bb = {}; continue; }}", test, target));
+				}
+				self.jump(*otherwise);
+			}
+			Terminator::SwitchVariant(operand, targets, otherwise) => {
+				let value = self.operand(operand);
+				for (variant, target) in targets {
+					self.line(format!("if ({}.variant === {}) {{ $bb = {}; continue; }}", value, variant, target));
+				}
+				self.jump(*otherwise);
+			}
+			Terminator::Call {
+				callee,
+				args,
+				dest,
+				target,
+				span,
+			} => {
+				let args: Vec<String> = args.iter().map(|a| self.operand(a)).collect();
+				let args = args.join(", ");
+				let call = match callee {
+					Callee::Instance(id) => format!("{}({})", self.symbols[*id], args),
+					Callee::Value(callee) => format!("{}({})", self.operand(callee), args),
+					Callee::Builtin(name) => format!("$builtins.{}({})", name, args),
+					Callee::Fn(..) | Callee::Method(..) => {
+						unreachable!("calls are to instances once the MIR is instantiated")
+					}
+				};
+				self.line(format!("$frame.site = {};", js_string(&Site::of(span).to_string())));
+				self.line(format!("_{} = {};", dest, call));
+				self.jump(*target);
+			}
+			Terminator::Return => self.line(format!("return _{};", RETURN_PLACE)),
+			Terminator::Unreachable => self.line("throw new Error(\"unreachable\");".to_string()),
+		}
+	}
+
+	/// Go to another block, there is none to go to when the body has a single block
+	fn jump(&mut self, target: usize) {
+		if self.body.blocks.len() > 1 {
+			self.line(format!("$bb = {}; continue;", target));
+		}
+	}
+
+	fn operand(&self, operand: &Operand) -> String {
+		match operand {
+			Operand::Local(id) => format!("_{}", id),
+			Operand::Const(c) => const_value(c),
+		}
+	}
+
+	fn operands(&self, operands: &[Operand]) -> String {
+		operands.iter().map(|o| self.operand(o)).collect::<Vec<String>>().join(", ")
+	}
+
+	fn rvalue(&self, value: &Rvalue) -> String {
+		match value {
+			Rvalue::Use(operand) => self.operand(operand),
+			Rvalue::Instance(id) => self.symbols[*id].clone(),
+			Rvalue::Fn(..) => unreachable!("functions are instances once the MIR is instantiated"),
+			Rvalue::Global(id) => format!("$global{}", id),
+			Rvalue::Tuple(operands) => format!("new $Tuple([{}])", self.operands(operands)),
+			Rvalue::Array(operands) => format!("[{}]", self.operands(operands)),
+			Rvalue::Adt(id, variant, operands) => format!(
+				"new $Adt({}, {}, [{}])",
+				js_string(&self.db.types[*id].path.join("::")),
+				variant,
+				self.operands(operands)
+			),
+			// Arrays are js arrays, tuples, structs and enums keep their fields in `items`
+			Rvalue::Field(operand, index) => match operand {
+				Operand::Local(id) if matches!(self.body.locals[*id].type_, Type::Array(..)) => {
+					format!("_{}[{}]", id, index)
+				}
+				_ => format!("{}.items[{}]", self.operand(operand), index),
+			},
+			Rvalue::Cast(operand, target) => format!("$cast({}, \"{}\")", self.operand(operand), target.name()),
+			Rvalue::Closure(id, captures) => format!("{}({})", closure_name(*id), self.operands(captures)),
+		}
+	}
 }
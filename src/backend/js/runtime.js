@@ -0,0 +1,607 @@
+// Runtime for the code generated by the js backend, which defines `$RELEASE` and `$PANIC_EXIT_CODE` before it
+//
+// Lama values are represented as:
+//  - `()`: undefined
+//  - i64: BigInt
+//  - f64: number
+//  - other numbers: $Num, holding a BigInt or a number and the name of the type
+//  - str: string
+//  - char: $Char, to tell it apart from a string
+//  - bool: boolean
+//  - tuples: $Tuple
+//  - arrays: Array
+//  - structs and enums: $Adt, whose names are registered in `$types`
+//  - `core::cell::Ref`: $Ref, left to the garbage collector of JavaScript
+//  - `std::vec::Vec`: $Vec, holding an Array
+//  - `std::string::String`: $String, holding a string
+
+/** The variants of each struct and enum, as their name and the names of their fields, null if they have none */
+const $types = {};
+
+class $Char {
+	constructor(c) {
+		this.c = c;
+	}
+
+	toString() {
+		return this.c;
+	}
+}
+
+class $Tuple {
+	constructor(items) {
+		this.items = items;
+	}
+
+	toString() {
+		const items = this.items.map((item) => $display(item));
+		return items.length === 1 ? `(${items[0]},)` : `(${items.join(", ")})`;
+	}
+}
+
+class $Adt {
+	constructor(type, variant, items) {
+		this.type = type;
+		this.variant = variant;
+		this.items = items;
+	}
+
+	toString() {
+		const [name, names] = $types[this.type][this.variant];
+		const items = this.items.map((item) => $display(item));
+		if (items.length === 0) return name;
+		if (names === null) return `${name}(${items.join(", ")})`;
+		return `${name} { ${items.map((item, i) => `${names[i]}: ${item}`).join(", ")} }`;
+	}
+}
+
+class $Ref {
+	constructor(value) {
+		this.value = value;
+	}
+
+	toString() {
+		return "<ref>";
+	}
+}
+
+class $Vec {
+	constructor(items) {
+		this.items = items;
+	}
+
+	toString() {
+		return `[${this.items.map((item) => $display(item)).join(", ")}]`;
+	}
+}
+
+class $String {
+	constructor(s) {
+		this.s = s;
+	}
+
+	toString() {
+		return this.s;
+	}
+}
+
+class $Num {
+	constructor(value, type) {
+		this.value = value;
+		this.type = type;
+	}
+
+	toString() {
+		return $display(this.value, this.type);
+	}
+}
+
+/** Whether each integer type is signed, and its size in bits */
+const $INT_TYPES = {
+	i8: [true, 8],
+	i16: [true, 16],
+	i32: [true, 32],
+	i64: [true, 64],
+	isize: [true, 64],
+	u8: [false, 8],
+	u16: [false, 16],
+	u32: [false, 32],
+	u64: [false, 64],
+	usize: [false, 64],
+};
+
+/** The functions being run, from the outermost, as `{ name, inLib, site }` with the call each one is making */
+const $stack = [];
+
+/** Start running a function, its frame has to be popped when it's done */
+function $enter(name, inLib) {
+	const frame = { name, inLib, site: null };
+	$stack.push(frame);
+	return frame;
+}
+
+class $Panic {
+	constructor(message) {
+		this.message = message;
+		// The frames are popped while the panic unwinds, so the call stack is kept from here
+		this.backtrace = $stack.map((frame) => ({ ...frame })).reverse();
+	}
+}
+
+function $panic(message) {
+	throw new $Panic(message);
+}
+
+/** The exception a function unwinds with, running out of the stack of JavaScript being the same panic as in the
+ * interpreter */
+function $unwind(e) {
+	if (e instanceof RangeError && e.message === "Maximum call stack size exceeded") {
+		return new $Panic("stack overflow: too many nested calls");
+	}
+	return e;
+}
+
+/** The BigInt or number inside a value */
+function $value(x) {
+	return x instanceof $Num ? x.value : x;
+}
+
+function $int(type, value) {
+	return type === "i64" ? value : new $Num(value, type);
+}
+
+function $float(type, value) {
+	return type === "f64" ? value : new $Num(Math.fround(value), type);
+}
+
+/** Wrap the value around to fit in the type, like two's complement arithmetic does */
+function $wrap(type, value) {
+	const [signed, bits] = $INT_TYPES[type];
+	return signed ? BigInt.asIntN(bits, value) : BigInt.asUintN(bits, value);
+}
+
+/** Give back the result of integer arithmetic, which panics if it overflows, unless in release mode where it wraps around */
+function $checked(type, operation, result) {
+	const wrapped = $wrap(type, result);
+	// Dividing the minimum by -1 overflows even in release mode
+	if (wrapped !== result && (!$RELEASE || operation === "divide")) {
+		$panic(`attempt to ${operation} with overflow`);
+	}
+	return $int(type, wrapped);
+}
+
+function $pow(type, base, exp) {
+	if (exp < 0n) $panic("attempt to raise to a negative power");
+	const [result, overflow] = $overflowingPow(type, base, exp);
+	if (overflow && !$RELEASE) $panic("attempt to multiply with overflow");
+	return $int(type, result);
+}
+
+/** The wrapped power, and whether it overflowed */
+function $overflowingPow(type, base, exp) {
+	// Square and multiply, wrapping every step so that big exponents don't make huge numbers
+	let result = 1n;
+	let overflow = false;
+	while (exp > 0n) {
+		if (exp & 1n) {
+			const product = result * base;
+			result = $wrap(type, product);
+			overflow = overflow || result !== product;
+		}
+		exp >>= 1n;
+		if (exp > 0n) {
+			const square = base * base;
+			base = $wrap(type, square);
+			overflow = overflow || base !== square;
+		}
+	}
+	return [result, overflow];
+}
+
+/** Integer arithmetic that gives back whether it worked and its result, instead of panicking */
+function $checkedOperation(type, op, a, b) {
+	let result;
+	switch (op) {
+		case "ADD":
+			result = a + b;
+			break;
+		case "SUB":
+			result = a - b;
+			break;
+		case "MUL":
+			result = a * b;
+			break;
+		case "DIV":
+			if (b === 0n) return new $Tuple([false, $int(type, 0n)]);
+			result = a / b;
+			break;
+		case "POW": {
+			const [power, overflow] = $overflowingPow(type, a, b);
+			return new $Tuple([!overflow, $int(type, overflow ? 0n : power)]);
+		}
+	}
+	const ok = $wrap(type, result) === result;
+	return new $Tuple([ok, $int(type, ok ? result : 0n)]);
+}
+
+/** Split a float into a mantissa in `[0.5, 1)` and a power of two, or give back zero, infinity and NaN with 0 */
+function $frexp(x) {
+	if (x === 0 || !Number.isFinite(x)) return [x, 0];
+	const view = new DataView(new ArrayBuffer(8));
+	view.setFloat64(0, x);
+	const exp = (view.getUint16(0) >> 4) & 0x7ff;
+	if (exp === 0) {
+		// Subnormals are scaled up to be normal first
+		const [mantissa, e] = $frexp(x * 2 ** 64);
+		return [mantissa, e - 64];
+	}
+	view.setUint16(0, (view.getUint16(0) & 0x800f) | (1022 << 4));
+	return [view.getFloat64(0), exp - 1022];
+}
+
+/** Multiply by a power of two, rounding only once if the result is subnormal, like C's `scalbn` */
+function $ldexp(x, exp) {
+	if (exp > 1023) {
+		x *= 2 ** 1023;
+		exp -= 1023;
+		if (exp > 1023) {
+			x *= 2 ** 1023;
+			exp = Math.min(exp - 1023, 1023);
+		}
+	} else if (exp < -1022) {
+		// Scaling to just above the subnormals keeps the rounding to the last multiplication
+		x *= 2 ** (-1022 + 53);
+		exp += 1022 - 53;
+		if (exp < -1022) {
+			x *= 2 ** (-1022 + 53);
+			exp = Math.max(exp + 1022 - 53, -1022);
+		}
+	}
+	return x * 2 ** exp;
+}
+
+/** Round half away from zero like Rust, where `Math.round` rounds half up */
+function $round(x) {
+	return x < 0 ? -Math.round(-x) : Math.round(x);
+}
+
+/** Convert a primitive value with `as` */
+function $cast(value, type) {
+	const x = $value(value);
+	if (type in $INT_TYPES) {
+		switch (typeof x) {
+			case "bigint":
+				return $int(type, $wrap(type, x));
+			case "number": {
+				// Saturates, with NaN becoming 0
+				const [signed, bits] = $INT_TYPES[type];
+				const min = signed ? -(1n << BigInt(bits - 1)) : 0n;
+				const max = signed ? -min - 1n : (1n << BigInt(bits)) - 1n;
+				if (Number.isNaN(x)) return $int(type, 0n);
+				if (x <= min) return $int(type, min);
+				if (x >= max) return $int(type, max);
+				return $int(type, BigInt(Math.trunc(x)));
+			}
+			case "boolean":
+				return $int(type, x ? 1n : 0n);
+			default:
+				return $int(type, $wrap(type, BigInt(x.c.codePointAt(0))));
+		}
+	}
+	if (type === "f32" || type === "f64") return $float(type, Number(x));
+	if (type === "char") return new $Char(String.fromCodePoint(Number(x)));
+	return value;
+}
+
+/** Whether two primitive values are equal */
+function $same(a, b) {
+	return a instanceof $Char ? a.c === b.c : $value(a) === $value(b);
+}
+
+const $builtins = {
+	PANIC: (message) => $panic(message),
+	NOT_BOOL: (a) => !a,
+	EQ_STR: (a, b) => a === b,
+	EQ_CHAR: (a, b) => a.c === b.c,
+	EQ_BOOL: (a, b) => a === b,
+	LT_STR: (a, b) => a < b,
+	LT_CHAR: (a, b) => a.c.codePointAt(0) < b.c.codePointAt(0),
+	LE_STR: (a, b) => a <= b,
+	LE_CHAR: (a, b) => a.c.codePointAt(0) <= b.c.codePointAt(0),
+	GT_STR: (a, b) => a > b,
+	GT_CHAR: (a, b) => a.c.codePointAt(0) > b.c.codePointAt(0),
+	GE_STR: (a, b) => a >= b,
+	GE_CHAR: (a, b) => a.c.codePointAt(0) >= b.c.codePointAt(0),
+	INDEX_ARRAY: (array, index) => array[$index(array.length, index)],
+	REPLACE_ARRAY: (array, index, value) => {
+		const copy = array.slice();
+		copy[$index(array.length, index)] = value;
+		return copy;
+	},
+	HASH_STR: (s) => {
+		// 64 bits FNV-1a of the UTF-8 bytes, like the interpreter
+		let hash = 0xcbf29ce484222325n;
+		for (const byte of new TextEncoder().encode(s)) {
+			hash = BigInt.asUintN(64, (hash ^ BigInt(byte)) * 0x100000001b3n);
+		}
+		return $int("u64", hash);
+	},
+	REF_NEW: (value) => new $Ref(value),
+	REF_GET: (ref) => ref.value,
+	REF_SET: (ref, value) => {
+		ref.value = value;
+	},
+	VEC_NEW: () => new $Vec([]),
+	VEC_LEN: (vec) => $int("usize", BigInt(vec.items.length)),
+	VEC_INDEX: (vec, index) => vec.items[$index(vec.items.length, index)],
+	VEC_SET: (vec, index, value) => {
+		vec.items[$index(vec.items.length, index)] = value;
+	},
+	VEC_PUSH: (vec, value) => {
+		vec.items.push(value);
+	},
+	VEC_INSERT: (vec, index, value) => {
+		const i = $value(index);
+		if (i > BigInt(vec.items.length)) $panic(`insertion index (is ${i}) should be <= len (is ${vec.items.length})`);
+		vec.items.splice(Number(i), 0, value);
+	},
+	VEC_REMOVE: (vec, index) => {
+		const i = $value(index);
+		if (i >= BigInt(vec.items.length)) $panic(`removal index (is ${i}) should be < len (is ${vec.items.length})`);
+		return vec.items.splice(Number(i), 1)[0];
+	},
+	VEC_CLEAR: (vec) => {
+		vec.items = [];
+	},
+	// Lengths and positions in `String`s count code points, like `char`s do
+	STRING_NEW: () => new $String(""),
+	STRING_FROM: (s) => new $String(s),
+	STRING_PUSH: (string, c) => {
+		string.s += c.c;
+	},
+	STRING_PUSH_STR: (string, s) => {
+		string.s += s;
+	},
+	STRING_LEN: (string) => $int("usize", BigInt(Array.from(string.s).length)),
+	STRING_AS_STR: (string) => string.s,
+	STRING_SLICE: (string, start, end) => {
+		const chars = Array.from(string.s);
+		const [a, b] = [$value(start), $value(end)];
+		if (b > BigInt(chars.length)) $panic(`char index ${b} is out of bounds of \`${string.s}\``);
+		if (a > b) $panic(`slice index starts at ${a} but ends at ${b}`);
+		return chars.slice(Number(a), Number(b)).join("");
+	},
+	LEN_ARRAY: (array) => $int("usize", BigInt(array.length)),
+	CONCAT_STR: (a, b) => a + b,
+	CHARS_STR: (s) => $int("usize", BigInt(Array.from(s).length)),
+	PREFIX_STR: (s, count) => Array.from(s).slice(0, Number($value(count))).join(""),
+	DISPLAY_VALUE: (value) => $display(value),
+	DEBUG_VALUE: (value) => {
+		if (typeof value === "string") return `"${$escape(value, '"')}"`;
+		if (value instanceof $Char) return `'${$escape(value.c, "'")}'`;
+		if (typeof value === "number") return $debugFloat(value, "f64");
+		if (value instanceof $Num && !(value.type in $INT_TYPES)) return $debugFloat(value.value, value.type);
+		return $display(value);
+	},
+	FIXED_FLOAT: (value, precision) => $fixed($value(value), Number($value(precision))),
+	FREXP_F64: (x) => {
+		const [mantissa, exp] = $frexp(x);
+		return new $Tuple([mantissa, new $Num(BigInt(exp), "i32")]);
+	},
+	LDEXP_F64: (x, exp) => $ldexp(x, Number($value(exp))),
+	// The host functions that can fail give back whether they worked, and their output or their error
+	IO_PRINT: (s) => {
+		process.stdout.write(s);
+	},
+	IO_EPRINTLN: (s) => {
+		process.stderr.write(`${s}\n`);
+	},
+	IO_READ_LINE: () =>
+		$host(() => {
+			// Read a byte at a time, so that the input after the line is left for the next read
+			const bytes = [];
+			const byte = Buffer.alloc(1);
+			while (bytes[bytes.length - 1] !== 0x0a) {
+				let read;
+				try {
+					read = $fs.readSync(0, byte, 0, 1, null);
+				} catch (e) {
+					if (e.code === "EAGAIN") continue;
+					if (e.code === "EOF") break;
+					throw e;
+				}
+				if (read === 0) break;
+				bytes.push(byte[0]);
+			}
+			return Buffer.from(bytes).toString("utf8");
+		}),
+	FS_READ: (path) => $host(() => $fs.readFileSync(path, "utf8")),
+	FS_WRITE: (path, contents) =>
+		$host(() => {
+			$fs.writeFileSync(path, contents);
+			return "";
+		}),
+};
+
+const $fs = require("fs");
+
+/** Run a host function, with the same messages as the interpreter for its errors */
+function $host(f) {
+	try {
+		return new $Tuple([true, f()]);
+	} catch (e) {
+		if (e.code === "ENOENT") return new $Tuple([false, "file not found"]);
+		if (e.code === "EACCES" || e.code === "EPERM") return new $Tuple([false, "permission denied"]);
+		if (typeof e.code !== "string") throw e;
+		return new $Tuple([false, e.message]);
+	}
+}
+
+/** Escape a text like the `Debug` of Rust, with a backslash before the quote it's in */
+function $escape(s, quote) {
+	const escapes = { "\0": "\\0", "\t": "\\t", "\r": "\\r", "\n": "\\n", "\\": "\\\\" };
+	let escaped = "";
+	for (const c of s) {
+		if (c in escapes) escaped += escapes[c];
+		else if (c === quote) escaped += `\\${c}`;
+		else if (/[\p{C}\p{Zl}\p{Zp}\p{Grapheme_Extend}]/u.test(c)) escaped += `\\u{${c.codePointAt(0).toString(16)}}`;
+		else escaped += c;
+	}
+	return escaped;
+}
+
+/** A float like the `Debug` of Rust, which has a point or an exponent */
+function $debugFloat(x, type) {
+	const abs = Math.abs(x);
+	if (Number.isFinite(x) && abs !== 0 && (abs < 1e-4 || abs >= 1e16)) {
+		return $shortest(x, type).replace("e+", "e");
+	}
+	const text = $display(x, type);
+	return /^-?\d+$/.test(text) ? `${text}.0` : text;
+}
+
+/** A float with the given number of digits after the point, rounding its exact value half to even like Rust */
+function $fixed(x, precision) {
+	if (!Number.isFinite(x)) return $display(x);
+	// The float is exactly mantissa * 2^exponent
+	const view = new DataView(new ArrayBuffer(8));
+	view.setFloat64(0, x);
+	const bits = view.getBigUint64(0);
+	const biased = Number((bits >> 52n) & 0x7ffn);
+	let mantissa = bits & 0xfffffffffffffn;
+	let exponent = -1074;
+	if (biased !== 0) {
+		mantissa |= 1n << 52n;
+		exponent = biased - 1075;
+	}
+	const scaled = mantissa * 10n ** BigInt(precision);
+	let n;
+	if (exponent >= 0) {
+		n = scaled << BigInt(exponent);
+	} else {
+		const divisor = 1n << BigInt(-exponent);
+		const remainder = scaled % divisor;
+		n = scaled / divisor;
+		if (2n * remainder > divisor || (2n * remainder === divisor && n % 2n === 1n)) n += 1n;
+	}
+	const digits = n.toString().padStart(precision + 1, "0");
+	const text = precision === 0 ? digits : `${digits.slice(0, -precision)}.${digits.slice(-precision)}`;
+	return bits >> 63n === 1n ? `-${text}` : text;
+}
+
+/** Write a number without an exponent, like Rust does, `1e21` is `1000000000000000000000` */
+function $decimal(text) {
+	const match = /^(-?)(\d)(?:\.(\d+))?e([+-]\d+)$/.exec(text);
+	if (!match) return text;
+	const [, sign, first, rest = "", exponent] = match;
+	const digits = first + rest;
+	const point = 1 + Number(exponent);
+	if (point <= 0) return `${sign}0.${"0".repeat(-point)}${digits}`;
+	if (point >= digits.length) return sign + digits + "0".repeat(point - digits.length);
+	return `${sign}${digits.slice(0, point)}.${digits.slice(point)}`;
+}
+
+/** The index as a number, panicking if it's out of bounds */
+function $index(len, index) {
+	const i = $value(index);
+	if (i >= BigInt(len)) $panic(`index out of bounds: the len is ${len} but the index is ${i}`);
+	return Number(i);
+}
+
+// The builtins of the numbers are the same for every type, like `ADD_U8`
+for (const type of [...Object.keys($INT_TYPES), "f32", "f64"]) {
+	const name = type.toUpperCase();
+	const int = type in $INT_TYPES;
+	const number = int ? (x) => $int(type, x) : (x) => $float(type, x);
+	const arithmetic = {
+		ADD: [(a, b) => a + b, "add"],
+		SUB: [(a, b) => a - b, "subtract"],
+		MUL: [(a, b) => a * b, "multiply"],
+		DIV: [(a, b) => a / b, "divide"],
+	};
+	for (const [op, [f, operation]] of Object.entries(arithmetic)) {
+		$builtins[`${op}_${name}`] = int
+			? (a, b) => {
+					if (op === "DIV" && $value(b) === 0n) $panic("attempt to divide by zero");
+					return $checked(type, operation, f($value(a), $value(b)));
+			  }
+			: (a, b) => number(f($value(a), $value(b)));
+	}
+	$builtins[`POW_${name}`] = int
+		? (a, b) => $pow(type, $value(a), $value(b))
+		: (a, b) => number($value(a) ** $value(b));
+	$builtins[`NEG_${name}`] = int ? (a) => $checked(type, "negate", -$value(a)) : (a) => number(-$value(a));
+	$builtins[`ABS_${name}`] = int
+		? (a) => ($value(a) < 0n ? $checked(type, "negate", -$value(a)) : a)
+		: (a) => number(Math.abs($value(a)));
+	if (int) {
+		for (const op of ["ADD", "SUB", "MUL", "DIV", "POW"]) {
+			$builtins[`CHECKED_${op}_${name}`] = (a, b) => $checkedOperation(type, op, $value(a), $value(b));
+		}
+	} else {
+		// These are exact, or correctly rounded like `sqrt`, so every backend gives the same result
+		const rounding = { SQRT: Math.sqrt, FLOOR: Math.floor, CEIL: Math.ceil, ROUND: $round, TRUNC: Math.trunc };
+		for (const [op, f] of Object.entries(rounding)) {
+			$builtins[`${op}_${name}`] = (a) => number(f($value(a)));
+		}
+	}
+	if (int) $builtins[`NOT_${name}`] = (a) => number($wrap(type, ~$value(a)));
+	$builtins[`EQ_${name}`] = (a, b) => $value(a) === $value(b);
+	$builtins[`LT_${name}`] = (a, b) => $value(a) < $value(b);
+	$builtins[`LE_${name}`] = (a, b) => $value(a) <= $value(b);
+	$builtins[`GT_${name}`] = (a, b) => $value(a) > $value(b);
+	$builtins[`GE_${name}`] = (a, b) => $value(a) >= $value(b);
+}
+
+/**
+ * The shortest digits that give back the same float, in exponential notation.
+ * Unlike `String`, which rounds half way cases to even digits, they are rounded up like Rust does
+ */
+function $shortest(x, type) {
+	const same = type === "f32" ? (y) => Math.fround(y) === x : (y) => y === x;
+	for (let precision = 0; precision < 16; precision++) {
+		const text = x.toExponential(precision);
+		if (same(Number(text))) return text;
+	}
+	return x.toExponential(16);
+}
+
+function $display(value, type = "f64") {
+	switch (typeof value) {
+		case "undefined":
+			return "()";
+		case "number":
+			if (value === Infinity) return "inf";
+			if (value === -Infinity) return "-inf";
+			if (Object.is(value, -0)) return "-0";
+			return $decimal($shortest(value, type));
+		case "function":
+			return "<fn>";
+		case "object":
+			if (Array.isArray(value)) return `[${value.map((item) => $display(item)).join(", ")}]`;
+	}
+	return String(value);
+}
+
+function $main(main) {
+	try {
+		const result = main();
+		if (result !== undefined) console.log($display(result));
+	} catch (e) {
+		if (!(e instanceof $Panic)) throw e;
+		// Like with `#[track_caller]`, a panic inside the libraries is reported where they were called from
+		const frame = e.backtrace.find((frame) => !frame.inLib);
+		console.error(`thread 'main' panicked at '${e.message}'${frame ? `, ${frame.site}` : ""}`);
+		const backtrace = process.env.LAMA_BACKTRACE;
+		if (backtrace !== undefined && backtrace !== "0") {
+			console.error("stack backtrace:");
+			e.backtrace.forEach((frame, i) => {
+				console.error(`${String(i).padStart(4)}: ${frame.name}`);
+				console.error(`             at ${frame.site}`);
+			});
+		} else {
+			console.error("note: run with `LAMA_BACKTRACE=1` environment variable to display a backtrace");
+		}
+		process.exit($PANIC_EXIT_CODE);
+	}
+}
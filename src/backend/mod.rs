@@ -1,12 +1,17 @@
-use crate::{ast::Expr, error::Return, modules::ModuleTree, options::CodegenOptions, span::Span};
+use std::fmt::Display;
+
+use crate::{ast::Expr, error::Return, fs::File, mir::Mir, modules::ModuleTree, options::CodegenOptions, span::Span};
 
 pub mod interpreter;
 pub mod js;
 pub mod print_ast;
 
 pub trait Backend {
+	/// Evaluate an expression typed in the REPL, which isn't started yet
+	#[allow(dead_code)]
 	fn eval_expr(&self, expr: Span<Expr>, options: CodegenOptions) -> Return<String>;
-	fn codegen(&self, module: ModuleTree, options: CodegenOptions) -> Return<String>;
+	/// Generate the program from the MIR of the checked crate, the module tree is there for the backends that print it
+	fn codegen(&self, module: &ModuleTree, mir: &Mir, options: CodegenOptions) -> Return<String>;
 }
 
 impl<T: Backend> Backend for Box<T> {
@@ -14,7 +19,43 @@ impl<T: Backend> Backend for Box<T> {
         self.as_ref().eval_expr(expr, options)
     }
 
-    fn codegen(&self, module: ModuleTree, options: CodegenOptions) -> Return<String> {
-        self.as_ref().codegen(module, options)
+    fn codegen(&self, module: &ModuleTree, mir: &Mir, options: CodegenOptions) -> Return<String> {
+        self.as_ref().codegen(module, mir, options)
     }
 }
+
+/// The status a program exits with when it panics. It isn't the 101 of Rust programs, which is what the compiler
+/// exits with when it has a bug itself
+pub const PANIC_EXIT_CODE: i32 = 102;
+
+/// The environment variable that shows the Lama call stack when a program panics, unless it's `0`
+pub const BACKTRACE_VAR: &str = "LAMA_BACKTRACE";
+
+/// How many calls the interpreter can be running at once, past that the program panics with [`STACK_OVERFLOW`]
+pub const MAX_CALL_DEPTH: usize = 100_000;
+
+/// The message of the panic of a program that makes too many nested calls, which the js backend also gives when
+/// JavaScript runs out of stack
+pub const STACK_OVERFLOW: &str = "stack overflow: too many nested calls";
+
+/// Where a call is made, as the file and the line and column it starts at
+#[derive(Clone, Copy)]
+pub struct Site<'a> {
+	file: &'a File,
+	start: (usize, usize),
+}
+
+impl<'a> Site<'a> {
+	pub fn of<T>(span: &'a Span<T>) -> Self {
+		Self {
+			file: span.file(),
+			start: span.start(),
+		}
+	}
+}
+
+impl Display for Site<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}:{}", self.file, self.start.0, self.start.1)
+	}
+}
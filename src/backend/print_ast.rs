@@ -1,6 +1,8 @@
 use crate::{
 	ast::Expr,
 	error::Return,
+	mir::Mir,
+	modules::ModuleTree,
 	options::CodegenOptions,
 	span::Span,
 };
@@ -13,7 +15,7 @@ impl super::Backend for Codegen {
 		Ok(String::default())
 	}
 
-	fn codegen(&self, module: crate::modules::ModuleTree, _options: CodegenOptions) -> Return<String> {
+	fn codegen(&self, module: &ModuleTree, _mir: &Mir, _options: CodegenOptions) -> Return<String> {
         println!("{:?}", module.root.ast);
 		Ok(String::default())
     }
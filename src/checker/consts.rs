@@ -0,0 +1,255 @@
+//! The evaluation of `const` and `static` items at compile time, with the operations of the interpreter
+
+use std::convert::TryFrom;
+
+use crate::{
+	ast::{Block, ConstKind, Expr, Literal, Statement},
+	backend::interpreter::{fold_builtin, fold_cast},
+	error::{ErrorCode, Return},
+	hir::{literal_value, Const},
+	span::{BoxedSpan, Span},
+};
+
+use super::{
+	db::{ConstId, Item, ModulePath, TypeDB, TypeId, TypeScope},
+	expr::FnChecker,
+	types::Type,
+	CheckedFn,
+};
+
+/// The value of a constant
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConstValue {
+	Const(Const),
+	Tuple(Vec<ConstValue>),
+	Array(Vec<ConstValue>),
+	/// A value of a struct or of an enum, with its fields in the order they are declared
+	Adt(TypeId, usize, Vec<ConstValue>),
+}
+
+/// Report an error, which stops the evaluation
+fn fail<S, T>(span: &Span<S>, code: ErrorCode, message: String) -> Return<T> {
+	span.as_error(code, message).display()?;
+	unreachable!("errors always stop the checker")
+}
+
+/// Check the value of the constant, then evaluate it
+pub fn eval(db: &TypeDB, id: ConstId) -> Return<ConstValue> {
+	let checked = FnChecker::check_const(db, id)?;
+	let entry = &db.consts[id];
+	let evaluator = Evaluator {
+		db,
+		checked: &checked,
+		module: &entry.module,
+		kind: entry.def.kind,
+	};
+	evaluator.sub(&entry.def.value)
+}
+
+struct Evaluator<'a, 'b> {
+	db: &'b TypeDB<'a>,
+	/// The types of the literals of the value
+	checked: &'b CheckedFn,
+	module: &'b ModulePath,
+	/// Constants can't use statics, statics can use both
+	kind: ConstKind,
+}
+
+impl<'a, 'b> Evaluator<'a, 'b> {
+	fn sub(&self, expr: &BoxedSpan<Expr>) -> Return<ConstValue> {
+		self.expr(expr, expr)
+	}
+
+	fn expr<S>(&self, expr: &Expr, span: &Span<S>) -> Return<ConstValue> {
+		Ok(match expr {
+			Expr::Literal(literal) => ConstValue::Const(literal_value(self.checked, literal, false)),
+			Expr::Neg(value) => match &***value {
+				Expr::Literal(literal @ Literal::Int(..)) => ConstValue::Const(literal_value(self.checked, literal, true)),
+				_ => self.operator(span, "NEG", &[value])?,
+			},
+			Expr::Not(value) => self.operator(span, "NOT", &[value])?,
+			Expr::Add(a, _, b) => self.operator(span, "ADD", &[a, b])?,
+			Expr::Sub(a, _, b) => self.operator(span, "SUB", &[a, b])?,
+			Expr::Mul(a, _, b) => self.operator(span, "MUL", &[a, b])?,
+			Expr::Div(a, _, b) => self.operator(span, "DIV", &[a, b])?,
+			Expr::Pow(a, _, b) => self.operator(span, "POW", &[a, b])?,
+			Expr::Eq(a, _, b) => self.operator(span, "EQ", &[a, b])?,
+			Expr::Ne(a, _, b) => match self.operator(span, "EQ", &[a, b])? {
+				ConstValue::Const(Const::Bool(equal)) => ConstValue::Const(Const::Bool(!equal)),
+				value => unreachable!("`==` gives back a bool, not {:?}", value),
+			},
+			Expr::Lt(a, _, b) => self.operator(span, "LT", &[a, b])?,
+			Expr::Le(a, _, b) => self.operator(span, "LE", &[a, b])?,
+			Expr::Gt(a, _, b) => self.operator(span, "GT", &[a, b])?,
+			Expr::Ge(a, _, b) => self.operator(span, "GE", &[a, b])?,
+			Expr::And(a, _, b) => match self.bool(a)? {
+				true => ConstValue::Const(Const::Bool(self.bool(b)?)),
+				false => ConstValue::Const(Const::Bool(false)),
+			},
+			Expr::Or(a, _, b) => match self.bool(a)? {
+				true => ConstValue::Const(Const::Bool(true)),
+				false => ConstValue::Const(Const::Bool(self.bool(b)?)),
+			},
+			Expr::Cast(value, type_) => {
+				let scope = TypeScope {
+					module: self.module.clone(),
+					..Default::default()
+				};
+				match (self.sub(value)?, self.db.lower_type(&scope, type_)?) {
+					(ConstValue::Const(c), Type::Primitive(target)) => ConstValue::Const(fold_cast(&c, target)),
+					// Other casts are between the same types
+					(value, _) => value,
+				}
+			}
+			Expr::Tuple(values) => ConstValue::Tuple(self.exprs(values)?),
+			Expr::Array(values) => ConstValue::Array(self.exprs(values)?),
+			Expr::Index(array, index) => match (self.sub(array)?, self.sub(index)?) {
+				(ConstValue::Array(mut values), ConstValue::Const(Const::Int(index, _))) => {
+					match usize::try_from(index) {
+						Ok(i) if i < values.len() => values.swap_remove(i),
+						_ => {
+							let message = format!(
+								"index out of bounds: the len is {} but the index is {}",
+								values.len(),
+								index
+							);
+							return self.failed(span, message);
+						}
+					}
+				}
+				_ => return self.unsupported(span, "indexing something else than an array"),
+			},
+			Expr::Field(value, name) => match self.sub(value)? {
+				ConstValue::Tuple(mut values) => values.swap_remove(name.parse().unwrap()),
+				ConstValue::Adt(id, variant, mut values) => {
+					values.swap_remove(self.db.types[id].field_index(variant, name).unwrap())
+				}
+				_ => unreachable!("the checker only allows fields of tuples and structs"),
+			},
+			Expr::Struct(path, fields) => {
+				let item = self.db.resolve_path(self.module, path)?;
+				let (id, variant) = self.db.variant_of(&item).unwrap();
+				let mut values = vec![None; fields.len()];
+				for (name, value) in fields {
+					values[self.db.types[id].field_index(variant, name).unwrap()] = Some(self.sub(value)?);
+				}
+				ConstValue::Adt(id, variant, values.into_iter().map(Option::unwrap).collect())
+			}
+			Expr::FnCall(callee, args) => {
+				let variant = match &***callee {
+					Expr::Ident(path) => {
+						let item = self.db.resolve_path(self.module, path)?;
+						self.db.variant_of(&item)
+					}
+					_ => None,
+				};
+				match variant {
+					Some((id, variant)) => ConstValue::Adt(id, variant, self.exprs(args)?),
+					None => return self.unsupported(span, "calls"),
+				}
+			}
+			Expr::Ident(path) => self.ident(span, path)?,
+			Expr::Block(block) => self.block(span, block)?,
+			Expr::If(condition, block, elseif_clauses, else_clause) => {
+				if self.bool(condition)? {
+					return self.block(span, block);
+				}
+				for (condition, block) in elseif_clauses {
+					if self.bool(condition)? {
+						return self.block(span, block);
+					}
+				}
+				match else_clause {
+					Some(block) => self.block(span, block)?,
+					None => ConstValue::Const(Const::Empty),
+				}
+			}
+			Expr::Assign(..) => return self.unsupported(span, "assignments"),
+			Expr::Closure(..) => return self.unsupported(span, "closures"),
+			Expr::MethodCall(..) => return self.unsupported(span, "method calls"),
+			Expr::Try(..) => return self.unsupported(span, "`?`"),
+			Expr::Match(..) => return self.unsupported(span, "`match`"),
+			Expr::Let(..) => return self.unsupported(span, "`let`"),
+			Expr::Return(..) => return self.unsupported(span, "`return`"),
+			Expr::While(..) | Expr::Loop(..) | Expr::For(..) | Expr::Break(..) | Expr::Continue(..) => {
+				return self.unsupported(span, "loops")
+			}
+		})
+	}
+
+	fn exprs(&self, exprs: &[BoxedSpan<Expr>]) -> Return<Vec<ConstValue>> {
+		exprs.iter().map(|expr| self.sub(expr)).collect()
+	}
+
+	fn bool(&self, expr: &BoxedSpan<Expr>) -> Return<bool> {
+		match self.sub(expr)? {
+			ConstValue::Const(Const::Bool(b)) => Ok(b),
+			value => unreachable!("the checker only allows bools in conditions, not {:?}", value),
+		}
+	}
+
+	/// A block with no statement but its value
+	fn block<S>(&self, span: &Span<S>, block: &Block) -> Return<ConstValue> {
+		match block.as_slice() {
+			[] => Ok(ConstValue::Const(Const::Empty)),
+			[statement] => match &***statement {
+				Statement::Returning(expr) => self.expr(expr, statement),
+				_ => self.unsupported(statement, "statements"),
+			},
+			_ => self.unsupported(span, "statements"),
+		}
+	}
+
+	/// The value of another constant, or of a unit struct or variant
+	fn ident<S>(&self, span: &Span<S>, path: &[Span<String>]) -> Return<ConstValue> {
+		let item = self.db.resolve_path(self.module, path)?;
+		if let Some((id, variant)) = self.db.variant_of(&item) {
+			return Ok(ConstValue::Adt(id, variant, Vec::new()));
+		}
+		match item {
+			Item::Const(id) if self.kind == ConstKind::Const && self.db.consts[id].def.kind == ConstKind::Static => {
+				let name = &self.db.consts[id].def.name;
+				fail(
+					span,
+					ErrorCode::ConstEvalError,
+					format!("constants cannot refer to statics, `{}` is one", name),
+				)
+			}
+			Item::Const(id) => self.db.const_value(id),
+			_ => self.unsupported(span, "functions"),
+		}
+	}
+
+	/// An operator on primitives, computed like the interpreter does
+	fn operator<S>(&self, span: &Span<S>, name: &str, operands: &[&BoxedSpan<Expr>]) -> Return<ConstValue> {
+		let mut args = Vec::new();
+		for operand in operands {
+			match self.sub(operand)? {
+				ConstValue::Const(c) => args.push(c),
+				_ => return self.unsupported(span, "operators on other types than primitives"),
+			}
+		}
+		match fold_builtin(name, &args, false) {
+			Some(Ok(value)) => Ok(ConstValue::Const(value)),
+			Some(Err(message)) => self.failed(span, message),
+			None => unreachable!("operators on primitives don't use the heap"),
+		}
+	}
+
+	/// An operation that panics, like it would at run time
+	fn failed<S, T>(&self, span: &Span<S>, message: String) -> Return<T> {
+		fail(
+			span,
+			ErrorCode::ConstEvalError,
+			format!("evaluation of constant value failed: {}", message),
+		)
+	}
+
+	fn unsupported<S, T>(&self, span: &Span<S>, what: &str) -> Return<T> {
+		fail(
+			span,
+			ErrorCode::ConstEvalError,
+			format!("{} can't be evaluated in constants", what),
+		)
+	}
+}
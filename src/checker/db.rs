@@ -1,41 +1,1151 @@
+use std::cell::RefCell;
+
 use hashbrown::HashMap;
 
-use crate::{ast::{Impl, TraitDef, TraitImpl}, span::Span};
+use crate::{
+	ast::{self, ConstDef, ConstKind, Fields, FnArg, FnDef, Generics, TraitDef, TraitImpl, TypeInTrait, WhereClause},
+	error::{ErrorCode, Return},
+	hir::Const,
+	modules::{Module, ModuleTree},
+	span::Span,
+};
+
+use super::{
+	consts::{self, ConstValue},
+	infer::InferCtx,
+	lang,
+	types::{Primitive, TraitRef, Type},
+};
 
 pub type ModulePath = Vec<String>;
+pub type FnId = usize;
+pub type TraitId = usize;
+pub type ImplId = usize;
+pub type TypeId = usize;
+pub type ConstId = usize;
+
+/// Name under which the root module of the crate being compiled is registered
+pub const LOCAL_CRATE: &str = "crate";
 
+/// How deep the impl search can go when checking the where clauses of impls
+const MAX_IMPL_DEPTH: usize = 16;
+
+/// Every item of the crate being compiled and its extern libs
 #[derive(Debug, Default)]
-pub struct TypeDB {
-	children_dbs: HashMap<String, TypeDBNode>
+pub struct TypeDB<'a> {
+	modules: HashMap<ModulePath, Namespace>,
+	pub fns: Vec<FnEntry<'a>>,
+	pub traits: Vec<TraitEntry<'a>>,
+	pub impls: Vec<ImplEntry<'a>>,
+	pub types: Vec<TypeEntry<'a>>,
+	pub consts: Vec<ConstEntry<'a>>,
+	/// The values of the constants evaluated so far, `None` while one is being evaluated
+	const_values: RefCell<HashMap<ConstId, Option<ConstValue>>>,
+}
+
+#[derive(Debug, Default)]
+pub struct Namespace {
+	pub items: HashMap<String, Item>,
+	pub allow_builtins: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Item {
+	Module(ModulePath),
+	Fn(FnId),
+	Trait(TraitId),
+	Type(TypeId),
+	/// A variant of an enum, with its index
+	Variant(TypeId, usize),
+	Const(ConstId),
+}
+
+/// What the checker resolved a method call to
+#[derive(Debug, Clone, Copy)]
+pub enum Method {
+	/// A method of an inherent impl, the call records which one
+	Inherent,
+	/// A method of a trait, dispatched on the type of the receiver
+	Trait(TraitId),
+}
+
+/// A function a body calls or takes as a value
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+	Fn(FnId),
+	/// A method of a trait, whose impl is only known once the types are
+	Method(TraitId, String),
+}
+
+/// A use of a function with the types given to its generics, in the order of [`FnSig::generics`].
+/// For trait methods those are the generics of the trait, `Self`, then the ones of the method
+#[derive(Debug, Clone)]
+pub struct Use {
+	pub span: Span<()>,
+	pub callee: Callee,
+	pub generics: Vec<Type>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FnParent {
+	Module,
+	Impl(ImplId),
+	Trait(TraitId),
 }
 
-impl TypeDB {
-	pub fn new_root() -> Self {
-		Default::default()
+#[derive(Debug)]
+pub struct FnEntry<'a> {
+	pub def: &'a FnDef,
+	pub module: ModulePath,
+	pub parent: FnParent,
+}
+
+#[derive(Debug)]
+pub struct TraitEntry<'a> {
+	pub def: &'a TraitDef,
+	pub module: ModulePath,
+	pub path: ModulePath,
+	/// Methods with a body in the trait definition
+	pub default_fns: HashMap<String, FnId>,
+}
+
+#[derive(Debug)]
+pub struct ConstEntry<'a> {
+	pub def: &'a ConstDef,
+	pub module: ModulePath,
+	pub path: ModulePath,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeKind {
+	Struct,
+	Enum,
+}
+
+/// A struct or an enum. A struct is handled as an enum with a single variant named like it
+#[derive(Debug)]
+pub struct TypeEntry<'a> {
+	pub kind: TypeKind,
+	pub generics: &'a Option<Generics>,
+	pub where_clause: &'a Option<WhereClause>,
+	pub variants: Vec<(&'a Span<String>, &'a Fields)>,
+	pub module: ModulePath,
+	pub path: ModulePath,
+}
+
+impl<'a> TypeEntry<'a> {
+	pub fn generic_names(&self) -> Vec<String> {
+		self.generics
+			.iter()
+			.flatten()
+			.map(|g| g.to_string())
+			.collect()
+	}
+
+	pub fn variant_index(&self, name: &str) -> Option<usize> {
+		self.variants.iter().position(|(n, _)| ***n == name)
+	}
+
+	/// The position of a field of the variant, named like `x` or like `0` for tuple variants
+	pub fn field_index(&self, variant: usize, name: &str) -> Option<usize> {
+		match self.variants[variant].1 {
+			Fields::Unit => None,
+			Fields::Tuple(types) => name.parse().ok().filter(|i| *i < types.len()),
+			Fields::Named(fields) => fields.iter().position(|(n, _)| **n == name),
+		}
+	}
+
+	/// What the variant is, for error messages
+	pub fn variant_kind(&self, variant: usize) -> &'static str {
+		match (self.kind, self.variants[variant].1) {
+			(TypeKind::Struct, Fields::Unit) => "unit struct",
+			(TypeKind::Struct, Fields::Tuple(_)) => "tuple struct",
+			(TypeKind::Struct, Fields::Named(_)) => "struct",
+			(TypeKind::Enum, Fields::Unit) => "unit variant",
+			(TypeKind::Enum, Fields::Tuple(_)) => "tuple variant",
+			(TypeKind::Enum, Fields::Named(_)) => "struct variant",
+		}
+	}
+
+	/// How the variant is written in the source, like `Shape::Circle`, or just `Point` for structs
+	pub fn variant_name(&self, variant: usize) -> String {
+		match self.kind {
+			TypeKind::Struct => self.variants[variant].0.to_string(),
+			TypeKind::Enum => format!("{}::{}", self.path.last().unwrap(), self.variants[variant].0),
+		}
 	}
 }
 
 #[derive(Debug)]
-pub enum TypeDBNode {
-	DB(TypeDB),
-	Reference(ModulePath),
-	Type(Type),
-	Trait(TraitDef)
+pub struct ImplEntry<'a> {
+	pub module: ModulePath,
+	pub generics: &'a Option<Generics>,
+	pub where_clause: &'a Option<WhereClause>,
+	pub type_: &'a Span<ast::Type>,
+	pub trait_: Option<(TraitId, &'a Span<ast::Trait>)>,
+	pub types: &'a [(Span<TypeInTrait>, Span<ast::Type>)],
+	pub fns: HashMap<String, FnId>,
+	/// The implementing type, with the impl generics as [`Type::Generic`]
+	pub self_type: Type,
+	/// The implemented trait, with the impl generics as [`Type::Generic`]
+	pub trait_ref: Option<TraitRef>,
 }
 
+impl<'a> ImplEntry<'a> {
+	pub fn generic_names(&self) -> Vec<String> {
+		self.generics
+			.iter()
+			.flatten()
+			.map(|g| g.to_string())
+			.collect()
+	}
+}
+
+/// Everything needed to give meaning to the type names in some part of the source
+#[derive(Debug, Clone, Default)]
+pub struct TypeScope {
+	pub module: ModulePath,
+	pub generics: Vec<String>,
+	pub self_type: Option<Type>,
+	pub impl_: Option<ImplId>,
+	pub bounds: Vec<(Type, TraitRef)>,
+}
+
+/// A lowered function signature.
+/// `generics` contains every generic that has to be instantiated to call the function
+#[derive(Debug, Clone)]
+pub struct FnSig {
+	pub generics: Vec<String>,
+	pub args: Vec<Type>,
+	pub ret: Type,
+	pub bounds: Vec<(Type, TraitRef)>,
+}
+
+impl FnSig {
+	/// Replace every generic with a fresh inference variable
+	pub fn instantiate(&self, infer: &mut InferCtx) -> (FnSig, HashMap<String, Type>) {
+		let map: HashMap<String, Type> = self
+			.generics
+			.iter()
+			.map(|g| (g.clone(), infer.new_var()))
+			.collect();
+		(
+			FnSig {
+				generics: Vec::new(),
+				args: self.args.iter().map(|t| t.subst(&map)).collect(),
+				ret: self.ret.subst(&map),
+				bounds: self
+					.bounds
+					.iter()
+					.map(|(t, tr)| (t.subst(&map), tr.subst(&map)))
+					.collect(),
+			},
+			map,
+		)
+	}
+}
+
+/// Where the implementation of a trait for a type comes from
 #[derive(Debug)]
-pub struct Type {
-	generics: Vec<Span<String>>,
-	impls: Vec<Impl>,
-	trait_impls: Vec<(ModulePath, TraitImpl)>
+pub enum ImplSource {
+	/// An impl item, with the types its generics took
+	Impl(ImplId, HashMap<String, Type>),
+	/// A bound in the where clauses of the current item
+	Bound,
+	/// There are multiple candidates, more type information is needed
+	Ambiguous,
+}
+
+enum PendingImpl<'a> {
+	Trait(ModulePath, &'a TraitImpl),
+	Inherent(ModulePath, &'a ast::Impl),
 }
 
-impl Type {
-	pub fn add_impl(&mut self, i: Impl) {
-		self.impls.push(i)
+impl<'a> TypeDB<'a> {
+	pub fn new(tree: &'a ModuleTree) -> Return<Self> {
+		let mut db = Self::default();
+		let mut pending = Vec::new();
+		for (name, lib) in &tree.externlibs {
+			db.add_module(vec![name.clone()], &lib.root, &mut pending);
+		}
+		db.add_module(vec![LOCAL_CRATE.to_string()], &tree.root, &mut pending);
+		// Impls are added once every trait is known
+		for impl_ in pending {
+			db.add_impl(impl_)?;
+		}
+		for id in 0..db.impls.len() {
+			let scope = TypeScope {
+				module: db.impls[id].module.clone(),
+				generics: db.impls[id].generic_names(),
+				..Default::default()
+			};
+			db.impls[id].self_type = db.lower_type(&scope, db.impls[id].type_)?;
+			if let Some((_, trait_)) = db.impls[id].trait_ {
+				db.impls[id].trait_ref = Some(db.lower_trait(&scope, trait_)?);
+			}
+		}
+		Ok(db)
+	}
+
+	fn add_module(&mut self, path: ModulePath, module: &'a Module, pending: &mut Vec<PendingImpl<'a>>) {
+		let mut namespace = Namespace {
+			allow_builtins: module.allow_builtins,
+			..Default::default()
+		};
+		for def in &module.ast.fns {
+			namespace
+				.items
+				.insert(def.name.to_string(), Item::Fn(self.fns.len()));
+			self.fns.push(FnEntry {
+				def,
+				module: path.clone(),
+				parent: FnParent::Module,
+			});
+		}
+		for def in &module.ast.trait_defs {
+			let id = self.traits.len();
+			let mut default_fns = HashMap::new();
+			for f in &def.fn_defs {
+				default_fns.insert(f.name.to_string(), self.fns.len());
+				self.fns.push(FnEntry {
+					def: f,
+					module: path.clone(),
+					parent: FnParent::Trait(id),
+				});
+			}
+			let mut trait_path = path.clone();
+			trait_path.push(def.name.to_string());
+			namespace.items.insert(def.name.to_string(), Item::Trait(id));
+			self.traits.push(TraitEntry {
+				def,
+				module: path.clone(),
+				path: trait_path,
+				default_fns,
+			});
+		}
+		for def in &module.ast.structs {
+			namespace.items.insert(def.name.to_string(), Item::Type(self.types.len()));
+			self.types.push(TypeEntry {
+				kind: TypeKind::Struct,
+				generics: &def.generics,
+				where_clause: &def.where_clause,
+				variants: vec![(&def.name, &def.fields)],
+				module: path.clone(),
+				path: [&path[..], &[def.name.to_string()]].concat(),
+			});
+		}
+		for def in &module.ast.enums {
+			namespace.items.insert(def.name.to_string(), Item::Type(self.types.len()));
+			self.types.push(TypeEntry {
+				kind: TypeKind::Enum,
+				generics: &def.generics,
+				where_clause: &def.where_clause,
+				variants: def.variants.iter().map(|v| (&v.name, &v.fields)).collect(),
+				module: path.clone(),
+				path: [&path[..], &[def.name.to_string()]].concat(),
+			});
+		}
+		for def in &module.ast.consts {
+			namespace.items.insert(def.name.to_string(), Item::Const(self.consts.len()));
+			self.consts.push(ConstEntry {
+				def,
+				module: path.clone(),
+				path: [&path[..], &[def.name.to_string()]].concat(),
+			});
+		}
+		for impl_ in &module.ast.trait_impls {
+			pending.push(PendingImpl::Trait(path.clone(), impl_));
+		}
+		for impl_ in &module.ast.impls {
+			pending.push(PendingImpl::Inherent(path.clone(), impl_));
+		}
+		for (name, (_, child)) in &module.children {
+			let mut child_path = path.clone();
+			child_path.push(name.clone());
+			namespace
+				.items
+				.insert(name.clone(), Item::Module(child_path.clone()));
+			self.add_module(child_path, child, pending);
+		}
+		self.modules.insert(path, namespace);
+	}
+
+	fn add_impl(&mut self, impl_: PendingImpl<'a>) -> Return<()> {
+		let id = self.impls.len();
+		let (module, generics, where_clause, type_, trait_, types, fn_defs) = match impl_ {
+			PendingImpl::Trait(module, i) => {
+				let trait_id = match self.resolve_path_str(&module, &i.trait_.name)? {
+					Item::Trait(t) => t,
+					_ => {
+						i.trait_
+							.as_error(
+								ErrorCode::UnknownNameError,
+								format!("`{}` is not a trait", i.trait_.name),
+							)
+							.display()?;
+						unreachable!()
+					}
+				};
+				if is_fn_trait(&self.traits[trait_id].path) {
+					i.trait_
+						.as_error(
+							ErrorCode::ImplError,
+							format!(
+								"manual implementations of `{}` are not allowed, only functions and closures implement it",
+								i.trait_.name
+							),
+						)
+						.display()?;
+					unreachable!()
+				}
+				(
+					module,
+					&i.generics,
+					&i.where_clause,
+					&i.type_,
+					Some((trait_id, &i.trait_)),
+					&i.types[..],
+					&i.fn_defs,
+				)
+			}
+			PendingImpl::Inherent(module, i) => (
+				module,
+				&i.generics,
+				&i.where_clause,
+				&i.type_,
+				None,
+				&i.types[..],
+				&i.fn_defs,
+			),
+		};
+		let mut fns = HashMap::new();
+		for def in fn_defs {
+			fns.insert(def.name.to_string(), self.fns.len());
+			self.fns.push(FnEntry {
+				def,
+				module: module.clone(),
+				parent: FnParent::Impl(id),
+			});
+		}
+		self.impls.push(ImplEntry {
+			module,
+			generics,
+			where_clause,
+			type_,
+			trait_,
+			types,
+			fns,
+			self_type: Type::Empty,
+			trait_ref: None,
+		});
+		Ok(())
+	}
+
+	pub fn namespace(&self, module: &[String]) -> &Namespace {
+		&self.modules[module]
+	}
+
+	/// Find the item at the given path, relative to the `from` module
+	pub fn lookup_path<S: AsRef<str>>(&self, from: &[String], path: &[S]) -> Option<Item> {
+		self.lookup_path_partial(from, path).ok()
+	}
+
+	/// Find the item at the given path. On failure, returns the index of the segment that wasn't found
+	fn lookup_path_partial<S: AsRef<str>>(&self, from: &[String], path: &[S]) -> Result<Item, usize> {
+		let (mut current, start) = match path[0].as_ref() {
+			"" => match path.get(1) {
+				Some(name) if self.modules.contains_key(&vec![name.as_ref().to_string()]) => {
+					(Item::Module(vec![name.as_ref().to_string()]), 2)
+				}
+				_ => return Err(1),
+			},
+			"self" => (Item::Module(from.to_vec()), 1),
+			"super" if from.len() > 1 => (Item::Module(from[..from.len() - 1].to_vec()), 1),
+			first => match self.modules[from].items.get(first) {
+				Some(item) => (item.clone(), 1),
+				None if self.modules.contains_key(&vec![first.to_string()]) => {
+					(Item::Module(vec![first.to_string()]), 1)
+				}
+				None => return Err(0),
+			},
+		};
+		for (i, segment) in path.iter().enumerate().skip(start) {
+			current = match &current {
+				Item::Module(m) => match self.modules[m].items.get(segment.as_ref()) {
+					Some(item) => item.clone(),
+					None => return Err(i),
+				},
+				Item::Type(t) => {
+					let entry = &self.types[*t];
+					match entry.variant_index(segment.as_ref()) {
+						Some(v) if entry.kind == TypeKind::Enum => Item::Variant(*t, v),
+						// Variants shadow the associated functions with the same name
+						_ => match self.associated_fn(*t, segment.as_ref()) {
+							Some(id) => Item::Fn(id),
+							None => return Err(i),
+						},
+					}
+				}
+				_ => return Err(i),
+			}
+		}
+		Ok(current)
+	}
+
+	/// Find the item at the given path, reporting an error if it doesn't exist
+	pub fn resolve_path(&self, from: &[String], path: &[Span<String>]) -> Return<Item> {
+		let segments: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+		self.lookup_path_partial(from, &segments).or_else(|i| {
+			let segment = &path[i.min(path.len() - 1)];
+			segment
+				.as_error(
+					ErrorCode::UnknownNameError,
+					format!(
+						"cannot find `{}` in this scope",
+						path.iter()
+							.map(|s| s.as_str())
+							.collect::<Vec<&str>>()
+							.join("::")
+					),
+				)
+				.display()?;
+			unreachable!()
+		})
+	}
+
+	/// Resolve a path that was parsed as a single string, like the names of types and traits
+	pub fn resolve_path_str(&self, from: &[String], path: &Span<String>) -> Return<Item> {
+		let segments: Vec<&str> = path.split("::").map(str::trim).collect();
+		match self.lookup_path_partial(from, &segments) {
+			Ok(item) => Ok(item),
+			Err(_) => {
+				path.as_error(
+					ErrorCode::UnknownNameError,
+					format!("cannot find `{}` in this scope", path),
+				)
+				.display()?;
+				unreachable!()
+			}
+		}
+	}
+
+	/// Get a trait the language depends on, like the ones for operators
+	pub fn lang_trait(&self, path: &[&str]) -> TraitId {
+		let mut absolute = vec![""];
+		absolute.extend_from_slice(path);
+		match self.lookup_path(&[LOCAL_CRATE.to_string()], &absolute) {
+			Some(Item::Trait(id)) => id,
+			_ => panic!("Missing lang trait `{}`", path.join("::")),
+		}
 	}
 
-	pub fn add_trait_impl(&mut self, path: ModulePath, i: TraitImpl) {
-		self.trait_impls.push((path, i))
+	pub fn main_fn(&self) -> Option<FnId> {
+		match self.modules[&vec![LOCAL_CRATE.to_string()]].items.get("main") {
+			Some(Item::Fn(id)) => Some(*id),
+			_ => None,
+		}
 	}
-}
\ No newline at end of file
+
+	/// A readable path to the function, for mangling and error messages
+	pub fn fn_path(&self, id: FnId) -> String {
+		let entry = &self.fns[id];
+		let mut path = entry.module.join("::");
+		match entry.parent {
+			FnParent::Module => (),
+			FnParent::Trait(t) => {
+				path = self.traits[t].path.join("::");
+			}
+			FnParent::Impl(i) => {
+				let impl_ = &self.impls[i];
+				path = match &impl_.trait_ref {
+					Some(t) => format!("<{} as {}>", impl_.self_type, t),
+					None => format!("{}", impl_.self_type),
+				};
+			}
+		}
+		format!("{}::{}", path, entry.def.name)
+	}
+
+	/// The method called `name` of the impl, falling back to the default in the trait
+	pub fn impl_method(&self, impl_id: ImplId, name: &str) -> Option<FnId> {
+		let impl_ = &self.impls[impl_id];
+		impl_.fns.get(name).copied().or_else(|| {
+			impl_
+				.trait_
+				.and_then(|(t, _)| self.traits[t].default_fns.get(name).copied())
+		})
+	}
+
+	pub fn impl_scope(&self, id: ImplId) -> Return<TypeScope> {
+		let impl_ = &self.impls[id];
+		let mut scope = TypeScope {
+			module: impl_.module.clone(),
+			generics: impl_.generic_names(),
+			self_type: Some(impl_.self_type.clone()),
+			impl_: Some(id),
+			bounds: Vec::new(),
+		};
+		self.add_bounds(&mut scope, impl_.where_clause)?;
+		Ok(scope)
+	}
+
+	pub fn type_scope(&self, id: TypeId) -> Return<TypeScope> {
+		let entry = &self.types[id];
+		let mut scope = TypeScope {
+			module: entry.module.clone(),
+			generics: entry.generic_names(),
+			..Default::default()
+		};
+		self.add_bounds(&mut scope, entry.where_clause)?;
+		Ok(scope)
+	}
+
+	/// The type of the fields of a variant, with the generics of the type as [`Type::Generic`]
+	pub fn variant_fields(&self, id: TypeId, variant: usize) -> Return<Vec<Type>> {
+		let scope = self.type_scope(id)?;
+		match self.types[id].variants[variant].1 {
+			Fields::Unit => Ok(Vec::new()),
+			Fields::Tuple(types) => types.iter().map(|t| self.lower_type(&scope, t)).collect(),
+			Fields::Named(fields) => fields.iter().map(|(_, t)| self.lower_type(&scope, t)).collect(),
+		}
+	}
+
+	/// A variant seen as a function that takes its fields and gives back the type
+	pub fn variant_signature(&self, id: TypeId, variant: usize) -> Return<FnSig> {
+		let scope = self.type_scope(id)?;
+		let entry = &self.types[id];
+		Ok(FnSig {
+			generics: scope.generics.clone(),
+			args: self.variant_fields(id, variant)?,
+			ret: Type::Adt(entry.path.clone(), scope.generics.iter().cloned().map(Type::Generic).collect()),
+			bounds: scope.bounds,
+		})
+	}
+
+	/// The variant an item is, structs being their only variant
+	pub fn variant_of(&self, item: &Item) -> Option<(TypeId, usize)> {
+		match item {
+			Item::Type(id) if self.types[*id].kind == TypeKind::Struct => Some((*id, 0)),
+			Item::Variant(id, variant) => Some((*id, *variant)),
+			_ => None,
+		}
+	}
+
+	/// The unit struct or variant a name in a pattern refers to, which makes it not a binding
+	pub fn unit_variant(&self, from: &[String], name: &str) -> Option<(TypeId, usize)> {
+		let (id, variant) = self.variant_of(&self.lookup_path(from, &[name])?)?;
+		match self.types[id].variants[variant].1 {
+			Fields::Unit => Some((id, variant)),
+			_ => None,
+		}
+	}
+
+	/// A function of an inherent impl of the type, like `Point::new`
+	fn associated_fn(&self, id: TypeId, name: &str) -> Option<FnId> {
+		let path = &self.types[id].path;
+		self.impls
+			.iter()
+			.filter(|i| i.trait_.is_none() && matches!(&i.self_type, Type::Adt(p, _) if p == path))
+			.find_map(|i| i.fns.get(name).copied())
+	}
+
+	/// The function called `name` of an inherent impl that `self_type` can be the type of
+	pub fn inherent_method(&self, infer: &InferCtx, self_type: &Type, name: &str) -> Option<FnId> {
+		self.impls
+			.iter()
+			.filter(|i| i.trait_.is_none())
+			.find_map(|impl_| {
+				let id = *impl_.fns.get(name)?;
+				let mut attempt = infer.clone();
+				let map: HashMap<String, Type> = impl_
+					.generic_names()
+					.into_iter()
+					.map(|g| (g, attempt.new_var()))
+					.collect();
+				attempt.unify(&impl_.self_type.subst(&map), self_type).then_some(id)
+			})
+	}
+
+	/// The traits declaring a method called `name` that takes `self`, which can be called on a receiver
+	pub fn traits_with_method(&self, name: &str) -> Vec<TraitId> {
+		(0..self.traits.len())
+			.filter(|id| {
+				let def = self.traits[*id].def;
+				def.fn_signatures
+					.iter()
+					.map(|f| (&f.name, &f.args))
+					.chain(def.fn_defs.iter().map(|f| (&f.name, &f.args)))
+					.any(|(n, args)| **n == name && takes_self(args))
+			})
+			.collect()
+	}
+
+	/// Whether the function takes `self`, which makes it a method
+	pub fn is_method(&self, id: FnId) -> bool {
+		takes_self(&self.fns[id].def.args)
+	}
+
+	pub fn type_by_path(&self, path: &[String]) -> Option<TypeId> {
+		self.types.iter().position(|t| t.path == path)
+	}
+
+	pub fn trait_scope(&self, id: TraitId) -> Return<TypeScope> {
+		let trait_ = &self.traits[id];
+		let mut generics: Vec<String> = trait_.def.generics.iter().flatten().map(|g| g.to_string()).collect();
+		let trait_ref = TraitRef {
+			path: trait_.path.clone(),
+			generics: generics.iter().cloned().map(Type::Generic).collect(),
+		};
+		generics.push("Self".to_string());
+		let self_type = Type::Generic("Self".to_string());
+		let mut scope = TypeScope {
+			module: trait_.module.clone(),
+			generics,
+			self_type: Some(self_type.clone()),
+			impl_: None,
+			bounds: vec![(self_type, trait_ref)],
+		};
+		self.add_bounds(&mut scope, &trait_.def.where_clause)?;
+		Ok(scope)
+	}
+
+	/// The scope inside of the function, including its generics
+	pub fn fn_scope(&self, id: FnId) -> Return<TypeScope> {
+		let entry = &self.fns[id];
+		let mut scope = match entry.parent {
+			FnParent::Module => TypeScope {
+				module: entry.module.clone(),
+				..Default::default()
+			},
+			FnParent::Impl(i) => self.impl_scope(i)?,
+			FnParent::Trait(t) => self.trait_scope(t)?,
+		};
+		scope
+			.generics
+			.extend(entry.def.generics.iter().flatten().map(|g| g.to_string()));
+		self.add_bounds(&mut scope, &entry.def.where_clause)?;
+		Ok(scope)
+	}
+
+	fn add_bounds(&self, scope: &mut TypeScope, where_clause: &Option<WhereClause>) -> Return<()> {
+		for (type_, traits) in where_clause.iter().flatten() {
+			let type_ = self.lower_type(scope, type_)?;
+			for trait_ in traits {
+				let trait_ = self.lower_trait(scope, trait_)?;
+				scope.bounds.push((type_.clone(), trait_));
+			}
+		}
+		Ok(())
+	}
+
+	pub fn fn_signature(&self, id: FnId) -> Return<(TypeScope, FnSig)> {
+		let scope = self.fn_scope(id)?;
+		let def = self.fns[id].def;
+		let sig = self.lower_signature(&scope, &def.args, &def.return_type)?;
+		Ok((scope, sig))
+	}
+
+	/// The signature of a method as declared in the trait, be it a default method or not
+	pub fn trait_method_signature(&self, id: TraitId, name: &str) -> Return<Option<FnSig>> {
+		let def = self.traits[id].def;
+		let (generics, where_clause, args, return_type) =
+			if let Some(f) = def.fn_signatures.iter().find(|f| *f.name == name) {
+				(&f.generics, &f.where_clause, &f.args, &f.return_type)
+			} else if let Some(f) = def.fn_defs.iter().find(|f| *f.name == name) {
+				(&f.generics, &f.where_clause, &f.args, &f.return_type)
+			} else {
+				return Ok(None);
+			};
+		let mut scope = self.trait_scope(id)?;
+		scope
+			.generics
+			.extend(generics.iter().flatten().map(|g| g.to_string()));
+		self.add_bounds(&mut scope, where_clause)?;
+		self.lower_signature(&scope, args, return_type).map(Some)
+	}
+
+	fn lower_signature(&self, scope: &TypeScope, args: &[FnArg], return_type: &Span<ast::Type>) -> Return<FnSig> {
+		Ok(FnSig {
+			generics: scope.generics.clone(),
+			args: args
+				.iter()
+				.map(|(_, t)| self.lower_type(scope, t))
+				.collect::<Return<_>>()?,
+			ret: self.lower_type(scope, return_type)?,
+			bounds: scope.bounds.clone(),
+		})
+	}
+
+	pub fn lower_trait(&self, scope: &TypeScope, trait_: &Span<ast::Trait>) -> Return<TraitRef> {
+		match self.resolve_path_str(&scope.module, &trait_.name)? {
+			Item::Trait(id) => Ok(TraitRef {
+				path: self.traits[id].path.clone(),
+				generics: trait_
+					.generics
+					.iter()
+					.map(|t| self.lower_type(scope, t))
+					.collect::<Return<_>>()?,
+			}),
+			_ => {
+				trait_
+					.as_error(
+						ErrorCode::UnknownNameError,
+						format!("`{}` is not a trait", trait_.name),
+					)
+					.display()?;
+				unreachable!()
+			}
+		}
+	}
+
+	/// Give meaning to a type written in the source
+	pub fn lower_type(&self, scope: &TypeScope, type_: &Span<ast::Type>) -> Return<Type> {
+		let (name, generics) = match &**type_ {
+			ast::Type::Empty => return Ok(Type::Empty),
+			ast::Type::Never => return Ok(Type::Never),
+			ast::Type::Fn(args, ret) => {
+				return Ok(Type::Fn(
+					args.iter()
+						.map(|t| self.lower_type(scope, t))
+						.collect::<Return<_>>()?,
+					Box::new(self.lower_type(scope, ret)?),
+				))
+			}
+			ast::Type::Tuple(types) => {
+				return Ok(Type::Tuple(
+					types
+						.iter()
+						.map(|t| self.lower_type(scope, t))
+						.collect::<Return<_>>()?,
+				))
+			}
+			ast::Type::Array(element, len) => {
+				let len = match &**len {
+					ast::ArrayLen::Literal(len) => Type::Length(*len),
+					ast::ArrayLen::Named(name) if scope.generics.contains(name) => Type::Generic(name.clone()),
+					ast::ArrayLen::Named(name) => Type::Length(self.array_len(scope, len, name)?),
+				};
+				return Ok(Type::Array(Box::new(self.lower_type(scope, element)?), Box::new(len)));
+			}
+			ast::Type::Other { name, generics } => (name, generics),
+		};
+		let segments: Vec<&str> = name.split("::").map(str::trim).collect();
+		match segments.as_slice() {
+			[single] if scope.generics.iter().any(|g| g == single) => {
+				return Ok(Type::Generic(single.to_string()))
+			}
+			[single] if *single == "Self" => {
+				if let Some(t) = &scope.self_type {
+					return Ok(t.clone());
+				}
+			}
+			[single] => {
+				if let Some(p) = Primitive::from_name(single) {
+					return Ok(Type::Primitive(p));
+				}
+			}
+			[base, assoc] if *base == "Self" || scope.generics.iter().any(|g| g == base) => {
+				return self.lower_assoc_type(scope, type_, base, assoc)
+			}
+			_ => (),
+		}
+		if let Some(Item::Type(id)) = self.lookup_path(&scope.module, &segments) {
+			let expected = self.types[id].generics.iter().flatten().count();
+			if generics.len() != expected {
+				type_
+					.as_error(
+						ErrorCode::ArgumentCountError,
+						format!(
+							"wrong number of generic arguments: `{}` takes {} but {} were supplied",
+							name,
+							expected,
+							generics.len()
+						),
+					)
+					.display()?;
+				unreachable!()
+			}
+			return Ok(Type::Adt(
+				self.types[id].path.clone(),
+				generics
+					.iter()
+					.map(|t| self.lower_type(scope, t))
+					.collect::<Return<_>>()?,
+			));
+		}
+		type_
+			.as_error(
+				ErrorCode::UnknownTypeError,
+				format!("cannot find type `{}` in this scope", name),
+			)
+			.display()?;
+		unreachable!()
+	}
+
+	/// The length of an array given by a constant, which must be a non-negative integer
+	fn array_len<S>(&self, scope: &TypeScope, span: &Span<S>, name: &str) -> Return<usize> {
+		let segments: Vec<&str> = name.split("::").map(str::trim).collect();
+		let id = match self.lookup_path(&scope.module, &segments) {
+			Some(Item::Const(id)) if self.consts[id].def.kind == ConstKind::Const => id,
+			Some(Item::Const(_)) => {
+				span.as_error(
+					ErrorCode::ConstEvalError,
+					format!("the length of an array can't be the static `{}`", name),
+				)
+				.display()?;
+				unreachable!()
+			}
+			_ => {
+				span.as_error(
+					ErrorCode::UnknownNameError,
+					format!("cannot find value `{}` in this scope", name),
+				)
+				.display()?;
+				unreachable!()
+			}
+		};
+		match self.const_value(id)? {
+			ConstValue::Const(Const::Int(len, _)) if len >= 0 => Ok(len as usize),
+			ConstValue::Const(Const::Int(len, _)) => {
+				span.as_error(
+					ErrorCode::ConstEvalError,
+					format!("the length of an array can't be negative, but `{}` is {}", name, len),
+				)
+				.display()?;
+				unreachable!()
+			}
+			_ => {
+				span.as_error(
+					ErrorCode::ConstEvalError,
+					format!("the length of an array must be an integer, but `{}` isn't", name),
+				)
+				.display()?;
+				unreachable!()
+			}
+		}
+	}
+
+	/// The type of a constant, in the scope of its module
+	pub fn const_type(&self, id: ConstId) -> Return<Type> {
+		let entry = &self.consts[id];
+		let scope = TypeScope {
+			module: entry.module.clone(),
+			..Default::default()
+		};
+		self.lower_type(&scope, &entry.def.type_)
+	}
+
+	/// The value of a constant, evaluated the first time it's asked for
+	pub fn const_value(&self, id: ConstId) -> Return<ConstValue> {
+		match self.const_values.borrow().get(&id) {
+			Some(Some(value)) => return Ok(value.clone()),
+			Some(None) => {
+				let def = self.consts[id].def;
+				def.name
+					.as_error(
+						ErrorCode::ConstEvalError,
+						format!("cycle detected when evaluating `{}`", def.name),
+					)
+					.display()?;
+				unreachable!()
+			}
+			None => (),
+		}
+		self.const_values.borrow_mut().insert(id, None);
+		let value = consts::eval(self, id)?;
+		self.const_values.borrow_mut().insert(id, Some(value.clone()));
+		Ok(value)
+	}
+
+	fn lower_assoc_type(&self, scope: &TypeScope, type_: &Span<ast::Type>, base: &str, name: &str) -> Return<Type> {
+		if base == "Self" {
+			if let Some(impl_) = scope.impl_ {
+				if let Some((_, t)) = self.impls[impl_].types.iter().find(|(d, _)| *d.name == name) {
+					return self.lower_type(scope, t);
+				}
+			}
+		}
+		let base_type = if base == "Self" {
+			scope.self_type.clone()
+		} else {
+			Some(Type::Generic(base.to_string()))
+		};
+		if let Some(base_type) = base_type {
+			for (bounded, trait_) in &scope.bounds {
+				if *bounded == base_type && self.trait_declares_type(trait_, name) {
+					return Ok(Type::Projection(
+						Box::new(base_type),
+						trait_.clone(),
+						name.to_string(),
+					));
+				}
+			}
+		}
+		type_
+			.as_error(
+				ErrorCode::UnknownTypeError,
+				format!("associated type `{}` not found for `{}`", name, base),
+			)
+			.display()?;
+		unreachable!()
+	}
+
+	fn trait_by_path(&self, path: &[String]) -> Option<TraitId> {
+		self.traits.iter().position(|t| t.path == path)
+	}
+
+	fn trait_declares_type(&self, trait_: &TraitRef, name: &str) -> bool {
+		self.trait_by_path(&trait_.path)
+			.map(|id| self.traits[id].def.types.iter().any(|(t, _)| *t.name == name))
+			.unwrap_or(false)
+	}
+
+	/// Find where `self_type` implements `trait_`, binding the inference variables if needed
+	pub fn find_impl(
+		&self,
+		infer: &mut InferCtx,
+		bounds: &[(Type, TraitRef)],
+		self_type: &Type,
+		trait_: &TraitRef,
+	) -> Option<ImplSource> {
+		self.find_impl_depth(infer, bounds, self_type, trait_, 0)
+	}
+
+	fn find_impl_depth(
+		&self,
+		infer: &mut InferCtx,
+		bounds: &[(Type, TraitRef)],
+		self_type: &Type,
+		trait_: &TraitRef,
+		depth: usize,
+	) -> Option<ImplSource> {
+		let self_type = infer.resolve(self_type);
+		for (bounded, bound) in bounds {
+			if *bounded == self_type && bound.path == trait_.path {
+				let mut attempt = infer.clone();
+				if generics_unify(&mut attempt, &bound.generics, &trait_.generics) {
+					*infer = attempt;
+					return Some(ImplSource::Bound);
+				}
+			}
+		}
+		if let (Type::Fn(args, ret), true) = (&self_type, is_fn_trait(&trait_.path)) {
+			let args = match args.len() {
+				0 => Type::Empty,
+				_ => Type::Tuple(args.clone()),
+			};
+			let mut attempt = infer.clone();
+			if !generics_unify(&mut attempt, &[args, (**ret).clone()], &trait_.generics) {
+				return None;
+			}
+			*infer = attempt;
+			return Some(ImplSource::Bound);
+		}
+		if depth > MAX_IMPL_DEPTH {
+			return None;
+		}
+		let mut candidates = Vec::new();
+		for (id, impl_) in self.impls.iter().enumerate() {
+			let impl_trait = match &impl_.trait_ref {
+				Some(t) if t.path == trait_.path => t,
+				_ => continue,
+			};
+			let mut attempt = infer.clone();
+			let map: HashMap<String, Type> = impl_
+				.generic_names()
+				.into_iter()
+				.map(|g| (g, attempt.new_var()))
+				.collect();
+			if !attempt.unify(&impl_.self_type.subst(&map), &self_type)
+				|| !generics_unify(&mut attempt, &impl_trait.subst(&map).generics, &trait_.generics)
+			{
+				continue;
+			}
+			let where_holds = match self.impl_scope(id) {
+				Ok(scope) => scope.bounds.iter().all(|(t, tr)| {
+					// Earlier bounds may have told what the associated types in this one are
+					let mut tr = tr.subst(&map);
+					for g in tr.generics.iter_mut() {
+						*g = self.normalize(&mut attempt, bounds, g).unwrap_or_else(|_| g.clone());
+					}
+					self.find_impl_depth(&mut attempt, bounds, &t.subst(&map), &tr, depth + 1).is_some()
+				}),
+				Err(_) => false,
+			};
+			if where_holds {
+				candidates.push((id, map, attempt));
+			}
+		}
+		match candidates.len() {
+			0 => None,
+			1 => {
+				let (id, map, attempt) = candidates.pop().unwrap();
+				*infer = attempt;
+				Some(ImplSource::Impl(id, map))
+			}
+			_ => Some(ImplSource::Ambiguous),
+		}
+	}
+
+	/// Replace the associated types with their definition when the impl is known
+	pub fn normalize(&self, infer: &mut InferCtx, bounds: &[(Type, TraitRef)], type_: &Type) -> Return<Type> {
+		Ok(match infer.resolve(type_) {
+			Type::Projection(self_type, trait_, name) => {
+				let self_type = self.normalize(infer, bounds, &self_type)?;
+				let mut trait_ = trait_;
+				for g in trait_.generics.iter_mut() {
+					*g = self.normalize(infer, bounds, g)?;
+				}
+				if let Type::Var(_) = self_type {
+					return Ok(Type::Projection(Box::new(self_type), trait_, name));
+				}
+				match self.find_impl(infer, bounds, &self_type, &trait_) {
+					Some(ImplSource::Impl(id, map)) => {
+						match self.impls[id].types.iter().find(|(d, _)| *d.name == name) {
+							Some((_, t)) => {
+								let t = self.lower_type(&self.impl_scope(id)?, t)?.subst(&map);
+								self.normalize(infer, bounds, &t)?
+							}
+							None => Type::Projection(Box::new(self_type), trait_, name),
+						}
+					}
+					_ => Type::Projection(Box::new(self_type), trait_, name),
+				}
+			}
+			Type::Tuple(types) => Type::Tuple(
+				types
+					.iter()
+					.map(|t| self.normalize(infer, bounds, t))
+					.collect::<Return<_>>()?,
+			),
+			Type::Array(element, len) => Type::Array(Box::new(self.normalize(infer, bounds, &element)?), len),
+			Type::Adt(path, generics) => Type::Adt(
+				path,
+				generics
+					.iter()
+					.map(|t| self.normalize(infer, bounds, t))
+					.collect::<Return<_>>()?,
+			),
+			Type::Fn(args, ret) => Type::Fn(
+				args.iter()
+					.map(|t| self.normalize(infer, bounds, t))
+					.collect::<Return<_>>()?,
+				Box::new(self.normalize(infer, bounds, &ret)?),
+			),
+			t => t,
+		})
+	}
+}
+
+/// Whether the trait is [`lang::FN`]
+pub fn is_fn_trait(path: &[String]) -> bool {
+	lang::FN == path
+}
+
+pub fn takes_self(args: &[FnArg]) -> bool {
+	matches!(args.first(), Some((name, _)) if **name == "self")
+}
+
+pub fn generics_unify(infer: &mut InferCtx, a: &[Type], b: &[Type]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).all(|(x, y)| infer.unify(x, y))
+}
@@ -0,0 +1,290 @@
+//! Exhaustiveness and reachability of patterns, following "Warnings for pattern matching" by Luc Maranget.
+//!
+//! Patterns are seen as constructors applied to sub-patterns, and a list of rows of patterns is checked
+//! column by column: a pattern is useful if some value matches it but none of the rows before it,
+//! and a match is exhaustive when a wildcard after all its arms isn't useful.
+
+use crate::ast::Fields;
+
+use super::{
+	db::TypeDB,
+	types::{Primitive, Type},
+};
+
+/// A pattern, without its bindings
+#[derive(Debug, Clone)]
+pub enum Pat {
+	Wild,
+	Ctor(Ctor, Vec<Pat>),
+	Or(Vec<Pat>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ctor {
+	/// A variant of an enum, and the only constructor of structs, tuples and arrays
+	Variant(usize),
+	Bool(bool),
+	Int(i128),
+	/// The bits of the float, so that constructors can be compared
+	Float(u64),
+	Str(String),
+	Char(char),
+}
+
+pub struct Exhaustiveness<'a, 'b> {
+	db: &'b TypeDB<'a>,
+}
+
+impl<'a, 'b> Exhaustiveness<'a, 'b> {
+	pub fn new(db: &'b TypeDB<'a>) -> Self {
+		Self { db }
+	}
+
+	/// All the constructors of a type, if it has a finite number of them
+	fn ctors(&self, t: &Type) -> Option<Vec<Ctor>> {
+		Some(match t {
+			Type::Primitive(Primitive::Bool) => vec![Ctor::Bool(false), Ctor::Bool(true)],
+			Type::Empty | Type::Tuple(_) | Type::Array(..) => vec![Ctor::Variant(0)],
+			Type::Adt(path, _) => {
+				let id = self.db.type_by_path(path)?;
+				(0..self.db.types[id].variants.len())
+					.map(Ctor::Variant)
+					.collect()
+			}
+			Type::Never => Vec::new(),
+			_ => return None,
+		})
+	}
+
+	/// The types of the fields of a constructor
+	fn fields(&self, t: &Type, ctor: &Ctor) -> Vec<Type> {
+		match (t, ctor) {
+			(Type::Tuple(types), _) => types.clone(),
+			(Type::Array(element, len), _) => match **len {
+				Type::Length(len) => vec![(**element).clone(); len],
+				_ => Vec::new(),
+			},
+			(Type::Adt(path, generics), Ctor::Variant(variant)) => {
+				let id = match self.db.type_by_path(path) {
+					Some(id) => id,
+					None => return Vec::new(),
+				};
+				let map = self.db.types[id]
+					.generic_names()
+					.into_iter()
+					.zip(generics.iter().cloned())
+					.collect();
+				let fields = self.db.variant_fields(id, *variant).unwrap_or_default();
+				fields.iter().map(|t| t.subst(&map)).collect()
+			}
+			_ => Vec::new(),
+		}
+	}
+
+	/// The rows of patterns matching the values that none of the rows match, when the columns have the given types
+	pub fn missing(&self, rows: Vec<Vec<Pat>>, types: &[Type]) -> Vec<Vec<Pat>> {
+		let (t, rest) = match types.split_first() {
+			Some(split) => split,
+			None if rows.is_empty() => return vec![Vec::new()],
+			None => return Vec::new(),
+		};
+		let rows = expand(rows);
+		let used = used_ctors(&rows);
+		match self.ctors(t) {
+			Some(all) if all.iter().all(|c| used.contains(c)) => {
+				let mut missing = Vec::new();
+				for ctor in all {
+					missing.extend(self.missing_with(&rows, t, rest, &ctor));
+				}
+				missing
+			}
+			all => {
+				let tails = self.missing(default(&rows), rest);
+				// Only name the missing constructors when some are matched, `_` reads better otherwise.
+				// Constructors with a field of an empty type, like `Err(Infallible)`, have no values to miss
+				let heads: Vec<Pat> = match all {
+					Some(all) if !used.is_empty() => all
+						.into_iter()
+						.filter(|c| !used.contains(c))
+						.filter_map(|c| {
+							let fields = self.fields(t, &c);
+							let args = vec![Pat::Wild; fields.len()];
+							self.useful(&[], &args, &fields).then_some(Pat::Ctor(c, args))
+						})
+						.collect(),
+					_ => vec![Pat::Wild],
+				};
+				let mut missing = Vec::new();
+				for head in &heads {
+					for tail in &tails {
+						missing.push([vec![head.clone()], tail.clone()].concat());
+					}
+				}
+				// The constructors that are matched can still miss some of their values, like `Some(false)`
+				let mut seen = Vec::new();
+				for ctor in used {
+					if seen.contains(&ctor) {
+						continue;
+					}
+					missing.extend(self.missing_with(&rows, t, rest, &ctor));
+					seen.push(ctor);
+				}
+				missing
+			}
+		}
+	}
+
+	/// The missing rows that start with a value made with the constructor
+	fn missing_with(&self, rows: &[Vec<Pat>], t: &Type, rest: &[Type], ctor: &Ctor) -> Vec<Vec<Pat>> {
+		let fields = self.fields(t, ctor);
+		let arity = fields.len();
+		let types = [fields, rest.to_vec()].concat();
+		self.missing(specialize(rows, ctor, arity), &types)
+			.into_iter()
+			.map(|row| {
+				let (args, tail) = row.split_at(arity);
+				[vec![Pat::Ctor(ctor.clone(), args.to_vec())], tail.to_vec()].concat()
+			})
+			.collect()
+	}
+
+	/// Whether some value matches the row but none of the rows
+	pub fn useful(&self, rows: &[Vec<Pat>], row: &[Pat], types: &[Type]) -> bool {
+		let (t, rest) = match types.split_first() {
+			Some(split) => split,
+			None => return rows.is_empty(),
+		};
+		let rows = expand(rows.to_vec());
+		match &row[0] {
+			Pat::Or(alternatives) => alternatives.iter().any(|alternative| {
+				self.useful(
+					&rows,
+					&[vec![alternative.clone()], row[1..].to_vec()].concat(),
+					types,
+				)
+			}),
+			Pat::Ctor(ctor, args) => {
+				let fields = self.fields(t, ctor);
+				self.useful(
+					&specialize(&rows, ctor, fields.len()),
+					&[args.clone(), row[1..].to_vec()].concat(),
+					&[fields, rest.to_vec()].concat(),
+				)
+			}
+			Pat::Wild => {
+				let used = used_ctors(&rows);
+				match self.ctors(t) {
+					Some(all) if all.iter().all(|c| used.contains(c)) => all.iter().any(|ctor| {
+						let fields = self.fields(t, ctor);
+						let arity = fields.len();
+						self.useful(
+							&specialize(&rows, ctor, arity),
+							&[vec![Pat::Wild; arity], row[1..].to_vec()].concat(),
+							&[fields, rest.to_vec()].concat(),
+						)
+					}),
+					_ => self.useful(&default(&rows), &row[1..], rest),
+				}
+			}
+		}
+	}
+
+	/// Write a pattern like it would be in the source
+	pub fn show(&self, pat: &Pat, t: &Type) -> String {
+		let (ctor, args) = match pat {
+			Pat::Wild => return "_".to_string(),
+			Pat::Or(alternatives) => {
+				return alternatives
+					.iter()
+					.map(|p| self.show(p, t))
+					.collect::<Vec<String>>()
+					.join(" | ")
+			}
+			Pat::Ctor(ctor, args) => (ctor, args),
+		};
+		let variant = match ctor {
+			Ctor::Variant(variant) => *variant,
+			Ctor::Bool(b) => return b.to_string(),
+			Ctor::Int(i) => return i.to_string(),
+			Ctor::Float(bits) => return format!("{:?}", f64::from_bits(*bits)),
+			Ctor::Str(s) => return format!("{:?}", s),
+			Ctor::Char(c) => return format!("{:?}", c),
+		};
+		let fields = self.fields(t, ctor);
+		let args: Vec<String> = args
+			.iter()
+			.zip(&fields)
+			.map(|(p, t)| self.show(p, t))
+			.collect();
+		match t {
+			Type::Tuple(_) if args.len() == 1 => format!("({},)", args[0]),
+			Type::Empty | Type::Tuple(_) => format!("({})", args.join(", ")),
+			Type::Array(..) => format!("[{}]", args.join(", ")),
+			Type::Adt(path, _) => {
+				let entry = &self.db.types[self.db.type_by_path(path).unwrap()];
+				let name = entry.variant_name(variant);
+				match entry.variants[variant].1 {
+					Fields::Unit => name,
+					Fields::Tuple(_) => format!("{}({})", name, args.join(", ")),
+					Fields::Named(names) => format!(
+						"{} {{ {} }}",
+						name,
+						names
+							.iter()
+							.zip(args)
+							.map(|((name, _), arg)| format!("{}: {}", &**name, arg))
+							.collect::<Vec<String>>()
+							.join(", ")
+					),
+				}
+			}
+			_ => "_".to_string(),
+		}
+	}
+}
+
+/// Replace the rows starting with an or-pattern by a row for each alternative
+fn expand(rows: Vec<Vec<Pat>>) -> Vec<Vec<Pat>> {
+	let mut expanded = Vec::new();
+	for row in rows {
+		match &row[0] {
+			Pat::Or(alternatives) => {
+				let rows = alternatives
+					.iter()
+					.map(|alternative| [vec![alternative.clone()], row[1..].to_vec()].concat())
+					.collect();
+				expanded.extend(expand(rows));
+			}
+			_ => expanded.push(row),
+		}
+	}
+	expanded
+}
+
+fn used_ctors(rows: &[Vec<Pat>]) -> Vec<Ctor> {
+	rows.iter()
+		.filter_map(|row| match &row[0] {
+			Pat::Ctor(ctor, _) => Some(ctor.clone()),
+			_ => None,
+		})
+		.collect()
+}
+
+/// The rows that match values made with the constructor, with its fields in place of the first column
+fn specialize(rows: &[Vec<Pat>], ctor: &Ctor, arity: usize) -> Vec<Vec<Pat>> {
+	rows.iter()
+		.filter_map(|row| match &row[0] {
+			Pat::Wild => Some([vec![Pat::Wild; arity], row[1..].to_vec()].concat()),
+			Pat::Ctor(c, args) if c == ctor => Some([args.clone(), row[1..].to_vec()].concat()),
+			_ => None,
+		})
+		.collect()
+}
+
+/// The rows that match any value in the first column, without it
+fn default(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+	rows.iter()
+		.filter(|row| matches!(row[0], Pat::Wild))
+		.map(|row| row[1..].to_vec())
+		.collect()
+}
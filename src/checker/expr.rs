@@ -0,0 +1,1963 @@
+use std::convert::TryFrom;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+	ast::{self, Block, ConstKind, Expr, Fields, Label, Literal, Pattern, Statement},
+	error::{ErrorCode, Return},
+	span::{BoxedSpan, Span},
+};
+
+use super::{
+	db::{
+		is_fn_trait, Callee, ConstId, FnId, FnSig, ImplSource, Item, Method, TypeDB, TypeId, TypeKind,
+		TypeScope, Use,
+	},
+	key, CheckedFn,
+	exhaustive::{Ctor, Exhaustiveness, Pat},
+	infer::{InferCtx, VarKind},
+	lang::{self, LangItem},
+	types::{IntType, Primitive, TraitRef, Type},
+};
+
+#[derive(Debug, Clone)]
+struct Local {
+	type_: Type,
+	mutable: bool,
+	id: usize,
+}
+
+/// The locals that are assigned on every path to the current point, and on at least one.
+/// `None` when the current point can't be reached.
+type Flow = Option<Init>;
+
+#[derive(Debug, Clone, Default)]
+struct Init {
+	init: HashSet<usize>,
+	maybe: HashSet<usize>,
+}
+
+#[derive(Debug)]
+struct LoopScope {
+	label: Option<String>,
+	/// Only `loop`s can be broken out of with a value
+	break_type: Option<Type>,
+	broken: bool,
+	/// The state where the loop is left with `break`, and where it goes back to its start with `continue`
+	breaks: Flow,
+	continues: Flow,
+	/// The first local declared in the loop, the ones before it aren't declared again by each iteration
+	start_id: usize,
+	/// The immutable locals from before the loop assigned in it, which the next iteration can't assign again
+	assigned: Vec<(usize, Span<String>)>,
+}
+
+/// The state where two paths meet
+fn join_flow(a: Flow, b: Flow) -> Flow {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(Init {
+			init: a.init.intersection(&b.init).copied().collect(),
+			maybe: a.maybe.union(&b.maybe).copied().collect(),
+		}),
+		(a, None) => a,
+		(None, b) => b,
+	}
+}
+
+#[derive(Debug)]
+struct PatternCheck<'a> {
+	span: Span<()>,
+	type_: Type,
+	/// The pattern of each arm, and whether it has a guard
+	arms: Vec<(&'a Span<Pattern>, bool)>,
+	/// What binds the pattern when it has to be irrefutable, like a `let`
+	binding: Option<&'static str>,
+}
+
+/// A `?` operator, whose return type only has a known head once inference is done
+#[derive(Debug)]
+struct TryCheck {
+	span: Span<()>,
+	return_type: Type,
+}
+
+#[derive(Debug)]
+struct ClosureScope {
+	/// The index of the scope of its arguments in `locals`, the variables of the scopes before it are captured
+	depth: usize,
+	captures: Vec<String>,
+}
+
+/// Checks the body of a function
+pub struct FnChecker<'a, 'b> {
+	db: &'b TypeDB<'a>,
+	scope: TypeScope,
+	infer: InferCtx,
+	locals: Vec<HashMap<String, Local>>,
+	/// Number of locals bound so far, which gives each one an id
+	local_count: usize,
+	flow: Flow,
+	loops: Vec<LoopScope>,
+	return_type: Type,
+	allow_builtins: bool,
+	/// Trait bounds that couldn't be checked yet, because the type wasn't known
+	obligations: Vec<(Span<()>, Type, TraitRef)>,
+	/// Number literals, whose range can only be checked once their type is known, and whether they are negated
+	literals: Vec<(Span<()>, &'a Literal, Type, bool)>,
+	/// What was found out about the body so far
+	types: CheckedFn,
+	/// Matches, whose exhaustiveness can only be checked once their types are known
+	patterns: Vec<PatternCheck<'a>>,
+	/// The closures being checked, innermost last
+	closures: Vec<ClosureScope>,
+	tries: Vec<TryCheck>,
+	/// The expressions being checked, innermost last, by [`key`]
+	current: Vec<usize>,
+}
+
+/// Report an error, which stops the checker
+fn fail<S, T>(span: &Span<S>, code: ErrorCode, message: String) -> Return<T> {
+	span.as_error(code, message).display()?;
+	unreachable!("errors always stop the checker")
+}
+
+impl<'a, 'b> FnChecker<'a, 'b> {
+	pub fn check_fn(db: &'b TypeDB<'a>, id: FnId) -> Return<CheckedFn> {
+		let (scope, sig) = db.fn_signature(id)?;
+		let entry = &db.fns[id];
+		let mut args = HashMap::new();
+		let mut flow = Init::default();
+		for (id, ((name, _), type_)) in entry.def.args.iter().zip(sig.args).enumerate() {
+			args.insert(
+				name.to_string(),
+				Local {
+					type_,
+					mutable: false,
+					id,
+				},
+			);
+			flow.init.insert(id);
+			flow.maybe.insert(id);
+		}
+		let allow_builtins = db.namespace(&entry.module).allow_builtins;
+		let mut checker = Self::new(db, scope, args, Some(flow), sig.ret.clone(), allow_builtins);
+		let body = checker.block(&entry.def.body)?;
+		checker.expect(&entry.def.body, &sig.ret, &body)?;
+		checker.finish()
+	}
+
+	/// Check the value of a `const` or a `static`, which gives their type to its literals
+	pub fn check_const(db: &'b TypeDB<'a>, id: ConstId) -> Return<CheckedFn> {
+		let entry = &db.consts[id];
+		let scope = TypeScope {
+			module: entry.module.clone(),
+			..Default::default()
+		};
+		let type_ = db.const_type(id)?;
+		let mut checker = Self::new(db, scope, HashMap::new(), Some(Init::default()), type_.clone(), false);
+		let value = checker.sub(&entry.def.value)?;
+		checker.expect(&entry.def.value, &type_, &value)?;
+		checker.finish()
+	}
+
+	fn new(
+		db: &'b TypeDB<'a>,
+		scope: TypeScope,
+		args: HashMap<String, Local>,
+		flow: Flow,
+		return_type: Type,
+		allow_builtins: bool,
+	) -> Self {
+		Self {
+			db,
+			scope,
+			infer: InferCtx::default(),
+			local_count: args.len(),
+			locals: vec![args],
+			flow,
+			loops: Vec::new(),
+			return_type,
+			allow_builtins,
+			obligations: Vec::new(),
+			literals: Vec::new(),
+			types: CheckedFn::default(),
+			patterns: Vec::new(),
+			closures: Vec::new(),
+			tries: Vec::new(),
+			current: Vec::new(),
+		}
+	}
+
+	/// Check what could only be once the types are known, and give back what was found
+	fn finish(mut self) -> Return<CheckedFn> {
+		self.infer.default_numeric_vars();
+		self.check_literals()?;
+		self.check_tries()?;
+		self.check_patterns()?;
+		self.solve_obligations()?;
+		let mut types = std::mem::take(&mut self.types);
+		let found = types
+			.uses
+			.iter_mut()
+			.flat_map(|u| u.generics.iter_mut())
+			.chain(types.exprs.values_mut())
+			.chain(types.blocks.values_mut())
+			.chain(types.patterns.values_mut());
+		for type_ in found {
+			*type_ = self.normalize(type_)?;
+		}
+		Ok(types)
+	}
+
+	/// Record a use the current expression makes
+	fn push_use(&mut self, use_: Use) {
+		if let Some(expr) = self.current.last() {
+			self.types.calls.entry(*expr).or_default().push(self.types.uses.len());
+		}
+		self.types.uses.push(use_);
+	}
+
+	/// Record a use of a function, with the types its generics were instantiated with
+	fn use_fn<S>(&mut self, span: &Span<S>, callee: Callee, generics: &[String], map: &HashMap<String, Type>) {
+		self.push_use(Use {
+			span: Span::new_ref(span, |_| ()),
+			callee,
+			generics: generics.iter().map(|g| map[g].clone()).collect(),
+		});
+	}
+
+	/// Record a call to the method of a lang trait, the generics being the ones of the trait then `Self`
+	fn use_lang<S>(&mut self, span: &Span<S>, item: LangItem, generics: Vec<Type>) {
+		let callee = Callee::Method(self.db.lang_trait(item.0), item.1.to_string());
+		self.push_use(Use {
+			span: Span::new_ref(span, |_| ()),
+			callee,
+			generics,
+		});
+	}
+
+	fn normalize(&mut self, type_: &Type) -> Return<Type> {
+		self.db.normalize(&mut self.infer, &self.scope.bounds, type_)
+	}
+
+	/// Make sure `found` can be used where `expected` is needed
+	fn expect<S>(&mut self, span: &Span<S>, expected: &Type, found: &Type) -> Return<()> {
+		let expected = self.normalize(expected)?;
+		let found = self.normalize(found)?;
+		if self.infer.unify(&expected, &found) {
+			Ok(())
+		} else {
+			fail(
+				span,
+				ErrorCode::TypeMismatchError,
+				format!(
+					"mismatched types: expected `{}`, found `{}`",
+					self.infer.describe(&expected),
+					self.infer.describe(&found)
+				),
+			)
+		}
+	}
+
+	/// Require `type_` to implement `trait_`, deferring the check while the type isn't known
+	fn require<S>(&mut self, span: &Span<S>, type_: &Type, trait_: TraitRef) -> Return<()> {
+		let type_ = self.normalize(type_)?;
+		let trait_ = self.normalize_trait(trait_)?;
+		match (&type_, self.db.find_impl(&mut self.infer, &self.scope.bounds, &type_, &trait_)) {
+			(Type::Never, _) => Ok(()),
+			(Type::Var(_), _) | (_, Some(ImplSource::Ambiguous)) => {
+				self.obligations.push((Span::new_ref(span, |_| ()), type_, trait_));
+				Ok(())
+			}
+			(_, Some(_)) => Ok(()),
+			(_, None) => self.not_implemented(span, &type_, &trait_),
+		}
+	}
+
+	fn normalize_trait(&mut self, trait_: TraitRef) -> Return<TraitRef> {
+		let generics = trait_.generics.iter().map(|t| self.normalize(t)).collect::<Return<_>>()?;
+		Ok(TraitRef { generics, ..trait_ })
+	}
+
+	fn not_implemented<S, T>(&self, span: &Span<S>, type_: &Type, trait_: &TraitRef) -> Return<T> {
+		let generics: Vec<String> = trait_.generics.iter().map(|t| self.infer.describe(t)).collect();
+		let mut trait_ = trait_.path.last().unwrap().clone();
+		if !generics.is_empty() {
+			trait_ += &format!("<{}>", generics.join(", "));
+		}
+		fail(
+			span,
+			ErrorCode::TraitNotImplementedError,
+			format!(
+				"the trait `{}` is not implemented for `{}`",
+				trait_,
+				self.infer.describe(type_)
+			),
+		)
+	}
+
+	fn solve_obligations(&mut self) -> Return<()> {
+		for (span, type_, trait_) in std::mem::take(&mut self.obligations) {
+			let type_ = self.normalize(&type_)?;
+			if type_.has_vars() {
+				continue;
+			}
+			let trait_ = self.normalize_trait(trait_)?;
+			if self
+				.db
+				.find_impl(&mut self.infer, &self.scope.bounds, &type_, &trait_)
+				.is_none()
+			{
+				return self.not_implemented(&span, &type_, &trait_);
+			}
+		}
+		Ok(())
+	}
+
+	fn lang_trait(&self, item: LangItem) -> TraitRef {
+		TraitRef {
+			path: self.db.traits[self.db.lang_trait(item.0)].path.clone(),
+			generics: Vec::new(),
+		}
+	}
+
+	/// Check an operator, which is a call to the method of a trait in `core::ops`
+	fn operator<S>(&mut self, span: &Span<S>, item: LangItem, operands: Vec<Type>) -> Return<Type> {
+		let self_type = self.normalize(&operands[0])?;
+		if self_type == Type::Never {
+			return Ok(Type::Never);
+		}
+		// Like the arguments of a closure that's checked before it's called
+		if self.infer.kind(&self_type) == Some(VarKind::Any) {
+			return fail(span, ErrorCode::UnknownTypeError, "type annotations needed".to_string());
+		}
+		let numeric = self.numeric_operands(&operands);
+		let mut trait_ = self.lang_trait(item);
+		trait_.generics = operands[1..].to_vec();
+		self.require(span, &self_type, trait_.clone())?;
+		let mut generics = trait_.generics.clone();
+		generics.push(self_type.clone());
+		self.use_lang(span, item, generics);
+		if numeric {
+			// The builtin operators on numbers give back the same type, which lets literals be inferred
+			return Ok(self_type);
+		}
+		self.normalize(&Type::Projection(
+			Box::new(self_type),
+			trait_,
+			"Output".to_string(),
+		))
+	}
+
+	/// Whether all the operands are numbers of the same type, making them so if they are literals
+	fn numeric_operands(&mut self, operands: &[Type]) -> bool {
+		operands.iter().all(|t| self.infer.is_numeric(t))
+			&& operands[1..].iter().all(|t| self.infer.unify(&operands[0], t))
+	}
+
+	fn local(&self, name: &str) -> Option<&Local> {
+		self.locals.iter().rev().find_map(|scope| scope.get(name))
+	}
+
+	/// Whether the local variable is declared outside of the innermost closure
+	fn is_captured(&self, name: &str) -> bool {
+		match (self.locals.iter().rposition(|scope| scope.contains_key(name)), self.closures.last()) {
+			(Some(depth), Some(closure)) => depth < closure.depth,
+			_ => false,
+		}
+	}
+
+	/// Record that the closures the variable is used in capture it
+	fn capture(&mut self, name: &str) {
+		let depth = match self.locals.iter().rposition(|scope| scope.contains_key(name)) {
+			Some(depth) => depth,
+			None => return,
+		};
+		for closure in self.closures.iter_mut().filter(|c| depth < c.depth) {
+			if !closure.captures.iter().any(|c| c == name) {
+				closure.captures.push(name.to_string());
+			}
+		}
+	}
+
+	/// Check a pattern against the type of the value it matches, adding the variables it binds to the scope.
+	/// They are already assigned if `init` is set
+	fn bind(&mut self, pattern: &'a Span<Pattern>, type_: Type, init: bool) -> Return<()> {
+		self.types.patterns.insert(key(&**pattern), type_.clone());
+		match &**pattern {
+			Pattern::Wildcard => (),
+			Pattern::Binding {
+				mutable: None,
+				name,
+				subpattern: None,
+			} if self.db.unit_variant(&self.scope.module, name).is_some() => {
+				let (id, variant) = self.db.unit_variant(&self.scope.module, name).unwrap();
+				self.variant_pattern(pattern, &type_, id, variant)?;
+			}
+			Pattern::Binding {
+				mutable,
+				name,
+				subpattern,
+			} => {
+				if let Some(subpattern) = subpattern {
+					self.bind(subpattern, type_.clone(), init)?;
+				}
+				let id = self.local_count;
+				self.local_count += 1;
+				self.locals.last_mut().unwrap().insert(
+					name.to_string(),
+					Local {
+						type_,
+						mutable: mutable.is_some(),
+						id,
+					},
+				);
+				if init {
+					self.set_init(id);
+				}
+			}
+			Pattern::Literal(literal, negated) => {
+				if *negated && !matches!(literal, Literal::Int(..) | Literal::Float(..)) {
+					let found = self.literal(pattern, literal, false)?;
+					return fail(
+						pattern,
+						ErrorCode::TypeMismatchError,
+						format!("cannot apply unary operator `-` to type `{}`", found),
+					);
+				}
+				let found = self.literal(pattern, literal, *negated)?;
+				self.expect(pattern, &type_, &found)?;
+			}
+			Pattern::Tuple(patterns) => {
+				let types: Vec<Type> = patterns.iter().map(|_| self.infer.new_var()).collect();
+				self.expect(pattern, &type_, &Type::Tuple(types.clone()))?;
+				for (pattern, type_) in patterns.iter().zip(types) {
+					self.bind(pattern, type_, init)?;
+				}
+			}
+			Pattern::Array(patterns) => {
+				let element = self.infer.new_var();
+				let found = Type::Array(Box::new(element.clone()), Box::new(Type::Length(patterns.len())));
+				self.expect(pattern, &type_, &found)?;
+				for pattern in patterns {
+					self.bind(pattern, element.clone(), init)?;
+				}
+			}
+			Pattern::Path(path) => {
+				let (id, variant) = self.pattern_variant(path)?;
+				let entry = &self.db.types[id];
+				if !matches!(entry.variants[variant].1, Fields::Unit) {
+					return fail(
+						pattern,
+						ErrorCode::UnknownNameError,
+						format!(
+							"expected unit struct or unit variant, found {} `{}`",
+							entry.variant_kind(variant),
+							entry.variant_name(variant)
+						),
+					);
+				}
+				self.variant_pattern(pattern, &type_, id, variant)?;
+			}
+			Pattern::TupleStruct(path, patterns) => {
+				let (id, variant) = self.pattern_variant(path)?;
+				let entry = &self.db.types[id];
+				let len = match entry.variants[variant].1 {
+					Fields::Tuple(types) => types.len(),
+					_ => {
+						return fail(
+							pattern,
+							ErrorCode::UnknownNameError,
+							format!(
+								"expected tuple struct or tuple variant, found {} `{}`",
+								entry.variant_kind(variant),
+								entry.variant_name(variant)
+							),
+						)
+					}
+				};
+				if len != patterns.len() {
+					return fail(
+						pattern,
+						ErrorCode::ArgumentCountError,
+						format!(
+							"this pattern has {} fields, but the corresponding {} has {} fields",
+							patterns.len(),
+							entry.variant_kind(variant),
+							len
+						),
+					);
+				}
+				let fields = self.variant_pattern(pattern, &type_, id, variant)?;
+				for (pattern, type_) in patterns.iter().zip(fields) {
+					self.bind(pattern, type_, init)?;
+				}
+			}
+			Pattern::Struct(path, patterns, rest) => {
+				let (id, variant) = self.pattern_variant(path)?;
+				let entry = &self.db.types[id];
+				let names: Vec<&str> = match entry.variants[variant].1 {
+					Fields::Named(fields) => fields.iter().map(|(name, _)| name.as_str()).collect(),
+					_ => Vec::new(),
+				};
+				let fields = self.variant_pattern(pattern, &type_, id, variant)?;
+				let mut given = vec![false; names.len()];
+				for (name, field) in patterns {
+					let index = match entry.field_index(variant, name) {
+						Some(index) if index < given.len() => index,
+						_ => {
+							return fail(
+								name,
+								ErrorCode::UnknownNameError,
+								format!(
+									"{} `{}` does not have a field named `{}`",
+									entry.variant_kind(variant),
+									entry.variant_name(variant),
+									&**name
+								),
+							)
+						}
+					};
+					if given[index] {
+						return fail(
+							name,
+							ErrorCode::ArgumentCountError,
+							format!("field `{}` bound multiple times in the pattern", &**name),
+						);
+					}
+					given[index] = true;
+					self.bind(field, fields[index].clone(), init)?;
+				}
+				let missing: Vec<String> = names
+					.iter()
+					.zip(given)
+					.filter(|(_, given)| !given)
+					.map(|(name, _)| format!("`{}`", name))
+					.collect();
+				if !missing.is_empty() && !rest {
+					return fail(
+						pattern,
+						ErrorCode::ArgumentCountError,
+						format!(
+							"pattern does not mention field{} {}",
+							if missing.len() == 1 { "" } else { "s" },
+							missing.join(", ")
+						),
+					);
+				}
+			}
+			Pattern::Or(alternatives) => {
+				// Every alternative has to bind the same variables, with the same types
+				let mut bound: Option<HashMap<String, Local>> = None;
+				for alternative in alternatives {
+					self.locals.push(HashMap::new());
+					self.bind(alternative, type_.clone(), init)?;
+					let locals = self.locals.pop().unwrap();
+					let first = match &bound {
+						Some(first) => first,
+						None => {
+							bound = Some(locals);
+							continue;
+						}
+					};
+					let unbound = first
+						.keys()
+						.find(|name| !locals.contains_key(*name))
+						.or_else(|| locals.keys().find(|name| !first.contains_key(*name)));
+					if let Some(name) = unbound {
+						return fail(
+							alternative,
+							ErrorCode::UnknownNameError,
+							format!("variable `{}` is not bound in all patterns", name),
+						);
+					}
+					for (name, local) in &locals {
+						let expected = first[name].type_.clone();
+						self.expect(alternative, &expected, &local.type_)?;
+					}
+				}
+				self.locals.last_mut().unwrap().extend(bound.unwrap_or_default());
+			}
+		}
+		Ok(())
+	}
+
+	fn irrefutable(&mut self, pattern: &'a Span<Pattern>, type_: Type, binding: &'static str) {
+		let span = Span::new_ref(pattern, |_| ());
+		self.patterns.push(PatternCheck {
+			span,
+			type_,
+			arms: vec![(pattern, false)],
+			binding: Some(binding),
+		});
+	}
+
+	/// Resolve the path of a pattern, which has to be a struct or a variant
+	fn pattern_variant(&self, path: &[Span<String>]) -> Return<(TypeId, usize)> {
+		let item = self.db.resolve_path(&self.scope.module, path)?;
+		match self.db.variant_of(&item) {
+			Some(variant) => Ok(variant),
+			None => fail(
+				&path[path.len() - 1],
+				ErrorCode::UnknownNameError,
+				format!(
+					"expected struct or variant, found {} `{}`",
+					self.item_kind(&item),
+					join_path(path)
+				),
+			),
+		}
+	}
+
+	/// Make the matched type the one of the variant, giving back the types of its fields
+	fn variant_pattern<S>(&mut self, span: &Span<S>, type_: &Type, id: TypeId, variant: usize) -> Return<Vec<Type>> {
+		let (sig, _) = self.db.variant_signature(id, variant)?.instantiate(&mut self.infer);
+		self.expect(span, type_, &sig.ret)?;
+		Ok(sig.args)
+	}
+
+	/// Check that matches cover every value and warn about their arms that can't be reached
+	fn check_patterns(&mut self) -> Return<()> {
+		let exhaustiveness = Exhaustiveness::new(self.db);
+		for PatternCheck {
+			span,
+			type_,
+			arms,
+			binding,
+		} in std::mem::take(&mut self.patterns)
+		{
+			let type_ = self.normalize(&type_)?;
+			let types = [type_.clone()];
+			let mut rows = Vec::new();
+			for (pattern, guarded) in arms {
+				let alternatives = match &**pattern {
+					Pattern::Or(alternatives) => alternatives.iter().collect(),
+					_ => vec![pattern],
+				};
+				for alternative in alternatives {
+					let row = vec![self.lower_pattern(alternative)];
+					if binding.is_none() && !exhaustiveness.useful(&rows, &row, &types) {
+						alternative
+							.as_error(ErrorCode::UnreachablePatternWarning, "unreachable pattern".to_string())
+							.display()?;
+					}
+					// A guard may not match, so the arm doesn't cover anything
+					if !guarded {
+						rows.push(row);
+					}
+				}
+			}
+			let missing = exhaustiveness.missing(rows, &types);
+			if missing.is_empty() {
+				continue;
+			}
+			let mut shown: Vec<String> = missing
+				.iter()
+				.take(3)
+				.map(|row| format!("`{}`", exhaustiveness.show(&row[0], &type_)))
+				.collect();
+			let last = match missing.len() {
+				n if n > 3 => format!("{} more", n - 3),
+				_ => shown.pop().unwrap(),
+			};
+			let patterns = match shown.is_empty() {
+				true => last,
+				false => format!("{} and {}", shown.join(", "), last),
+			};
+			return fail(
+				&span,
+				ErrorCode::NonExhaustivePatternError,
+				match binding {
+					Some(binding) => format!("refutable pattern in {}: {} not covered", binding, patterns),
+					None => format!("non-exhaustive patterns: {} not covered", patterns),
+				},
+			);
+		}
+		Ok(())
+	}
+
+	fn set_init(&mut self, id: usize) {
+		if let Some(flow) = &mut self.flow {
+			flow.init.insert(id);
+			flow.maybe.insert(id);
+		}
+	}
+
+	/// The constructors of a checked pattern, without its bindings
+	fn lower_pattern(&self, pattern: &Pattern) -> Pat {
+		let variant = |path: &[Span<String>]| {
+			let item = self.db.resolve_path(&self.scope.module, path).unwrap();
+			self.db.variant_of(&item).unwrap()
+		};
+		let lower_all = |patterns: &[Span<Pattern>]| patterns.iter().map(|p| self.lower_pattern(p)).collect();
+		match pattern {
+			Pattern::Wildcard => Pat::Wild,
+			Pattern::Binding {
+				mutable: None,
+				name,
+				subpattern: None,
+			} if self.db.unit_variant(&self.scope.module, name).is_some() => {
+				let (_, variant) = self.db.unit_variant(&self.scope.module, name).unwrap();
+				Pat::Ctor(Ctor::Variant(variant), Vec::new())
+			}
+			Pattern::Binding {
+				subpattern: Some(subpattern),
+				..
+			} => self.lower_pattern(subpattern),
+			Pattern::Binding { .. } => Pat::Wild,
+			Pattern::Literal(literal, negated) => {
+				let ctor = match literal {
+					Literal::Int(value, _) => match self.types.literals.get(&key(literal)) {
+						Some(Primitive::Float(t)) => {
+							let value = t.round(*value as f64);
+							Ctor::Float(if *negated { -value } else { value }.to_bits())
+						}
+						_ => {
+							let value = i128::try_from(*value).unwrap();
+							Ctor::Int(if *negated { -value } else { value })
+						}
+					},
+					Literal::Float(value, _) => Ctor::Float(if *negated { -value } else { *value }.to_bits()),
+					Literal::String(s) => Ctor::Str(s.to_string()),
+					Literal::Char(c) => Ctor::Char(*c),
+					Literal::Bool(b) => Ctor::Bool(*b),
+				};
+				Pat::Ctor(ctor, Vec::new())
+			}
+			Pattern::Tuple(patterns) | Pattern::Array(patterns) => Pat::Ctor(Ctor::Variant(0), lower_all(patterns)),
+			Pattern::Path(path) => Pat::Ctor(Ctor::Variant(variant(path).1), Vec::new()),
+			Pattern::TupleStruct(path, patterns) => Pat::Ctor(Ctor::Variant(variant(path).1), lower_all(patterns)),
+			Pattern::Struct(path, patterns, _) => {
+				let (id, variant) = variant(path);
+				let entry = &self.db.types[id];
+				let len = match entry.variants[variant].1 {
+					Fields::Named(fields) => fields.len(),
+					_ => 0,
+				};
+				let mut fields = vec![Pat::Wild; len];
+				for (name, pattern) in patterns {
+					fields[entry.field_index(variant, name).unwrap()] = self.lower_pattern(pattern);
+				}
+				Pat::Ctor(Ctor::Variant(variant), fields)
+			}
+			Pattern::Or(alternatives) => Pat::Or(lower_all(alternatives)),
+		}
+	}
+
+	fn block(&mut self, block: &'a Block) -> Return<Type> {
+		self.locals.push(HashMap::new());
+		let mut result = None;
+		let mut diverges = false;
+		for statement in block {
+			match &***statement {
+				Statement::Returning(expr) => result = Some(self.expr(expr, statement)?),
+				Statement::NonReturning(expr) => {
+					let type_ = self.expr(expr, statement)?;
+					diverges |= self.infer.shallow(&type_) == Type::Never;
+				}
+				Statement::Let(pattern, type_, value, else_block) => {
+					let declared = match type_ {
+						Some(t) => Some(self.db.lower_type(&self.scope, t)?),
+						None => None,
+					};
+					let type_ = match (declared, value) {
+						(Some(declared), Some(value)) => {
+							let found = self.sub_expecting(value, &declared)?;
+							self.expect(value, &declared, &found)?;
+							declared
+						}
+						(None, Some(value)) => self.sub(value)?,
+						(Some(declared), None) => declared,
+						(None, None) => self.infer.new_var(),
+					};
+					match else_block {
+						// The variables of the pattern can't be used in the `else`
+						Some(block) => {
+							let matched = self.flow.clone();
+							let found = self.block(block)?;
+							if self.infer.shallow(&found) != Type::Never {
+								return fail(
+									statement,
+									ErrorCode::TypeMismatchError,
+									"`else` clause of `let...else` does not diverge".to_string(),
+								);
+							}
+							self.flow = matched;
+							self.bind(pattern, type_, true)?;
+						}
+						None => {
+							self.bind(pattern, type_.clone(), value.is_some())?;
+							self.irrefutable(pattern, type_, "local binding");
+						}
+					}
+				}
+			}
+		}
+		self.locals.pop();
+		let type_ = match result {
+			Some(t) => t,
+			None if diverges => Type::Never,
+			None => Type::Empty,
+		};
+		self.types.blocks.insert(key(block), type_.clone());
+		Ok(type_)
+	}
+
+	fn sub(&mut self, expr: &'a BoxedSpan<Expr>) -> Return<Type> {
+		self.expr(expr, expr)
+	}
+
+	/// Check an expression whose value should have the expected type, which closures use to infer their arguments.
+	/// The caller still has to make sure the types match
+	fn sub_expecting(&mut self, expr: &'a BoxedSpan<Expr>, expected: &Type) -> Return<Type> {
+		match &***expr {
+			Expr::Closure(params, return_type, body) => {
+				let type_ = self.closure(key(&***expr), params, return_type, body, Some(expected))?;
+				self.types.exprs.insert(key(&***expr), type_.clone());
+				Ok(type_)
+			}
+			_ => self.sub(expr),
+		}
+	}
+
+	fn expr<S>(&mut self, expr: &'a Expr, span: &Span<S>) -> Return<Type> {
+		self.current.push(key(expr));
+		let type_ = self.expr_inner(expr, span)?;
+		self.current.pop();
+		self.types.exprs.insert(key(expr), type_.clone());
+		if self.infer.shallow(&type_) == Type::Never {
+			self.flow = None;
+		}
+		Ok(type_)
+	}
+
+	fn expr_inner<S>(&mut self, expr: &'a Expr, span: &Span<S>) -> Return<Type> {
+		Ok(match expr {
+			Expr::Literal(literal) => self.literal(span, literal, false)?,
+			Expr::Add(a, _, b) => self.binary(span, lang::ADD, a, b)?,
+			Expr::Sub(a, _, b) => self.binary(span, lang::SUB, a, b)?,
+			Expr::Mul(a, _, b) => self.binary(span, lang::MUL, a, b)?,
+			Expr::Div(a, _, b) => self.binary(span, lang::DIV, a, b)?,
+			Expr::Pow(a, _, b) => self.binary(span, lang::POW, a, b)?,
+			Expr::Eq(a, _, b) => self.comparison(span, lang::EQ, a, b)?,
+			Expr::Ne(a, _, b) => self.comparison(span, lang::NE, a, b)?,
+			Expr::Lt(a, _, b) => self.comparison(span, lang::LT, a, b)?,
+			Expr::Le(a, _, b) => self.comparison(span, lang::LE, a, b)?,
+			Expr::Gt(a, _, b) => self.comparison(span, lang::GT, a, b)?,
+			Expr::Ge(a, _, b) => self.comparison(span, lang::GE, a, b)?,
+			Expr::And(a, _, b) | Expr::Or(a, _, b) => {
+				self.condition(a)?;
+				// `b` may not run
+				let skipped = self.flow.clone();
+				self.condition(b)?;
+				self.flow = join_flow(skipped, self.flow.take());
+				Type::bool()
+			}
+			Expr::Not(a) => {
+				let a = self.sub(a)?;
+				self.operator(span, lang::NOT, vec![a])?
+			}
+			Expr::Neg(a) => match &***a {
+				// The range of a literal depends on its sign
+				Expr::Literal(literal @ Literal::Int(..)) => self.literal(span, literal, true)?,
+				_ => {
+					let a = self.sub(a)?;
+					self.operator(span, lang::NEG, vec![a])?
+				}
+			},
+			Expr::Cast(value, type_) => self.cast(value, type_)?,
+			Expr::Tuple(elements) => Type::Tuple(elements.iter().map(|e| self.sub(e)).collect::<Return<_>>()?),
+			Expr::Array(elements) => {
+				let element = self.infer.new_var();
+				for e in elements {
+					let found = self.sub(e)?;
+					self.expect(e, &element, &found)?;
+				}
+				Type::Array(Box::new(element), Box::new(Type::Length(elements.len())))
+			}
+			Expr::Index(base, index) => self.binary(span, lang::INDEX, base, index)?,
+			Expr::Field(base, name) => self.field(base, name)?,
+			Expr::Struct(path, fields) => self.struct_literal(span, path, fields)?,
+			Expr::Assign(place, _, value) => {
+				self.assign(place, value)?;
+				Type::Empty
+			}
+			Expr::FnCall(callee, args) => self.call(span, callee, args)?,
+			Expr::Closure(params, return_type, body) => self.closure(key(expr), params, return_type, body, None)?,
+			Expr::MethodCall(receiver, name, args) => self.method_call(span, key(expr), receiver, name, args)?,
+			Expr::Try(operand) => self.try_operator(span, operand)?,
+			Expr::If(condition, block, elseif_clauses, else_clause) => {
+				let (mut result, mut otherwise) = self.branch(condition, block)?;
+				let mut end = self.flow.take();
+				for (condition, block) in elseif_clauses {
+					self.flow = otherwise;
+					let (branch, next) = self.branch(condition, block)?;
+					otherwise = next;
+					end = join_flow(end, self.flow.take());
+					result = self.join(span, result, branch)?;
+				}
+				self.flow = otherwise;
+				let result = match else_clause {
+					Some(block) => {
+						let branch = self.block(block)?;
+						self.join(span, result, branch)?
+					}
+					None => {
+						self.expect(span, &Type::Empty, &result)?;
+						Type::Empty
+					}
+				};
+				self.flow = join_flow(end, self.flow.take());
+				result
+			}
+			Expr::Match(scrutinee, arms) => {
+				let type_ = self.sub(scrutinee)?;
+				let start = self.flow.take();
+				let mut end = None;
+				let mut result = Type::Never;
+				for arm in arms {
+					self.flow = start.clone();
+					self.locals.push(HashMap::new());
+					self.bind(&arm.pattern, type_.clone(), true)?;
+					if let Some(guard) = &arm.guard {
+						self.condition(guard)?;
+					}
+					let body = self.sub(&arm.body)?;
+					self.locals.pop();
+					end = join_flow(end, self.flow.take());
+					result = self.join(&arm.body, result, body)?;
+				}
+				self.flow = end;
+				let arms = arms.iter().map(|arm| (&arm.pattern, arm.guard.is_some())).collect();
+				self.patterns.push(PatternCheck {
+					span: Span::new_ref(scrutinee, |_| ()),
+					type_,
+					arms,
+					binding: None,
+				});
+				result
+			}
+			Expr::Block(block) => self.block(block)?,
+			Expr::While(label, condition, body) => {
+				self.locals.push(HashMap::new());
+				self.condition(condition)?;
+				let exit = self.flow.clone();
+				self.loop_body(span, label, None, body, Some(exit))?;
+				self.locals.pop();
+				Type::Empty
+			}
+			Expr::Let(..) => unreachable!("`let` is only parsed as the condition of `if` and `while`"),
+			Expr::Loop(label, body) => {
+				let break_type = self.infer.new_var();
+				if self.loop_body(span, label, Some(break_type.clone()), body, None)? {
+					break_type
+				} else {
+					Type::Never
+				}
+			}
+			Expr::For(label, pattern, iterator, body) => {
+				let iterator_type = self.sub(iterator)?;
+				let trait_ = self.lang_trait(lang::ITERATOR_NEXT);
+				self.require(iterator, &iterator_type, trait_.clone())?;
+				self.use_lang(iterator, lang::ITERATOR_NEXT, vec![iterator_type.clone()]);
+				let item = self.normalize(&Type::Projection(
+					Box::new(iterator_type),
+					trait_,
+					"Item".to_string(),
+				))?;
+				let exit = self.flow.clone();
+				self.locals.push(HashMap::new());
+				self.bind(pattern, item.clone(), true)?;
+				self.irrefutable(pattern, item, "`for` loop binding");
+				self.loop_body(span, label, None, body, Some(exit))?;
+				self.locals.pop();
+				Type::Empty
+			}
+			Expr::Break(label, value) => {
+				let found = match value {
+					Some(value) => Some((self.sub(value)?, value)),
+					None => None,
+				};
+				let index = self.find_loop(span, label, "break")?;
+				let scope = &mut self.loops[index];
+				scope.broken = true;
+				scope.breaks = join_flow(scope.breaks.take(), self.flow.clone());
+				match (self.loops[index].break_type.clone(), found) {
+					(Some(expected), Some((found, value))) => self.expect(value, &expected, &found)?,
+					(Some(expected), None) => self.expect(span, &expected, &Type::Empty)?,
+					(None, Some((_, value))) => {
+						return fail(
+							value,
+							ErrorCode::LoopControlError,
+							"`break` with a value is only allowed inside `loop`".to_string(),
+						)
+					}
+					(None, None) => (),
+				}
+				Type::Never
+			}
+			Expr::Continue(label) => {
+				let index = self.find_loop(span, label, "continue")?;
+				let scope = &mut self.loops[index];
+				scope.continues = join_flow(scope.continues.take(), self.flow.clone());
+				Type::Never
+			}
+			Expr::Return(value) => {
+				let expected = self.return_type.clone();
+				match value {
+					Some(value) => {
+						let found = self.sub(value)?;
+						self.expect(value, &expected, &found)?;
+					}
+					None => self.expect(span, &expected, &Type::Empty)?,
+				}
+				Type::Never
+			}
+			Expr::Ident(path) => self.ident(span, path)?,
+		})
+	}
+
+	fn binary<S>(&mut self, span: &Span<S>, item: LangItem, a: &'a BoxedSpan<Expr>, b: &'a BoxedSpan<Expr>) -> Return<Type> {
+		let a = self.sub(a)?;
+		let b = self.sub(b)?;
+		self.operator(span, item, vec![a, b])
+	}
+
+	fn literal<S>(&mut self, span: &Span<S>, literal: &'a Literal, negated: bool) -> Return<Type> {
+		let type_ = match literal {
+			Literal::Int(_, suffix) => self.literal_type(suffix, VarKind::Int, "number")?,
+			Literal::Float(_, suffix) => self.literal_type(suffix, VarKind::Float, "float")?,
+			Literal::String(_) => return Ok(Type::str()),
+			Literal::Char(_) => return Ok(Type::char()),
+			Literal::Bool(_) => return Ok(Type::bool()),
+		};
+		self.literals
+			.push((Span::new_ref(span, |_| ()), literal, type_.clone(), negated));
+		Ok(type_)
+	}
+
+	/// The type of a number literal, given by its suffix, or a variable of the kind if it has none
+	fn literal_type(&mut self, suffix: &Option<Span<String>>, kind: VarKind, name: &str) -> Return<Type> {
+		let suffix = match suffix {
+			Some(suffix) => suffix,
+			None => return Ok(self.infer.new_kind_var(kind)),
+		};
+		match (Primitive::from_name(suffix), kind) {
+			(Some(p @ Primitive::Float(_)), _) | (Some(p @ Primitive::Int(_)), VarKind::Int) => Ok(Type::Primitive(p)),
+			_ => fail(
+				suffix,
+				ErrorCode::LiteralError,
+				format!("invalid suffix `{}` for {} literal", &**suffix, name),
+			),
+		}
+	}
+
+	/// Check that the number literals fit in the type they were given, and tell it to the backends
+	fn check_literals(&mut self) -> Return<()> {
+		for (span, literal, type_, negated) in std::mem::take(&mut self.literals) {
+			let primitive = match self.infer.shallow(&type_) {
+				Type::Primitive(p) => p,
+				t => unreachable!("number literals are always given a primitive type, found {:?}", t),
+			};
+			let fits = match (literal, primitive) {
+				(Literal::Int(..), Primitive::Int(t)) if negated && !t.signed() => {
+					return fail(
+						&span,
+						ErrorCode::TypeMismatchError,
+						format!("cannot apply unary operator `-` to type `{}`", t.name()),
+					)
+				}
+				(Literal::Int(value, _), Primitive::Int(t)) => match i128::try_from(*value) {
+					Ok(value) => t.contains(if negated { -value } else { value }),
+					Err(_) => false,
+				},
+				(Literal::Int(value, _), Primitive::Float(t)) => t.round(*value as f64).is_finite(),
+				(Literal::Float(value, _), Primitive::Float(t)) => t.round(*value).is_finite(),
+				_ => unreachable!("the type of a number literal is always a number"),
+			};
+			if !fits {
+				return fail(
+					&span,
+					ErrorCode::LiteralError,
+					format!("literal out of range for `{}`", primitive.name()),
+				);
+			}
+			self.types.literals.insert(key(literal), primitive);
+		}
+		Ok(())
+	}
+
+	/// Check `operand?`, which gives back the output of the operand, or returns early with what's left of it
+	fn try_operator<S>(
+		&mut self,
+		span: &Span<S>,
+		operand: &'a BoxedSpan<Expr>,
+	) -> Return<Type> {
+		let found = self.sub(operand)?;
+		let found = self.normalize(&found)?;
+		if found == Type::Never {
+			return Ok(Type::Never);
+		}
+		if self.infer.kind(&found) == Some(VarKind::Any) {
+			return fail(operand, ErrorCode::UnknownTypeError, "type annotations needed".to_string());
+		}
+		let try_ = self.lang_trait(lang::TRY_BRANCH);
+		if !self.may_implement(&found, &try_) {
+			return fail(
+				operand,
+				ErrorCode::TraitNotImplementedError,
+				format!(
+					"the `?` operator can only be applied to values that implement `Try`, found `{}`",
+					self.infer.describe(&found)
+				),
+			);
+		}
+		self.require(operand, &found, try_.clone())?;
+		self.use_lang(operand, lang::TRY_BRANCH, vec![found.clone()]);
+		let projection = |name: &str| Type::Projection(Box::new(found.clone()), try_.clone(), name.to_string());
+		let residual = self.normalize(&projection("Residual"))?;
+		let output = self.normalize(&projection("Output"))?;
+		let mut from_residual = self.lang_trait(lang::FROM_RESIDUAL);
+		from_residual.generics = vec![residual];
+		let return_type = self.normalize(&self.return_type.clone())?;
+		if !self.may_implement(&return_type, &from_residual) {
+			return fail(
+				span,
+				ErrorCode::TraitNotImplementedError,
+				format!(
+					"the `?` operator can't return early with `{}` from a function that returns `{}`",
+					self.infer.describe(&from_residual.generics[0]),
+					self.infer.describe(&return_type)
+				),
+			);
+		}
+		self.require(span, &return_type, from_residual.clone())?;
+		self.use_lang(span, lang::FROM_RESIDUAL, vec![from_residual.generics[0].clone(), return_type.clone()]);
+		self.tries.push(TryCheck {
+			span: Span::new_ref(span, |_| ()),
+			return_type,
+		});
+		Ok(output)
+	}
+
+	/// Whether the type implements the trait, or could once inference knows more about it
+	fn may_implement(&mut self, type_: &Type, trait_: &TraitRef) -> bool {
+		let mut attempt = self.infer.clone();
+		*type_ == Type::Never
+			|| self.infer.kind(type_) == Some(VarKind::Any)
+			|| self.db.find_impl(&mut attempt, &self.scope.bounds, type_, trait_).is_some()
+	}
+
+	/// Check that the types the `?` operators return early with aren't generic
+	fn check_tries(&mut self) -> Return<()> {
+		for TryCheck { span, return_type } in std::mem::take(&mut self.tries) {
+			let type_ = self.infer.resolve(&return_type);
+			match type_.head() {
+				Some(_) => (),
+				None => {
+					return fail(
+						&span,
+						ErrorCode::UnknownTypeError,
+						format!(
+							"the `?` operator can't return early with the generic type `{}`",
+							self.infer.describe(&type_)
+						),
+					)
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Check an `as` cast, which can only convert between primitives
+	fn cast(&mut self, value: &'a BoxedSpan<Expr>, type_: &Span<crate::ast::Type>) -> Return<Type> {
+		let found = self.sub(value)?;
+		let target = self.db.lower_type(&self.scope, type_)?;
+		let found = self.normalize(&found)?;
+		let valid = match (&found, &target) {
+			(Type::Never, _) => true,
+			(found, Type::Primitive(Primitive::Int(_) | Primitive::Float(_))) if self.infer.is_numeric(found) => true,
+			(Type::Primitive(Primitive::Bool | Primitive::Char), Type::Primitive(Primitive::Int(_))) => true,
+			// Only bytes can be cast to characters, as every one of their values is one
+			(found, Type::Primitive(Primitive::Char)) if self.infer.kind(found) == Some(VarKind::Int) => {
+				self.infer.unify(found, &Type::Primitive(Primitive::Int(IntType::U8)))
+			}
+			(Type::Primitive(Primitive::Int(IntType::U8)), Type::Primitive(Primitive::Char)) => true,
+			(found, target) => self.infer.unify(found, target),
+		};
+		if valid {
+			return Ok(target);
+		}
+		let primitive = self.infer.is_numeric(&found) || matches!(self.infer.shallow(&found), Type::Primitive(_));
+		let found = self.infer.describe(&found);
+		let message = match target {
+			Type::Primitive(_) if primitive => format!("casting `{}` as `{}` is invalid", found, target),
+			_ => format!("non-primitive cast: `{}` as `{}`", found, target),
+		};
+		fail(value, ErrorCode::CastError, message)
+	}
+
+	fn field(&mut self, base: &'a BoxedSpan<Expr>, name: &Span<String>) -> Return<Type> {
+		let found = self.sub(base)?;
+		self.field_of(found, name)
+	}
+
+	fn field_of(&mut self, found: Type, name: &Span<String>) -> Return<Type> {
+		let found = self.normalize(&found)?;
+		match (&found, name.parse::<usize>()) {
+			(Type::Never, _) => Ok(Type::Never),
+			(Type::Tuple(types), Ok(index)) if index < types.len() => Ok(types[index].clone()),
+			(Type::Adt(path, generics), _) => {
+				let id = self.db.type_by_path(path).unwrap();
+				let entry = &self.db.types[id];
+				match entry.field_index(0, name) {
+					Some(index) if entry.kind == TypeKind::Struct => {
+						let map = entry.generic_names().into_iter().zip(generics.iter().cloned()).collect();
+						Ok(self.db.variant_fields(id, 0)?[index].subst(&map))
+					}
+					_ => fail(
+						name,
+						ErrorCode::UnknownNameError,
+						format!("no field `{}` on type `{}`", &**name, self.infer.describe(&found)),
+					),
+				}
+			}
+			_ => fail(
+				name,
+				ErrorCode::UnknownNameError,
+				format!("no field `{}` on type `{}`", &**name, self.infer.describe(&found)),
+			),
+		}
+	}
+
+	/// Check a comparison, which calls a method of a trait in `core::cmp` and is always a `bool`
+	fn comparison<S>(&mut self, span: &Span<S>, item: LangItem, a: &'a BoxedSpan<Expr>, b: &'a BoxedSpan<Expr>) -> Return<Type> {
+		let a = self.sub(a)?;
+		let b = self.sub(b)?;
+		let self_type = self.normalize(&a)?;
+		if self_type == Type::Never {
+			return Ok(Type::Never);
+		}
+		self.numeric_operands(&[a, b.clone()]);
+		let mut trait_ = self.lang_trait(item);
+		trait_.generics = vec![b.clone()];
+		self.require(span, &self_type, trait_)?;
+		self.use_lang(span, item, vec![b, self_type]);
+		Ok(Type::bool())
+	}
+
+	/// Conditions of `if`, `while`, `&&` and `||` have to be a `bool`,
+	/// unless they are a `let`, whose variables are added to the current scope
+	fn condition(&mut self, condition: &'a BoxedSpan<Expr>) -> Return<()> {
+		if let Expr::Let(pattern, value) = &***condition {
+			let found = self.sub(value)?;
+			return self.bind(pattern, found, true);
+		}
+		let found = self.sub(condition)?;
+		self.expect(condition, &Type::bool(), &found)
+	}
+
+	/// Check a block run when its condition holds, which sees the variables the condition binds.
+	/// Also gives the state for when the condition doesn't hold
+	fn branch(&mut self, condition: &'a BoxedSpan<Expr>, block: &'a Block) -> Return<(Type, Flow)> {
+		self.locals.push(HashMap::new());
+		self.condition(condition)?;
+		let otherwise = self.flow.clone();
+		let result = self.block(block)?;
+		self.locals.pop();
+		Ok((result, otherwise))
+	}
+
+	/// The type of two branches that have to agree
+	fn join<S>(&mut self, span: &Span<S>, a: Type, b: Type) -> Return<Type> {
+		if self.infer.shallow(&a) == Type::Never {
+			return Ok(b);
+		}
+		self.expect(span, &a, &b)?;
+		Ok(a)
+	}
+
+	/// Check the body of a loop, returning whether it was broken out of.
+	/// `exit` is the state before the loop for the ones that can end before an iteration, `None` for `loop`.
+	fn loop_body<S>(
+		&mut self,
+		span: &Span<S>,
+		label: &Option<Label>,
+		break_type: Option<Type>,
+		body: &'a Block,
+		exit: Option<Flow>,
+	) -> Return<bool> {
+		self.loops.push(LoopScope {
+			label: label.as_ref().map(|l| l.to_string()),
+			break_type,
+			broken: false,
+			breaks: None,
+			continues: None,
+			start_id: self.local_count,
+			assigned: Vec::new(),
+		});
+		let body = self.block(body)?;
+		self.expect(span, &Type::Empty, &body)?;
+		let scope = self.loops.pop().unwrap();
+		// The state the next iteration starts from, where the locals the last one assigned are still assigned
+		let back = join_flow(self.flow.take(), scope.continues);
+		let again = back.as_ref().map(|back| back.maybe.clone()).unwrap_or_default();
+		for (id, name) in &scope.assigned {
+			if again.contains(id) {
+				return fail(
+					name,
+					ErrorCode::AssignmentError,
+					format!("cannot assign twice to immutable variable `{}`", **name),
+				);
+			}
+		}
+		let ended = match exit {
+			Some(exit) => join_flow(exit, back),
+			None => None,
+		};
+		self.flow = join_flow(ended, scope.breaks);
+		if let Some(flow) = &mut self.flow {
+			flow.maybe.extend(again);
+		}
+		Ok(scope.broken)
+	}
+
+	fn find_loop<S>(&self, span: &Span<S>, label: &Option<Label>, keyword: &str) -> Return<usize> {
+		match label {
+			None if self.loops.is_empty() => fail(
+				span,
+				ErrorCode::LoopControlError,
+				format!("`{}` outside of a loop", keyword),
+			),
+			None => Ok(self.loops.len() - 1),
+			Some(label) => match self
+				.loops
+				.iter()
+				.rposition(|l| l.label.as_deref() == Some(label.as_str()))
+			{
+				Some(index) => Ok(index),
+				None => fail(
+					label,
+					ErrorCode::LoopControlError,
+					format!("use of undeclared label `{}`", label),
+				),
+			},
+		}
+	}
+
+	fn assign(&mut self, place: &'a BoxedSpan<Expr>, value: &'a BoxedSpan<Expr>) -> Return<()> {
+		let found = self.sub(value)?;
+		let expected = match &***place {
+			Expr::Ident(path) if path.len() == 1 && self.is_captured(&path[0]) => {
+				return fail(
+					place,
+					ErrorCode::AssignmentError,
+					format!("cannot assign to `{}`, as it is a captured variable in a closure", path[0]),
+				)
+			}
+			Expr::Ident(path) if path.len() == 1 => match self.local(&path[0]).cloned() {
+				Some(local) if local.mutable || !self.maybe_init(local.id) => {
+					if !local.mutable {
+						for scope in self.loops.iter_mut().filter(|scope| local.id < scope.start_id) {
+							scope.assigned.push((local.id, path[0].clone()));
+						}
+					}
+					self.set_init(local.id);
+					local.type_
+				}
+				Some(_) => {
+					return fail(
+						place,
+						ErrorCode::AssignmentError,
+						format!("cannot assign twice to immutable variable `{}`", path[0]),
+					)
+				}
+				None => {
+					self.sub(place)?;
+					return fail(
+						place,
+						ErrorCode::AssignmentError,
+						"invalid left-hand side of assignment".to_string(),
+					);
+				}
+			},
+			Expr::Field(..) | Expr::Index(..) => {
+				self.check_place(place, place)?;
+				self.place(place)?
+			}
+			_ => {
+				return fail(
+					place,
+					ErrorCode::AssignmentError,
+					"invalid left-hand side of assignment".to_string(),
+				)
+			}
+		};
+		self.expect(value, &expected, &found)
+	}
+
+	/// Check that an element or a field of a variable can be assigned to: the variable is given a copy of its value
+	/// with it replaced, so it has to be mutable
+	fn check_place(&self, place: &BoxedSpan<Expr>, part: &BoxedSpan<Expr>) -> Return<()> {
+		let name = match &***part {
+			Expr::Field(base, _) | Expr::Index(base, _) => return self.check_place(place, base),
+			Expr::Ident(path) if path.len() == 1 && self.local(&path[0]).is_some() => &path[0],
+			_ => {
+				return fail(
+					place,
+					ErrorCode::AssignmentError,
+					"invalid left-hand side of assignment".to_string(),
+				)
+			}
+		};
+		if self.is_captured(name) {
+			return fail(
+				place,
+				ErrorCode::AssignmentError,
+				format!(
+					"cannot assign to `{}`, as `{}` is a captured variable in a closure",
+					describe_place(place),
+					&**name
+				),
+			);
+		}
+		if self.local(name).unwrap().mutable {
+			return Ok(());
+		}
+		fail(
+			place,
+			ErrorCode::AssignmentError,
+			format!(
+				"cannot assign to `{}`, as `{}` is not declared as mutable",
+				describe_place(place),
+				&**name
+			),
+		)
+	}
+
+	/// Check a place that is assigned to, giving back its type. Each element it's in is replaced with
+	/// `IndexSet::index_set`, which is the second use of the place after `Index::index`
+	fn place(&mut self, place: &'a BoxedSpan<Expr>) -> Return<Type> {
+		let type_ = match &***place {
+			Expr::Index(base, index) => {
+				let base_type = self.place(base)?;
+				let index_type = self.sub(index)?;
+				self.current.push(key(&***place));
+				let element = self.operator(place, lang::INDEX, vec![base_type.clone(), index_type.clone()])?;
+				let self_type = self.normalize(&base_type)?;
+				if self_type != Type::Never {
+					let mut trait_ = self.lang_trait(lang::INDEX_SET);
+					trait_.generics = vec![index_type, element.clone()];
+					self.require(place, &self_type, trait_.clone())?;
+					let mut generics = trait_.generics;
+					generics.push(self_type);
+					self.use_lang(place, lang::INDEX_SET, generics);
+				}
+				self.current.pop();
+				element
+			}
+			Expr::Field(base, name) => {
+				let base_type = self.place(base)?;
+				self.field_of(base_type, name)?
+			}
+			_ => return self.sub(place),
+		};
+		self.types.exprs.insert(key(&***place), type_.clone());
+		Ok(type_)
+	}
+
+	/// Whether a local may have been assigned on some path to the current point
+	fn maybe_init(&self, id: usize) -> bool {
+		self.flow.as_ref().map(|flow| flow.maybe.contains(&id)).unwrap_or(false)
+	}
+
+	fn ident<S>(&mut self, span: &Span<S>, path: &[Span<String>]) -> Return<Type> {
+		if let [name] = path {
+			if let Some(local) = self.local(name).cloned() {
+				if let Some(flow) = &self.flow {
+					if !flow.init.contains(&local.id) {
+						let state = if flow.maybe.contains(&local.id) {
+							"is possibly-uninitialized"
+						} else {
+							"isn't initialized"
+						};
+						return fail(
+							span,
+							ErrorCode::UninitializedError,
+							format!("used binding `{}` {}", name, state),
+						);
+					}
+				}
+				self.capture(name);
+				return Ok(local.type_);
+			}
+			if self.allow_builtins && name.starts_with("BUILTIN_") {
+				// Builtins take the arguments of the function they are in
+				return Ok(self.return_type.clone());
+			}
+		}
+		let item = self.db.resolve_path(&self.scope.module, path)?;
+		// Unit structs and variants are values, the others need their fields
+		if let Some((id, variant)) = self.db.variant_of(&item) {
+			if let Fields::Unit = self.db.types[id].variants[variant].1 {
+				return self.construct(span, id, variant, &[]);
+			}
+		}
+		if let Item::Const(id) = item {
+			return self.db.const_type(id);
+		}
+		// Functions are values too, of a function type
+		if let Item::Fn(id) = item {
+			let (_, sig) = self.db.fn_signature(id)?;
+			let (instantiated, map) = sig.instantiate(&mut self.infer);
+			self.use_fn(span, Callee::Fn(id), &sig.generics, &map);
+			let sig = instantiated;
+			for (type_, trait_) in sig.bounds {
+				self.require(span, &type_, trait_)?;
+			}
+			let ret = self.normalize(&sig.ret)?;
+			return Ok(Type::Fn(sig.args, Box::new(ret)));
+		}
+		fail(
+			span,
+			ErrorCode::UnknownNameError,
+			format!("expected value, found {} `{}`", self.item_kind(&item), join_path(path)),
+		)
+	}
+
+	/// What an item is, to tell it in errors
+	fn item_kind(&self, item: &Item) -> &'static str {
+		match item {
+			Item::Fn(_) => "function",
+			Item::Trait(_) => "trait",
+			Item::Module(_) => "module",
+			Item::Type(_) if self.db.variant_of(item).is_none() => "enum",
+			Item::Type(id) => self.db.types[*id].variant_kind(0),
+			Item::Variant(id, variant) => self.db.types[*id].variant_kind(*variant),
+			Item::Const(id) => match self.db.consts[*id].def.kind {
+				ConstKind::Const => "constant",
+				ConstKind::Static => "static",
+			},
+		}
+	}
+
+	fn call<S>(&mut self, span: &Span<S>, callee: &'a BoxedSpan<Expr>, args: &'a [BoxedSpan<Expr>]) -> Return<Type> {
+		let id = match &***callee {
+			Expr::Ident(path) if !(path.len() == 1 && self.local(&path[0]).is_some()) => {
+				let item = self.db.resolve_path(&self.scope.module, path)?;
+				if let Some((id, variant)) = self.db.variant_of(&item) {
+					return self.tuple_constructor(span, callee, id, variant, args);
+				}
+				match item {
+					Item::Fn(id) => id,
+					_ => {
+						return fail(
+							callee,
+							ErrorCode::NotCallableError,
+							format!("`{}` is not a function", join_path(path)),
+						)
+					}
+				}
+			}
+			_ => {
+				let found = self.sub(callee)?;
+				let found = self.normalize(&found)?;
+				let (params, ret) = match self.callable(&found) {
+					Some(signature) => signature,
+					None if found == Type::Never => return Ok(Type::Never),
+					None if self.infer.kind(&found) == Some(VarKind::Any) => {
+						return fail(
+							callee,
+							ErrorCode::UnknownTypeError,
+							"type annotations needed".to_string(),
+						)
+					}
+					None => {
+						return fail(
+							callee,
+							ErrorCode::NotCallableError,
+							format!("expected function, found `{}`", self.infer.describe(&found)),
+						)
+					}
+				};
+				let sig = FnSig {
+					generics: Vec::new(),
+					args: params,
+					ret,
+					bounds: Vec::new(),
+				};
+				return self.check_call(span, sig, None, args);
+			}
+		};
+		let (_, sig) = self.db.fn_signature(id)?;
+		let (instantiated, map) = sig.instantiate(&mut self.infer);
+		self.use_fn(span, Callee::Fn(id), &sig.generics, &map);
+		self.check_call(span, instantiated, None, args)
+	}
+
+	/// Check a call like `a.f(b)`, to a method of an inherent impl of the type of `a` or else of a trait
+	fn method_call<S>(
+		&mut self,
+		span: &Span<S>,
+		expr: usize,
+		receiver: &'a BoxedSpan<Expr>,
+		name: &Span<String>,
+		args: &'a [BoxedSpan<Expr>],
+	) -> Return<Type> {
+		let found = self.sub(receiver)?;
+		let self_type = self.normalize(&found)?;
+		match self.infer.kind(&self_type) {
+			_ if self_type == Type::Never => return Ok(Type::Never),
+			Some(VarKind::Any) => {
+				return fail(
+					receiver,
+					ErrorCode::UnknownTypeError,
+					"type annotations needed".to_string(),
+				)
+			}
+			_ => (),
+		}
+		if let Some(id) = self.db.inherent_method(&self.infer, &self_type, name) {
+			if !self.db.is_method(id) {
+				return fail(
+					name,
+					ErrorCode::NotCallableError,
+					format!("`{}` is an associated function, not a method", &**name),
+				);
+			}
+			self.types.methods.insert(expr, Method::Inherent);
+			let (_, sig) = self.db.fn_signature(id)?;
+			let (instantiated, map) = sig.instantiate(&mut self.infer);
+			self.use_fn(span, Callee::Fn(id), &sig.generics, &map);
+			return self.check_call(span, instantiated, Some((receiver, self_type)), args);
+		}
+		let mut candidates = Vec::new();
+		for trait_ in self.db.traits_with_method(name) {
+			let mut attempt = self.infer.clone();
+			let (sig, _) = self.db.trait_method_signature(trait_, name)?.unwrap().instantiate(&mut attempt);
+			// The first bound of a trait method is the one of `Self` on the trait
+			let trait_ref = sig.bounds[0].1.clone();
+			if attempt.unify(&sig.args[0], &self_type)
+				&& self.db.find_impl(&mut attempt, &self.scope.bounds, &self_type, &trait_ref).is_some()
+			{
+				candidates.push(trait_);
+			}
+		}
+		let trait_ = match candidates.as_slice() {
+			[trait_] => *trait_,
+			[] => {
+				return fail(
+					name,
+					ErrorCode::UnknownNameError,
+					format!(
+						"no method named `{}` found for type `{}`",
+						&**name,
+						self.infer.describe(&self_type)
+					),
+				)
+			}
+			_ => {
+				return fail(
+					name,
+					ErrorCode::UnknownNameError,
+					format!(
+						"multiple applicable methods named `{}` found for type `{}`",
+						&**name,
+						self.infer.describe(&self_type)
+					),
+				)
+			}
+		};
+		self.types.methods.insert(expr, Method::Trait(trait_));
+		let sig = self.db.trait_method_signature(trait_, name)?.unwrap();
+		let (instantiated, map) = sig.instantiate(&mut self.infer);
+		self.use_fn(span, Callee::Method(trait_, name.to_string()), &sig.generics, &map);
+		self.check_call(span, instantiated, Some((receiver, self_type)), args)
+	}
+
+	/// Check the arguments of a call against an instantiated signature.
+	/// For method calls, the receiver was already checked and has the given type.
+	fn check_call<S>(
+		&mut self,
+		span: &Span<S>,
+		sig: FnSig,
+		receiver: Option<(&BoxedSpan<Expr>, Type)>,
+		args: &'a [BoxedSpan<Expr>],
+	) -> Return<Type> {
+		let (kind, params) = match &receiver {
+			Some(_) => ("method", &sig.args[1..]),
+			None => ("function", &sig.args[..]),
+		};
+		if params.len() != args.len() {
+			return fail(
+				span,
+				ErrorCode::ArgumentCountError,
+				format!(
+					"this {} takes {} arguments but {} were supplied",
+					kind,
+					params.len(),
+					args.len()
+				),
+			);
+		}
+		if let Some((receiver, found)) = &receiver {
+			self.expect(*receiver, &sig.args[0], found)?;
+		}
+		for (arg, expected) in args.iter().zip(params) {
+			// A closure given for a generic bound by a `Fn` trait has the function type of the bound
+			let callable = sig
+				.bounds
+				.iter()
+				.filter(|(t, _)| self.infer.shallow(t) == self.infer.shallow(expected))
+				.find_map(|(_, trait_)| fn_type(trait_));
+			let callable = callable.map(|t| self.normalize(&t)).transpose()?;
+			let found = self.sub_expecting(arg, callable.as_ref().unwrap_or(expected))?;
+			self.expect(arg, expected, &found)?;
+		}
+		for (type_, trait_) in sig.bounds {
+			self.require(span, &type_, trait_)?;
+		}
+		self.normalize(&sig.ret)
+	}
+}
+
+impl<'a, 'b> FnChecker<'a, 'b> {
+	/// The arguments and the result of a value that can be called, if it can be
+	fn callable(&self, type_: &Type) -> Option<(Vec<Type>, Type)> {
+		let bound = |(bounded, trait_): &(Type, _)| match fn_type(trait_) {
+			Some(t) if bounded == type_ => Some(t),
+			_ => None,
+		};
+		match self.infer.shallow(type_) {
+			Type::Fn(args, ret) => Some((args, *ret)),
+			_ => match self.scope.bounds.iter().find_map(bound) {
+				Some(Type::Fn(args, ret)) => Some((args, *ret)),
+				_ => None,
+			},
+		}
+	}
+
+	/// Check a closure. The type it's expected to have, if known, gives the types of the arguments that aren't written
+	fn closure(
+		&mut self,
+		expr: usize,
+		params: &'a [(Span<Pattern>, Option<Span<ast::Type>>)],
+		return_type: &Option<Span<ast::Type>>,
+		body: &'a BoxedSpan<Expr>,
+		expected: Option<&Type>,
+	) -> Return<Type> {
+		let mut args = Vec::new();
+		for (_, type_) in params {
+			args.push(match type_ {
+				Some(t) => self.db.lower_type(&self.scope, t)?,
+				None => self.infer.new_var(),
+			});
+		}
+		let ret = match return_type {
+			Some(t) => self.db.lower_type(&self.scope, t)?,
+			None => self.infer.new_var(),
+		};
+		let type_ = Type::Fn(args.clone(), Box::new(ret.clone()));
+		if let Some(expected) = expected {
+			let mut attempt = self.infer.clone();
+			if attempt.unify(expected, &type_) {
+				self.infer = attempt;
+			}
+		}
+		self.closures.push(ClosureScope {
+			depth: self.locals.len(),
+			captures: Vec::new(),
+		});
+		self.locals.push(HashMap::new());
+		// `break` and `continue` can't leave the closure, and `return` and `?` leave the closure only
+		let loops = std::mem::take(&mut self.loops);
+		let return_type = std::mem::replace(&mut self.return_type, ret.clone());
+		let flow = self.flow.clone();
+		for ((pattern, _), arg) in params.iter().zip(args) {
+			self.bind(pattern, arg.clone(), true)?;
+			self.irrefutable(pattern, arg, "closure argument");
+		}
+		let found = self.sub(body)?;
+		self.expect(body, &ret, &found)?;
+		self.flow = flow;
+		self.return_type = return_type;
+		self.loops = loops;
+		self.locals.pop();
+		let closure = self.closures.pop().unwrap();
+		self.types.captures.insert(expr, closure.captures);
+		Ok(type_)
+	}
+
+	/// Check a call to a tuple struct or a tuple variant, like `Some(1)`
+	fn tuple_constructor<S>(
+		&mut self,
+		span: &Span<S>,
+		callee: &'a BoxedSpan<Expr>,
+		id: TypeId,
+		variant: usize,
+		args: &'a [BoxedSpan<Expr>],
+	) -> Return<Type> {
+		let entry = &self.db.types[id];
+		let len = match entry.variants[variant].1 {
+			Fields::Tuple(types) => types.len(),
+			_ => {
+				return fail(
+					callee,
+					ErrorCode::NotCallableError,
+					format!(
+						"expected function, found {} `{}`",
+						entry.variant_kind(variant),
+						entry.variant_name(variant)
+					),
+				)
+			}
+		};
+		if len != args.len() {
+			return fail(
+				span,
+				ErrorCode::ArgumentCountError,
+				format!(
+					"this {} takes {} arguments but {} were supplied",
+					entry.variant_kind(variant),
+					len,
+					args.len()
+				),
+			);
+		}
+		let fields: Vec<(usize, &'a BoxedSpan<Expr>)> = args.iter().enumerate().collect();
+		self.construct(span, id, variant, &fields)
+	}
+
+	/// Check a struct literal, like `Point { x: 1, y: 2 }`, which gives every named field a value
+	fn struct_literal<S>(
+		&mut self,
+		span: &Span<S>,
+		path: &[Span<String>],
+		fields: &'a [(Span<String>, BoxedSpan<Expr>)],
+	) -> Return<Type> {
+		let item = self.db.resolve_path(&self.scope.module, path)?;
+		let (id, variant) = match self.db.variant_of(&item) {
+			Some(variant) => variant,
+			None => {
+				return fail(
+					&path[path.len() - 1],
+					ErrorCode::UnknownNameError,
+					format!("expected struct or variant, found `{}`", join_path(path)),
+				)
+			}
+		};
+		let entry = &self.db.types[id];
+		let names: Vec<&str> = match entry.variants[variant].1 {
+			Fields::Named(fields) => fields.iter().map(|(name, _)| name.as_str()).collect(),
+			_ => Vec::new(),
+		};
+		let mut given = vec![false; names.len()];
+		let mut values = Vec::new();
+		for (name, value) in fields {
+			let index = match entry.field_index(variant, name) {
+				Some(index) if index < given.len() => index,
+				_ => {
+					return fail(
+						name,
+						ErrorCode::UnknownNameError,
+						format!(
+							"{} `{}` has no field named `{}`",
+							entry.variant_kind(variant),
+							entry.variant_name(variant),
+							&**name
+						),
+					)
+				}
+			};
+			if given[index] {
+				return fail(
+					name,
+					ErrorCode::ArgumentCountError,
+					format!("field `{}` specified more than once", &**name),
+				);
+			}
+			given[index] = true;
+			values.push((index, value));
+		}
+		let missing: Vec<String> = names
+			.iter()
+			.zip(given)
+			.filter(|(_, given)| !given)
+			.map(|(name, _)| format!("`{}`", name))
+			.collect();
+		if !missing.is_empty() {
+			return fail(
+				span,
+				ErrorCode::ArgumentCountError,
+				format!(
+					"missing field{} {} in initializer of `{}`",
+					if missing.len() == 1 { "" } else { "s" },
+					missing.join(", "),
+					entry.variant_name(variant)
+				),
+			);
+		}
+		self.construct(span, id, variant, &values)
+	}
+
+	/// Check the values given to the fields of a variant, by their position
+	fn construct<S>(
+		&mut self,
+		span: &Span<S>,
+		id: TypeId,
+		variant: usize,
+		fields: &[(usize, &'a BoxedSpan<Expr>)],
+	) -> Return<Type> {
+		let path = &self.db.types[id].path;
+		if lang::BUILTIN_TYPES.iter().any(|builtin| path.iter().eq(*builtin)) {
+			return fail(
+				span,
+				ErrorCode::PrivacyError,
+				format!(
+					"`{}` can only be created with `{}::new`",
+					path.join("::"),
+					path.last().unwrap()
+				),
+			);
+		}
+		let (sig, _) = self.db.variant_signature(id, variant)?.instantiate(&mut self.infer);
+		for (index, value) in fields {
+			let found = self.sub(value)?;
+			self.expect(value, &sig.args[*index], &found)?;
+		}
+		for (type_, trait_) in sig.bounds {
+			self.require(span, &type_, trait_)?;
+		}
+		Ok(sig.ret)
+	}
+}
+
+/// The function type implementing `Fn`, if that's the trait
+fn fn_type(trait_: &TraitRef) -> Option<Type> {
+	if !is_fn_trait(&trait_.path) {
+		return None;
+	}
+	let args = match trait_.generics.first()? {
+		Type::Empty => Vec::new(),
+		Type::Tuple(types) => types.clone(),
+		_ => return None,
+	};
+	Some(Type::Fn(args, Box::new(trait_.generics.get(1)?.clone())))
+}
+
+fn join_path(path: &[Span<String>]) -> String {
+	path.iter()
+		.map(|s| s.as_str())
+		.collect::<Vec<&str>>()
+		.join("::")
+}
+
+/// How a place is shown in errors, with `_` for its indices
+fn describe_place(place: &Expr) -> String {
+	match place {
+		Expr::Field(base, name) => format!("{}.{}", describe_place(base), &**name),
+		Expr::Index(base, _) => format!("{}[_]", describe_place(base)),
+		Expr::Ident(path) => join_path(path),
+		_ => "_".to_string(),
+	}
+}
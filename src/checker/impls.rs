@@ -0,0 +1,307 @@
+//! Checks of trait impls: against the trait they implement, and against each other for coherence,
+//! so that a type implements a trait in at most one way
+
+use hashbrown::HashMap;
+
+use crate::{
+	ast::{FnArg, Generics, TraitDef},
+	error::{ErrorCode, Return},
+	span::Span,
+};
+
+use super::{
+	db::{generics_unify, takes_self, FnId, FnParent, ImplId, TraitId, TypeDB},
+	infer::InferCtx,
+	types::Type,
+};
+
+/// Report an error, which stops the checker
+fn fail<S, T>(span: &Span<S>, code: ErrorCode, message: String) -> Return<T> {
+	span.as_error(code, message).display()?;
+	unreachable!("errors always stop the checker")
+}
+
+/// Check that a trait impl defines the items of the trait that have no default and nothing else,
+/// and that its methods have the signatures the trait declares
+pub fn check_trait_impl(db: &TypeDB, id: ImplId) -> Return<()> {
+	let impl_ = &db.impls[id];
+	let (trait_id, trait_span) = match impl_.trait_ {
+		Some(trait_) => trait_,
+		None => return Ok(()),
+	};
+	let def = db.traits[trait_id].def;
+	for (type_, _) in impl_.types {
+		if !def.types.iter().any(|(t, _)| *t.name == *type_.name) {
+			return fail(
+				&type_.name,
+				ErrorCode::ImplError,
+				format!("type `{}` is not a member of trait `{}`", type_.name, def.name),
+			);
+		}
+	}
+	// In the order they are written, for the errors to be reported in that order too
+	let fns: Vec<FnId> = (0..db.fns.len())
+		.filter(|f| matches!(db.fns[*f].parent, FnParent::Impl(i) if i == id))
+		.collect();
+	for &fn_id in &fns {
+		let name = &db.fns[fn_id].def.name;
+		if trait_fn(def, name).is_none() {
+			return fail(
+				name,
+				ErrorCode::ImplError,
+				format!("method `{}` is not a member of trait `{}`", name, def.name),
+			);
+		}
+	}
+	let missing: Vec<String> = def
+		.types
+		.iter()
+		.map(|(t, _)| &t.name)
+		.filter(|name| !impl_.types.iter().any(|(t, _)| *t.name == ***name))
+		.chain(
+			def.fn_signatures
+				.iter()
+				.map(|f| &f.name)
+				.filter(|name| !impl_.fns.contains_key(name.as_str())),
+		)
+		.map(|name| format!("`{}`", name))
+		.collect();
+	if !missing.is_empty() {
+		return fail(
+			trait_span,
+			ErrorCode::ImplError,
+			format!("not all trait items implemented, missing: {}", missing.join(", ")),
+		);
+	}
+	for fn_id in fns {
+		check_method(db, id, trait_id, fn_id)?;
+	}
+	Ok(())
+}
+
+/// The generics and the arguments of the method of the trait called `name`, be it a default method or not
+fn trait_fn<'a>(def: &'a TraitDef, name: &str) -> Option<(&'a Option<Generics>, &'a [FnArg])> {
+	def.fn_signatures
+		.iter()
+		.find(|f| *f.name == name)
+		.map(|f| (&f.generics, &f.args[..]))
+		.or_else(|| {
+			def.fn_defs
+				.iter()
+				.find(|f| *f.name == name)
+				.map(|f| (&f.generics, &f.args[..]))
+		})
+}
+
+/// Check a method of the impl against its declaration in the trait.
+/// The types of the declaration are seen from the impl, with `Self` and the generics of the trait
+/// replaced by what the impl gives them, and the generics of the method by the ones of the impl method
+fn check_method(db: &TypeDB, impl_id: ImplId, trait_id: TraitId, fn_id: FnId) -> Return<()> {
+	let impl_ = &db.impls[impl_id];
+	let trait_ = &db.traits[trait_id];
+	let def = db.fns[fn_id].def;
+	let name = &def.name;
+	let (trait_generics, trait_args) = trait_fn(trait_.def, name).unwrap();
+	match (takes_self(trait_args), takes_self(&def.args)) {
+		(true, false) => {
+			return fail(
+				name,
+				ErrorCode::ImplError,
+				format!(
+					"method `{}` has a `self` declaration in the trait, but not in the impl",
+					name
+				),
+			)
+		}
+		(false, true) => {
+			return fail(
+				name,
+				ErrorCode::ImplError,
+				format!(
+					"method `{}` has a `self` declaration in the impl, but not in the trait",
+					name
+				),
+			)
+		}
+		_ => (),
+	}
+	if trait_args.len() != def.args.len() {
+		return fail(
+			name,
+			ErrorCode::ImplError,
+			format!(
+				"method `{}` has {} parameter{} but the declaration in trait `{}` has {}",
+				name,
+				def.args.len(),
+				if def.args.len() == 1 { "" } else { "s" },
+				trait_.def.name,
+				trait_args.len()
+			),
+		);
+	}
+	let trait_generics: Vec<&Span<String>> = trait_generics.iter().flatten().collect();
+	let impl_generics: Vec<&Span<String>> = def.generics.iter().flatten().collect();
+	if trait_generics.len() != impl_generics.len() {
+		return fail(
+			name,
+			ErrorCode::ImplError,
+			format!(
+				"method `{}` has {} type parameter{} but its trait declaration has {}",
+				name,
+				impl_generics.len(),
+				if impl_generics.len() == 1 { "" } else { "s" },
+				trait_generics.len()
+			),
+		);
+	}
+	let trait_ref = impl_.trait_ref.as_ref().expect("trait impls have their trait lowered");
+	let mut map: HashMap<String, Type> = trait_
+		.def
+		.generics
+		.iter()
+		.flatten()
+		.map(|g| g.to_string())
+		.zip(trait_ref.generics.iter().cloned())
+		.collect();
+	map.insert("Self".to_string(), impl_.self_type.clone());
+	for (t, i) in trait_generics.iter().zip(&impl_generics) {
+		map.insert(t.to_string(), Type::Generic(i.to_string()));
+	}
+	let expected = db.trait_method_signature(trait_id, name)?.unwrap();
+	let (scope, found) = db.fn_signature(fn_id)?;
+	let mut infer = InferCtx::default();
+	let mut normalize = |type_: &Type| db.normalize(&mut infer, &scope.bounds, type_);
+	let expected_types = expected.args.iter().chain([&expected.ret]);
+	let found_types = found.args.iter().chain([&found.ret]);
+	let spans = def.args.iter().map(|(_, t)| t).chain([&def.return_type]);
+	for ((expected, found), span) in expected_types.zip(found_types).zip(spans) {
+		let expected = normalize(&expected.subst(&map))?;
+		let found = normalize(found)?;
+		if expected != found {
+			return fail(
+				span,
+				ErrorCode::ImplError,
+				format!(
+					"method `{}` has an incompatible type for trait: expected `{}`, found `{}`",
+					name, expected, found
+				),
+			);
+		}
+	}
+	// The bounds of the impl method can't ask for more than the ones of the trait method
+	let mut assumed = db.impl_scope(impl_id)?.bounds;
+	assumed.extend(expected.bounds.iter().map(|(t, tr)| (t.subst(&map), tr.subst(&map))));
+	for (type_, trait_) in &found.bounds {
+		if db.find_impl(&mut infer, &assumed, type_, trait_).is_none() {
+			return fail(
+				name,
+				ErrorCode::ImplError,
+				format!(
+					"impl method `{}` has stricter requirements than trait: `{}: {}`",
+					name, type_, trait_
+				),
+			);
+		}
+	}
+	Ok(())
+}
+
+/// Check the orphan rule for every trait impl, then that no two impls of a trait overlap
+pub fn check_coherence(db: &TypeDB) -> Return<()> {
+	for id in 0..db.impls.len() {
+		check_orphan(db, id)?;
+	}
+	for b in 0..db.impls.len() {
+		for a in 0..b {
+			if overlap(db, a, b)? {
+				let (_, trait_) = db.impls[b].trait_.unwrap();
+				let (_, first) = db.impls[a].trait_.unwrap();
+				trait_
+					.as_error(
+						ErrorCode::CoherenceError,
+						format!(
+							"conflicting implementations of trait `{}` for type `{}`",
+							db.impls[b].trait_ref.as_ref().unwrap(),
+							db.impls[b].self_type
+						),
+					)
+					.with_note(first, "first implementation here")
+					.display()?;
+			}
+		}
+	}
+	Ok(())
+}
+
+/// A trait can only be implemented by the crate defining it, or by one defining the type or a generic of the trait.
+/// The types the language has itself, like the primitives, belong to `core`
+fn check_orphan(db: &TypeDB, id: ImplId) -> Return<()> {
+	let impl_ = &db.impls[id];
+	let (trait_id, _) = match impl_.trait_ {
+		Some(trait_) => trait_,
+		None => return Ok(()),
+	};
+	let crate_ = &impl_.module[0];
+	let local = |type_: &Type| match type_ {
+		Type::Adt(path, _) => path[0] == *crate_,
+		Type::Generic(_) | Type::Projection(..) | Type::Var(_) => false,
+		_ => crate_ == "core",
+	};
+	let trait_ref = impl_.trait_ref.as_ref().expect("trait impls have their trait lowered");
+	if db.traits[trait_id].path[0] == *crate_
+		|| local(&impl_.self_type)
+		|| trait_ref.generics.iter().any(local)
+	{
+		return Ok(());
+	}
+	fail(
+		impl_.type_,
+		ErrorCode::CoherenceError,
+		format!(
+			"only traits defined in the current crate can be implemented for types defined outside of it, \
+			 but `{}` and `{}` are both foreign",
+			trait_ref, impl_.self_type
+		),
+	)
+}
+
+/// Whether some type could be given to both impls of the same trait: their types unify,
+/// and none of their where clauses is known not to hold for it
+fn overlap(db: &TypeDB, a: ImplId, b: ImplId) -> Return<bool> {
+	let (trait_a, trait_b) = match (&db.impls[a].trait_ref, &db.impls[b].trait_ref) {
+		(Some(x), Some(y)) if x.path == y.path => (x, y),
+		_ => return Ok(false),
+	};
+	let mut infer = InferCtx::default();
+	let mut fresh = |id: ImplId| -> HashMap<String, Type> {
+		db.impls[id]
+			.generic_names()
+			.into_iter()
+			.map(|g| (g, infer.new_var()))
+			.collect()
+	};
+	let (map_a, map_b) = (fresh(a), fresh(b));
+	if !infer.unify(&db.impls[a].self_type.subst(&map_a), &db.impls[b].self_type.subst(&map_b))
+		|| !generics_unify(&mut infer, &trait_a.subst(&map_a).generics, &trait_b.subst(&map_b).generics)
+	{
+		return Ok(false);
+	}
+	for (id, map) in [(a, map_a), (b, map_b)] {
+		for (type_, trait_) in db.impl_scope(id)?.bounds {
+			let mut trait_ = trait_.subst(&map);
+			for g in trait_.generics.iter_mut() {
+				*g = db.normalize(&mut infer, &[], g)?;
+			}
+			let type_ = db.normalize(&mut infer, &[], &type_.subst(&map))?;
+			// A bound on a type that is still unknown could hold for some type, and searching for one could
+			// go through every impl of the trait
+			if infer.resolve(&type_).has_vars() {
+				continue;
+			}
+			if db.find_impl(&mut infer, &[], &type_, &trait_).is_none() {
+				return Ok(false);
+			}
+		}
+	}
+	Ok(true)
+}
@@ -0,0 +1,186 @@
+use super::types::{write_fn, FloatType, IntType, Primitive, Type};
+
+/// What an inference variable can become
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+	Any,
+	/// The type of an integer literal, any integer type
+	Int,
+	/// The type of a float literal, any float type
+	Float,
+}
+
+/// Keeps track of the inference variables of a function body
+#[derive(Debug, Default, Clone)]
+pub struct InferCtx {
+	vars: Vec<(Option<Type>, VarKind)>,
+}
+
+impl InferCtx {
+	pub fn new_var(&mut self) -> Type {
+		self.new_kind_var(VarKind::Any)
+	}
+
+	pub fn new_kind_var(&mut self, kind: VarKind) -> Type {
+		self.vars.push((None, kind));
+		Type::Var(self.vars.len() - 1)
+	}
+
+	/// The kind of the variable the type is, if it's an unbound one
+	pub fn kind(&self, t: &Type) -> Option<VarKind> {
+		match self.shallow(t) {
+			Type::Var(v) => Some(self.vars[v].1),
+			_ => None,
+		}
+	}
+
+	/// Whether the type is a number or will be one
+	pub fn is_numeric(&self, t: &Type) -> bool {
+		match self.shallow(t) {
+			Type::Primitive(Primitive::Int(_)) | Type::Primitive(Primitive::Float(_)) => true,
+			Type::Var(v) => self.vars[v].1 != VarKind::Any,
+			_ => false,
+		}
+	}
+
+	/// Give the literals whose type wasn't constrained their default type
+	pub fn default_numeric_vars(&mut self) {
+		for v in 0..self.vars.len() {
+			let default = match self.vars[v] {
+				(None, VarKind::Int) => Type::Primitive(Primitive::Int(IntType::I64)),
+				(None, VarKind::Float) => Type::Primitive(Primitive::Float(FloatType::F64)),
+				_ => continue,
+			};
+			self.vars[v].0 = Some(default);
+		}
+	}
+
+	/// A readable name for the type, with the variables that have to be numbers shown as such
+	pub fn describe(&self, t: &Type) -> String {
+		match self.shallow(t) {
+			Type::Var(v) => match self.vars[v].1 {
+				VarKind::Any => "_",
+				VarKind::Int => "{integer}",
+				VarKind::Float => "{float}",
+			}
+			.to_string(),
+			Type::Tuple(types) if types.len() == 1 => format!("({},)", self.describe(&types[0])),
+			Type::Tuple(types) => format!(
+				"({})",
+				types
+					.iter()
+					.map(|t| self.describe(t))
+					.collect::<Vec<String>>()
+					.join(", ")
+			),
+			Type::Array(element, len) => format!("[{}; {}]", self.describe(&element), self.describe(&len)),
+			Type::Adt(path, generics) if !generics.is_empty() => format!(
+				"{}<{}>",
+				path.last().unwrap(),
+				generics
+					.iter()
+					.map(|t| self.describe(t))
+					.collect::<Vec<String>>()
+					.join(", ")
+			),
+			Type::Fn(args, ret) => {
+				let mut s = String::new();
+				write_fn(&mut s, args.iter().map(|t| self.describe(t)), self.describe(&ret)).unwrap();
+				s
+			}
+			t => self.resolve(&t).to_string(),
+		}
+	}
+
+	/// Follow the variable bindings until a type which isn't a bound variable is found
+	pub fn shallow(&self, t: &Type) -> Type {
+		let mut t = t;
+		while let Type::Var(v) = t {
+			match &self.vars[*v].0 {
+				Some(bound) => t = bound,
+				None => break,
+			}
+		}
+		t.clone()
+	}
+
+	/// Replace all the bound variables inside the type
+	pub fn resolve(&self, t: &Type) -> Type {
+		match self.shallow(t) {
+			Type::Projection(self_type, trait_, name) => {
+				let mut trait_ = trait_;
+				for g in trait_.generics.iter_mut() {
+					*g = self.resolve(g);
+				}
+				Type::Projection(Box::new(self.resolve(&self_type)), trait_, name)
+			}
+			Type::Tuple(types) => Type::Tuple(types.iter().map(|t| self.resolve(t)).collect()),
+			Type::Array(element, len) => Type::Array(Box::new(self.resolve(&element)), Box::new(self.resolve(&len))),
+			Type::Adt(path, generics) => Type::Adt(path, generics.iter().map(|t| self.resolve(t)).collect()),
+			Type::Fn(args, ret) => Type::Fn(args.iter().map(|t| self.resolve(t)).collect(), Box::new(self.resolve(&ret))),
+			t => t,
+		}
+	}
+
+	/// Make both types equal, binding variables if needed.
+	/// `!` unifies with everything, as it never produces a value.
+	pub fn unify(&mut self, a: &Type, b: &Type) -> bool {
+		let (a, b) = (self.shallow(a), self.shallow(b));
+		match (&a, &b) {
+			(Type::Never, _) | (_, Type::Never) => true,
+			(Type::Var(x), Type::Var(y)) if x == y => true,
+			(Type::Var(x), Type::Var(y)) => {
+				// The variable that can become anything takes the kind of the other one
+				match (self.vars[*x].1, self.vars[*y].1) {
+					(VarKind::Any, _) => self.vars[*x].0 = Some(b.clone()),
+					(_, VarKind::Any) => self.vars[*y].0 = Some(a.clone()),
+					(x_kind, y_kind) if x_kind == y_kind => self.vars[*x].0 = Some(b.clone()),
+					_ => return false,
+				}
+				true
+			}
+			(Type::Var(v), t) | (t, Type::Var(v)) => {
+				let fits = matches!(
+					(self.vars[*v].1, t),
+					(VarKind::Any, _)
+						| (VarKind::Int, Type::Primitive(Primitive::Int(_)))
+						| (VarKind::Float, Type::Primitive(Primitive::Float(_)))
+				);
+				if !fits || self.occurs(*v, t) {
+					false
+				} else {
+					self.vars[*v].0 = Some(t.clone());
+					true
+				}
+			}
+			(Type::Projection(sa, ta, na), Type::Projection(sb, tb, nb)) => {
+				na == nb
+					&& ta.path == tb.path && self.unify(sa, sb)
+					&& ta.generics.len() == tb.generics.len()
+					&& ta
+						.generics
+						.iter()
+						.zip(tb.generics.iter())
+						.all(|(x, y)| self.unify(x, y))
+			}
+			(Type::Tuple(ta), Type::Tuple(tb)) => {
+				ta.len() == tb.len() && ta.iter().zip(tb.iter()).all(|(x, y)| self.unify(x, y))
+			}
+			(Type::Array(ea, la), Type::Array(eb, lb)) => self.unify(ea, eb) && self.unify(la, lb),
+			(Type::Adt(pa, ga), Type::Adt(pb, gb)) => {
+				pa == pb && ga.len() == gb.len() && ga.iter().zip(gb.iter()).all(|(x, y)| self.unify(x, y))
+			}
+			(Type::Fn(aa, ra), Type::Fn(ab, rb)) => {
+				aa.len() == ab.len() && aa.iter().zip(ab.iter()).all(|(x, y)| self.unify(x, y)) && self.unify(ra, rb)
+			}
+			(a, b) => a == b,
+		}
+	}
+
+	fn occurs(&self, v: usize, t: &Type) -> bool {
+		let t = self.resolve(t);
+		let mut found = false;
+		t.walk(&mut |t| found |= *t == Type::Var(v));
+		found
+	}
+}
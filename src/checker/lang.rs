@@ -0,0 +1,40 @@
+//! Traits in `core` the language itself depends on, along with the method each construct calls
+
+pub type LangItem = (&'static [&'static str], &'static str);
+
+pub const ADD: LangItem = (&["core", "ops", "Add"], "add");
+pub const SUB: LangItem = (&["core", "ops", "Sub"], "sub");
+pub const MUL: LangItem = (&["core", "ops", "Mul"], "mul");
+pub const DIV: LangItem = (&["core", "ops", "Div"], "div");
+pub const POW: LangItem = (&["core", "ops", "Pow"], "pow");
+pub const NOT: LangItem = (&["core", "ops", "Not"], "not");
+pub const NEG: LangItem = (&["core", "ops", "Neg"], "neg");
+pub const INDEX: LangItem = (&["core", "ops", "Index"], "index");
+/// `a[i] = value` assigns `IndexSet::index_set(a, i, value)` to `a`
+pub const INDEX_SET: LangItem = (&["core", "ops", "IndexSet"], "index_set");
+
+pub const EQ: LangItem = (&["core", "cmp", "PartialEq"], "eq");
+pub const NE: LangItem = (&["core", "cmp", "PartialEq"], "ne");
+pub const LT: LangItem = (&["core", "cmp", "PartialOrd"], "lt");
+pub const LE: LangItem = (&["core", "cmp", "PartialOrd"], "le");
+pub const GT: LangItem = (&["core", "cmp", "PartialOrd"], "gt");
+pub const GE: LangItem = (&["core", "cmp", "PartialOrd"], "ge");
+
+/// The trait of callable values, which only function types implement
+pub const FN: &[&str] = &["core", "ops", "Fn"];
+
+pub const REF: &[&str] = &["core", "cell", "Ref"];
+pub const VEC: &[&str] = &["std", "vec", "Vec"];
+pub const STRING: &[&str] = &["std", "string", "String"];
+
+/// Types the backends represent themselves, which can only be created with their `new`
+pub const BUILTIN_TYPES: [&[&str]; 3] = [REF, VEC, STRING];
+
+/// `value?` calls `Try::branch`, and returns `FromResidual::from_residual` when it gives back `ControlFlow::Break`
+pub const TRY_BRANCH: LangItem = (&["core", "ops", "Try"], "branch");
+pub const FROM_RESIDUAL: LangItem = (&["core", "ops", "FromResidual"], "from_residual");
+pub const CONTROL_FLOW: &[&str] = &["core", "ops", "ControlFlow"];
+
+/// `for` loops call `Iterator::next` until it gives back `Option::None`
+pub const ITERATOR_NEXT: LangItem = (&["core", "iter", "Iterator"], "next");
+pub const OPTION: &[&str] = &["core", "option", "Option"];
@@ -1,2 +1,80 @@
+pub mod consts;
 pub mod db;
-pub mod scope;
\ No newline at end of file
+mod exhaustive;
+mod expr;
+mod impls;
+pub mod infer;
+pub mod lang;
+pub mod scope;
+pub mod types;
+
+use hashbrown::HashMap;
+
+use crate::{error::Return, modules::ModuleTree};
+
+use db::{Method, TypeDB, Use};
+use expr::FnChecker;
+use types::{Primitive, Type};
+
+/// What checking found out about the crate, for the passes after it
+pub struct Checked<'a> {
+	pub db: TypeDB<'a>,
+	/// What was found about the body of each function, by [`db::FnId`]
+	pub fns: Vec<CheckedFn>,
+}
+
+/// What inference found out about the nodes of a function body, which are keyed by their address with [`key`]
+#[derive(Debug, Default)]
+pub struct CheckedFn {
+	/// The type inferred for each number literal
+	pub literals: HashMap<usize, Primitive>,
+	/// What each method call was resolved to
+	pub methods: HashMap<usize, Method>,
+	/// The variables each closure captures, which are copied when it's created
+	pub captures: HashMap<usize, Vec<String>>,
+	/// The functions the body uses, with the types given to their generics
+	pub uses: Vec<Use>,
+	/// The indices in `uses` of the ones each expression makes itself, like the method an operator calls
+	pub calls: HashMap<usize, Vec<usize>>,
+	pub exprs: HashMap<usize, Type>,
+	pub blocks: HashMap<usize, Type>,
+	/// The type of the value each pattern matches
+	pub patterns: HashMap<usize, Type>,
+}
+
+/// What identifies a node of the AST in [`CheckedFn`], which is never moved once parsed
+pub fn key<T>(node: &T) -> usize {
+	node as *const T as usize
+}
+
+/// Check the crate and all its extern libs
+pub fn check(tree: &ModuleTree) -> Return<Checked<'_>> {
+	let db = TypeDB::new(tree)?;
+	for (id, trait_) in db.traits.iter().enumerate() {
+		db.trait_scope(id)?;
+		for signature in &trait_.def.fn_signatures {
+			db.trait_method_signature(id, &signature.name)?;
+		}
+	}
+	for (id, type_) in db.types.iter().enumerate() {
+		for variant in 0..type_.variants.len() {
+			db.variant_fields(id, variant)?;
+		}
+	}
+	for id in 0..db.impls.len() {
+		db.impl_scope(id)?;
+	}
+	// Which impl a type uses is only known once impls don't overlap
+	impls::check_coherence(&db)?;
+	for id in 0..db.impls.len() {
+		impls::check_trait_impl(&db, id)?;
+	}
+	// Constants are evaluated even when they're not used, to report their errors
+	for id in 0..db.consts.len() {
+		db.const_value(id)?;
+	}
+	let fns = (0..db.fns.len())
+		.map(|id| FnChecker::check_fn(&db, id))
+		.collect::<Return<_>>()?;
+	Ok(Checked { db, fns })
+}
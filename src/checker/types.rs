@@ -0,0 +1,395 @@
+use std::fmt::Display;
+
+use hashbrown::HashMap;
+
+use super::db::ModulePath;
+
+/// The integer types, `int` is another name for `i64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd)]
+pub enum IntType {
+	I8,
+	I16,
+	I32,
+	I64,
+	Isize,
+	U8,
+	U16,
+	U32,
+	U64,
+	Usize,
+}
+
+impl IntType {
+	pub const ALL: [Self; 10] = [
+		Self::I8,
+		Self::I16,
+		Self::I32,
+		Self::I64,
+		Self::Isize,
+		Self::U8,
+		Self::U16,
+		Self::U32,
+		Self::U64,
+		Self::Usize,
+	];
+
+	pub fn bits(&self) -> u32 {
+		match self {
+			Self::I8 | Self::U8 => 8,
+			Self::I16 | Self::U16 => 16,
+			Self::I32 | Self::U32 => 32,
+			Self::I64 | Self::U64 | Self::Isize | Self::Usize => 64,
+		}
+	}
+
+	pub fn signed(&self) -> bool {
+		matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64 | Self::Isize)
+	}
+
+	pub fn min(&self) -> i128 {
+		if self.signed() {
+			-(1 << (self.bits() - 1))
+		} else {
+			0
+		}
+	}
+
+	pub fn max(&self) -> i128 {
+		if self.signed() {
+			(1 << (self.bits() - 1)) - 1
+		} else {
+			(1 << self.bits()) - 1
+		}
+	}
+
+	/// Wrap the value around to fit in the type, like two's complement arithmetic does
+	pub fn wrap(&self, value: i128) -> i128 {
+		let bits = self.bits();
+		let truncated = value & ((1 << bits) - 1);
+		if self.signed() && truncated > self.max() {
+			truncated - (1 << bits)
+		} else {
+			truncated
+		}
+	}
+
+	pub fn contains(&self, value: i128) -> bool {
+		self.min() <= value && value <= self.max()
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::I8 => "i8",
+			Self::I16 => "i16",
+			Self::I32 => "i32",
+			Self::I64 => "i64",
+			Self::Isize => "isize",
+			Self::U8 => "u8",
+			Self::U16 => "u16",
+			Self::U32 => "u32",
+			Self::U64 => "u64",
+			Self::Usize => "usize",
+		}
+	}
+}
+
+/// The floating point types, `float` is another name for `f64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd)]
+pub enum FloatType {
+	F32,
+	F64,
+}
+
+impl FloatType {
+	pub const ALL: [Self; 2] = [Self::F32, Self::F64];
+
+	/// Round the value to the precision of the type
+	pub fn round(&self, value: f64) -> f64 {
+		match self {
+			Self::F32 => value as f32 as f64,
+			Self::F64 => value,
+		}
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::F32 => "f32",
+			Self::F64 => "f64",
+		}
+	}
+}
+
+/// Types built into the language, that don't need to be declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Primitive {
+	Int(IntType),
+	Float(FloatType),
+	Str,
+	Char,
+	Bool,
+}
+
+impl Primitive {
+	pub fn from_name(name: &str) -> Option<Self> {
+		Some(match name {
+			"int" => Self::Int(IntType::I64),
+			"float" => Self::Float(FloatType::F64),
+			"str" => Self::Str,
+			"char" => Self::Char,
+			"bool" => Self::Bool,
+			_ => {
+				return IntType::ALL
+					.iter()
+					.find(|t| t.name() == name)
+					.map(|t| Self::Int(*t))
+					.or_else(|| {
+						FloatType::ALL
+							.iter()
+							.find(|t| t.name() == name)
+							.map(|t| Self::Float(*t))
+					})
+			}
+		})
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Int(t) => t.name(),
+			Self::Float(t) => t.name(),
+			Self::Str => "str",
+			Self::Char => "char",
+			Self::Bool => "bool",
+		}
+	}
+}
+
+/// A trait with all its generics applied
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TraitRef {
+	pub path: ModulePath,
+	pub generics: Vec<Type>,
+}
+
+impl TraitRef {
+	pub fn subst(&self, map: &HashMap<String, Type>) -> Self {
+		Self {
+			path: self.path.clone(),
+			generics: self.generics.iter().map(|t| t.subst(map)).collect(),
+		}
+	}
+}
+
+impl Display for TraitRef {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.path.last().unwrap())?;
+		write_generics(f, &self.generics)
+	}
+}
+
+/// A resolved type, as understood by the checker
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+	Empty,
+	/// The type of expressions that never produce a value, like `break`
+	Never,
+	Primitive(Primitive),
+	/// A tuple with at least one element, `()` is [`Type::Empty`]
+	Tuple(Vec<Type>),
+	/// The type of the elements and the length
+	Array(Box<Type>, Box<Type>),
+	/// The length of an array, only found inside [`Type::Array`] so that it can be generic or inferred
+	Length(usize),
+	/// A struct or an enum, with its generics applied
+	Adt(ModulePath, Vec<Type>),
+	/// A function or a closure, with the types of its arguments and of its result
+	Fn(Vec<Type>, Box<Type>),
+	/// A generic parameter in scope, it can't be unified with anything but itself
+	Generic(String),
+	/// An associated type of a trait implementation, like `<T as Add<int>>::Output`
+	Projection(Box<Type>, TraitRef, String),
+	/// An inference variable
+	Var(usize),
+}
+
+impl Type {
+	pub fn str() -> Self {
+		Self::Primitive(Primitive::Str)
+	}
+
+	pub fn char() -> Self {
+		Self::Primitive(Primitive::Char)
+	}
+
+	pub fn bool() -> Self {
+		Self::Primitive(Primitive::Bool)
+	}
+
+	/// Replace the generics with the types in the map
+	pub fn subst(&self, map: &HashMap<String, Type>) -> Self {
+		match self {
+			Self::Generic(name) => map.get(name).cloned().unwrap_or_else(|| self.clone()),
+			Self::Projection(self_type, trait_, name) => Self::Projection(
+				Box::new(self_type.subst(map)),
+				trait_.subst(map),
+				name.clone(),
+			),
+			Self::Tuple(types) => Self::Tuple(types.iter().map(|t| t.subst(map)).collect()),
+			Self::Array(element, len) => Self::Array(Box::new(element.subst(map)), Box::new(len.subst(map))),
+			Self::Adt(path, generics) => Self::Adt(path.clone(), generics.iter().map(|t| t.subst(map)).collect()),
+			Self::Fn(args, ret) => Self::Fn(args.iter().map(|t| t.subst(map)).collect(), Box::new(ret.subst(map))),
+			Self::Empty | Self::Never | Self::Primitive(_) | Self::Length(_) | Self::Var(_) => self.clone(),
+		}
+	}
+
+	/// Calls `f` on this type and every type contained in it
+	pub fn walk<F: FnMut(&Type)>(&self, f: &mut F) {
+		f(self);
+		match self {
+			Self::Projection(self_type, trait_, _) => {
+				self_type.walk(f);
+				for t in &trait_.generics {
+					t.walk(f);
+				}
+			}
+			Self::Tuple(types) | Self::Adt(_, types) => {
+				for t in types {
+					t.walk(f);
+				}
+			}
+			Self::Array(element, len) => {
+				element.walk(f);
+				len.walk(f);
+			}
+			Self::Fn(args, ret) => {
+				for t in args {
+					t.walk(f);
+				}
+				ret.walk(f);
+			}
+			_ => (),
+		}
+	}
+
+	/// The outermost constructor, `None` for generics and types that aren't known yet
+	pub fn head(&self) -> Option<TypeHead> {
+		match self {
+			Self::Empty => Some(TypeHead::Empty),
+			Self::Primitive(p) => Some(TypeHead::Primitive(*p)),
+			Self::Tuple(types) => Some(TypeHead::Tuple(types.len())),
+			Self::Array(..) => Some(TypeHead::Array),
+			Self::Adt(path, _) => Some(TypeHead::Adt(path.clone())),
+			Self::Fn(..) => Some(TypeHead::Fn),
+			Self::Never | Self::Length(_) | Self::Generic(_) | Self::Projection(..) | Self::Var(_) => None,
+		}
+	}
+
+	/// Replace the types inference couldn't tell with `()`, nothing depends on them
+	pub fn without_vars(&self) -> Self {
+		match self {
+			Self::Var(_) => Self::Empty,
+			Self::Tuple(types) => Self::Tuple(types.iter().map(Self::without_vars).collect()),
+			Self::Array(element, len) => Self::Array(Box::new(element.without_vars()), Box::new(len.without_vars())),
+			Self::Adt(path, generics) => Self::Adt(path.clone(), generics.iter().map(Self::without_vars).collect()),
+			Self::Fn(args, ret) => Self::Fn(args.iter().map(Self::without_vars).collect(), Box::new(ret.without_vars())),
+			Self::Projection(self_type, trait_, name) => Self::Projection(
+				Box::new(self_type.without_vars()),
+				TraitRef {
+					path: trait_.path.clone(),
+					generics: trait_.generics.iter().map(Self::without_vars).collect(),
+				},
+				name.clone(),
+			),
+			_ => self.clone(),
+		}
+	}
+
+	pub fn has_vars(&self) -> bool {
+		let mut found = false;
+		self.walk(&mut |t| found |= matches!(t, Type::Var(_)));
+		found
+	}
+}
+
+impl Display for Type {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "()"),
+			Self::Never => write!(f, "!"),
+			Self::Primitive(p) => write!(f, "{}", p.name()),
+			Self::Tuple(types) => write_tuple(f, types.iter().map(|t| t.to_string())),
+			Self::Array(element, len) => write!(f, "[{}; {}]", element, len),
+			Self::Length(len) => write!(f, "{}", len),
+			Self::Adt(path, generics) => {
+				write!(f, "{}", path.last().unwrap())?;
+				write_generics(f, generics)
+			}
+			Self::Fn(args, ret) => write_fn(f, args.iter().map(|t| t.to_string()), ret.to_string()),
+			Self::Generic(name) => write!(f, "{}", name),
+			Self::Projection(self_type, trait_, name) => {
+				write!(f, "<{} as {}>::{}", self_type, trait_, name)
+			}
+			Self::Var(_) => write!(f, "_"),
+		}
+	}
+}
+
+/// What the backends know about the type of a value at runtime, used to dispatch trait methods
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeHead {
+	Empty,
+	Primitive(Primitive),
+	/// A tuple with that many elements
+	Tuple(usize),
+	Array,
+	/// A struct or an enum, with its full path
+	Adt(ModulePath),
+	/// A function or a closure
+	Fn,
+}
+
+impl Display for TypeHead {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "()"),
+			Self::Primitive(p) => write!(f, "{}", p.name()),
+			Self::Tuple(len) => write_tuple(f, (0..*len).map(|_| "_".to_string())),
+			Self::Array => write!(f, "[_]"),
+			Self::Adt(path) => write!(f, "{}", path.join("::")),
+			Self::Fn => write!(f, "fn"),
+		}
+	}
+}
+
+/// Write the elements between parentheses, with a trailing comma if there's only one of them
+fn write_tuple<I: Iterator<Item = String>>(f: &mut std::fmt::Formatter<'_>, elements: I) -> std::fmt::Result {
+	let elements: Vec<String> = elements.collect();
+	if elements.len() == 1 {
+		write!(f, "({},)", elements[0])
+	} else {
+		write!(f, "({})", elements.join(", "))
+	}
+}
+
+/// Write a function type like `fn(int) -> int`, leaving out the result if it's `()`
+pub fn write_fn<W: std::fmt::Write, I: Iterator<Item = String>>(f: &mut W, args: I, ret: String) -> std::fmt::Result {
+	write!(f, "fn({})", args.collect::<Vec<String>>().join(", "))?;
+	if ret != "()" {
+		write!(f, " -> {}", ret)?;
+	}
+	Ok(())
+}
+
+fn write_generics(f: &mut std::fmt::Formatter<'_>, generics: &[Type]) -> std::fmt::Result {
+	if !generics.is_empty() {
+		write!(
+			f,
+			"<{}>",
+			generics
+				.iter()
+				.map(|t| t.to_string())
+				.collect::<Vec<String>>()
+				.join(", ")
+		)?;
+	}
+	Ok(())
+}
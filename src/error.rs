@@ -8,9 +8,32 @@ pub enum ErrorCode {
 	// Error
 	ModuleNotFoundError,
 	NoMainError,
+	UnknownNameError,
+	UnknownTypeError,
+	TypeMismatchError,
+	NotCallableError,
+	ArgumentCountError,
+	TraitNotImplementedError,
+	AssignmentError,
+	UninitializedError,
+	CoherenceError,
+	OutputFileError,
+	LoopControlError,
+	LiteralError,
+	SyntaxError,
+	CastError,
+	NonExhaustivePatternError,
+	ImplError,
+	PrivacyError,
+	FormatError,
+	ConstEvalError,
+	RecursionLimitError,
 	// Warn
+	UnreachablePatternWarning,
+	#[allow(dead_code)] // Placeholders to display the kinds no diagnostic uses yet
 	WarnTest,
 	// Info
+	#[allow(dead_code)]
 	InfoTest,
 }
 
@@ -20,6 +43,27 @@ impl ErrorCode {
 		match self {
 			ModuleNotFoundError => ErrorKind::Error,
 			NoMainError => ErrorKind::Error,
+			UnknownNameError => ErrorKind::Error,
+			UnknownTypeError => ErrorKind::Error,
+			TypeMismatchError => ErrorKind::Error,
+			NotCallableError => ErrorKind::Error,
+			ArgumentCountError => ErrorKind::Error,
+			TraitNotImplementedError => ErrorKind::Error,
+			AssignmentError => ErrorKind::Error,
+			UninitializedError => ErrorKind::Error,
+			CoherenceError => ErrorKind::Error,
+			OutputFileError => ErrorKind::Error,
+			LoopControlError => ErrorKind::Error,
+			LiteralError => ErrorKind::Error,
+			SyntaxError => ErrorKind::Error,
+			CastError => ErrorKind::Error,
+			NonExhaustivePatternError => ErrorKind::Error,
+			ImplError => ErrorKind::Error,
+			PrivacyError => ErrorKind::Error,
+			FormatError => ErrorKind::Error,
+			ConstEvalError => ErrorKind::Error,
+			RecursionLimitError => ErrorKind::Error,
+			UnreachablePatternWarning => ErrorKind::Warn,
 			WarnTest => ErrorKind::Warn,
 			InfoTest => ErrorKind::Info,
 		}
@@ -51,6 +95,8 @@ pub struct Error {
 	code: ErrorCode,
 	span: Span<()>,
 	message: String,
+	/// Other places the error is about, each with what it is
+	notes: Vec<(Span<()>, String)>,
 }
 
 impl Error {
@@ -59,11 +105,24 @@ impl Error {
 			code,
 			span,
 			message,
+			notes: Vec::new(),
 		}
 	}
 
+	pub fn with_note<S, M: ToString>(mut self, span: &Span<S>, message: M) -> Self {
+		self.notes.push((Span::new_ref(span, |_| ()), message.to_string()));
+		self
+	}
+
 	pub fn display(self) -> Result<(), Self> {
+		let (line, col) = self.span.start();
 		println!("{}: {}", self.code, self.message);
+		println!("  --> {}:{}:{}", self.span.file(), line, col);
+		for (span, message) in &self.notes {
+			let (line, col) = span.start();
+			println!("note: {}", message);
+			println!("  --> {}:{}:{}", span.file(), line, col);
+		}
 
 		match self.code.get_kind() {
 			ErrorKind::Error => Err(self),
@@ -11,6 +11,7 @@ use crate::{
 
 #[derive(Clone)]
 pub enum File {
+	#[allow(dead_code)] // The REPL isn't started yet
 	Repl(usize),
 	Path(PathBuf),
 }
@@ -36,13 +37,14 @@ pub struct Fs {
 }
 
 impl Fs {
+	#[allow(dead_code)]
 	pub fn insert_repl_statement(&mut self, statement: String) -> File {
 		let res = File::Repl(self.repl.len());
 		self.repl.push(statement);
 		res
 	}
 
-	pub fn load_file<'a>(&self, file: &File) -> String {
+	pub fn load_file(&self, file: &File) -> String {
 		// println!("{:?}", file);
 		match file {
 			File::Repl(i) => self.repl[*i].clone(),
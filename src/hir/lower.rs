@@ -0,0 +1,817 @@
+use hashbrown::HashMap;
+
+use crate::{
+	ast::{self, Block, ConstKind, Literal},
+	checker::{
+		consts::ConstValue,
+		db::{self, FnId, Item, Method, TypeDB, TypeId},
+		key,
+		lang,
+		types::{FloatType, IntType, Primitive, Type},
+		Checked, CheckedFn,
+	},
+	error::Return,
+	span::{BoxedSpan, Span},
+};
+
+use super::{Arm, Body, BodyId, Callee, Const, Expr, ExprKind, Hir, Local, LocalId, LoopId, Pattern, PatternKind, Statement};
+
+/// Lower the body of every function, with the types the checker found
+pub fn lower<'a, 'b>(checked: &'b Checked<'a>) -> Return<Hir<'a, 'b>> {
+	let db = &checked.db;
+	let mut bodies = Vec::new();
+	let mut fns = Vec::new();
+	for id in 0..db.fns.len() {
+		fns.push(lower_fn(db, id, &checked.fns[id], &mut bodies)?);
+	}
+	Ok(Hir { db, bodies, fns })
+}
+
+/// What replaces a part of a value, when assigning to it
+enum Replaced {
+	/// An element, by calling `IndexSet::index_set` with the variable holding the index
+	Index(Callee, LocalId),
+	/// A field of a tuple or a struct, by its position
+	Field(usize),
+}
+
+/// A body being lowered
+#[derive(Default)]
+struct Frame {
+	locals: Vec<Local>,
+	scopes: Vec<HashMap<String, LocalId>>,
+	/// The loops around the expression being lowered, with their label
+	loops: Vec<(Option<String>, LoopId)>,
+	next_loop: LoopId,
+}
+
+struct Lowerer<'a, 'b, 'c> {
+	db: &'b TypeDB<'a>,
+	fn_: FnId,
+	checked: &'c CheckedFn,
+	bodies: &'c mut Vec<Body>,
+	/// The function, then the closures being lowered in it, innermost last
+	frames: Vec<Frame>,
+	/// Whether the pattern being lowered is an alternative of an or-pattern after the first one,
+	/// whose bindings are the variables the first one declared
+	alternative: bool,
+}
+
+fn lower_fn(db: &TypeDB, id: FnId, checked: &CheckedFn, bodies: &mut Vec<Body>) -> Return<BodyId> {
+	let entry = &db.fns[id];
+	let (_, sig) = db.fn_signature(id)?;
+	let mut frame = Frame::default();
+	let mut scope = HashMap::new();
+	for ((name, _), type_) in entry.def.args.iter().zip(&sig.args) {
+		scope.insert(name.to_string(), frame.locals.len());
+		frame.locals.push(Local {
+			name: name.to_string(),
+			type_: type_.clone(),
+			mutable: false,
+		});
+	}
+	frame.scopes.push(scope);
+	let mut lowerer = Lowerer {
+		db,
+		fn_: id,
+		checked,
+		bodies,
+		frames: vec![frame],
+		alternative: false,
+	};
+	let value = lowerer.block(&entry.def.body, &entry.def.body)?;
+	let frame = lowerer.frames.pop().unwrap();
+	bodies.push(Body {
+		fn_: id,
+		closure: false,
+		locals: frame.locals,
+		args: sig.args.len(),
+		captures: 0,
+		return_type: sig.ret,
+		value,
+	});
+	Ok(bodies.len() - 1)
+}
+
+impl<'a, 'b, 'c> Lowerer<'a, 'b, 'c> {
+	fn frame(&mut self) -> &mut Frame {
+		self.frames.last_mut().unwrap()
+	}
+
+	fn module(&self) -> &'b [String] {
+		&self.db.fns[self.fn_].module
+	}
+
+	fn new_local(&mut self, name: &str, type_: Type, mutable: bool) -> LocalId {
+		let locals = &mut self.frame().locals;
+		locals.push(Local {
+			name: name.to_string(),
+			type_,
+			mutable,
+		});
+		locals.len() - 1
+	}
+
+	fn lookup(&self, name: &str) -> Option<LocalId> {
+		self.frames.last().unwrap().scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+	}
+
+	/// The type the checker found for a node, or `!` if it didn't check it because it comes after something that diverges
+	fn type_of<T>(&self, types: &HashMap<usize, Type>, node: &T) -> Type {
+		types.get(&key(node)).map_or(Type::Never, Type::without_vars)
+	}
+
+	/// The function the expression calls, the first one it uses being at index 0
+	fn callee(&self, expr: &ast::Expr, index: usize) -> Option<Callee> {
+		let use_ = &self.checked.uses[*self.checked.calls.get(&key(expr))?.get(index)?];
+		let generics = use_.generics.iter().map(Type::without_vars).collect();
+		Some(match &use_.callee {
+			db::Callee::Fn(id) => Callee::Fn(*id, generics),
+			db::Callee::Method(trait_, name) => Callee::Method(*trait_, name.clone(), generics),
+		})
+	}
+
+	fn block<S>(&mut self, block: &Block, span: &Span<S>) -> Return<Expr> {
+		self.frame().scopes.push(HashMap::new());
+		let mut statements = Vec::new();
+		let mut value = None;
+		for (i, statement) in block.iter().enumerate() {
+			match &***statement {
+				ast::Statement::Returning(expr) if i == block.len() - 1 => {
+					value = Some(Box::new(self.expr(expr, statement)?));
+				}
+				ast::Statement::Returning(expr) | ast::Statement::NonReturning(expr) => {
+					statements.push(Statement::Expr(self.expr(expr, statement)?));
+				}
+				ast::Statement::Let(pattern, _, value, else_block) => {
+					let value = value.as_ref().map(|value| self.sub(value)).transpose()?;
+					// The variables of the pattern can't be used in the `else`
+					let else_block = else_block.as_ref().map(|block| self.block(block, statement).map(Box::new)).transpose()?;
+					let pattern = self.pattern(pattern)?;
+					statements.push(Statement::Let(pattern, value, else_block));
+				}
+			}
+		}
+		self.frame().scopes.pop();
+		Ok(Expr {
+			kind: ExprKind::Block(statements, value),
+			type_: self.type_of(&self.checked.blocks, block),
+			span: Span::new_ref(span, |_| ()),
+		})
+	}
+
+	fn sub(&mut self, expr: &BoxedSpan<ast::Expr>) -> Return<Expr> {
+		self.expr(expr, expr)
+	}
+
+	fn expr<S>(&mut self, expr: &ast::Expr, span: &Span<S>) -> Return<Expr> {
+		use ast::Expr as E;
+		let type_ = self.type_of(&self.checked.exprs, expr);
+		let kind = match expr {
+			E::Literal(literal) => ExprKind::Const(literal_value(self.checked, literal, false)),
+			E::Add(a, _, b)
+			| E::Sub(a, _, b)
+			| E::Mul(a, _, b)
+			| E::Div(a, _, b)
+			| E::Pow(a, _, b)
+			| E::Eq(a, _, b)
+			| E::Ne(a, _, b)
+			| E::Lt(a, _, b)
+			| E::Le(a, _, b)
+			| E::Gt(a, _, b)
+			| E::Ge(a, _, b)
+			| E::Index(a, b) => self.operator(expr, &[a, b])?,
+			E::And(a, _, b) => ExprKind::And(Box::new(self.condition(a)?), Box::new(self.condition(b)?)),
+			E::Or(a, _, b) => ExprKind::Or(Box::new(self.condition(a)?), Box::new(self.condition(b)?)),
+			E::Not(a) => self.operator(expr, &[a])?,
+			E::Neg(a) => match &***a {
+				E::Literal(literal @ Literal::Int(..)) => ExprKind::Const(literal_value(self.checked, literal, true)),
+				_ => self.operator(expr, &[a])?,
+			},
+			E::Cast(value, _) => {
+				let value = self.sub(value)?;
+				match type_ {
+					Type::Primitive(target) => ExprKind::Cast(Box::new(value), target),
+					// Other casts are between the same types
+					_ => return Ok(value),
+				}
+			}
+			E::Tuple(elements) => ExprKind::Tuple(self.all(elements)?),
+			E::Array(elements) => ExprKind::Array(self.all(elements)?),
+			E::Field(base, name) => {
+				let base = self.sub(base)?;
+				match self.field_index(&base.type_, name) {
+					Some(index) => ExprKind::Field(Box::new(base), index),
+					None => return Ok(base),
+				}
+			}
+			E::Struct(path, fields) => {
+				let (id, variant) = self.variant(path)?;
+				// The fields are evaluated in the order they are written
+				let mut values = Vec::new();
+				for (name, value) in fields {
+					let index = self.db.types[id].field_index(variant, name).unwrap();
+					values.push((index, self.sub(value)?));
+				}
+				ExprKind::Adt(id, variant, values)
+			}
+			E::Assign(place, _, value) => {
+				let id = match &***place {
+					E::Ident(path) => self.lookup(&path[0]).unwrap(),
+					_ => return self.assign_part(place, value, span),
+				};
+				ExprKind::Assign(id, Box::new(self.sub(value)?))
+			}
+			E::Block(block) => return self.block(block, span),
+			E::FnCall(callee, args) => match &***callee {
+				E::Ident(path) if !(path.len() == 1 && self.lookup(&path[0]).is_some()) => {
+					let item = self.db.resolve_path(self.module(), path)?;
+					match self.db.variant_of(&item) {
+						Some((id, variant)) => ExprKind::Adt(id, variant, self.all(args)?.into_iter().enumerate().collect()),
+						None => ExprKind::Call(self.callee(expr, 0).unwrap(), self.all(args)?),
+					}
+				}
+				_ => {
+					let callee = self.sub(callee)?;
+					if callee.type_ == Type::Never {
+						return Ok(callee);
+					}
+					ExprKind::Call(Callee::Value(Box::new(callee)), self.all(args)?)
+				}
+			},
+			E::Closure(params, _, body) => {
+				let captures = self.checked.captures.get(&key(expr)).map_or(&[][..], Vec::as_slice);
+				self.closure(&type_, params, body, captures)?
+			}
+			E::MethodCall(receiver, name, args) => {
+				let receiver = self.sub(receiver)?;
+				let callee = match (self.checked.methods.get(&key(expr)).copied(), self.callee(expr, 0)) {
+					(Some(Method::Inherent), Some(callee)) => callee,
+					(Some(Method::Trait(trait_)), Some(Callee::Method(_, _, generics))) => {
+						Callee::Method(trait_, name.to_string(), generics)
+					}
+					// The receiver diverges, so the method is never called
+					_ => return Ok(receiver),
+				};
+				let mut values = vec![receiver];
+				values.extend(self.all(args)?);
+				ExprKind::Call(callee, values)
+			}
+			E::Try(operand) => return self.try_operator(expr, span, type_, operand),
+			E::If(condition, block, elseif_clauses, else_clause) => {
+				let mut branches = vec![self.branch(condition, block)?];
+				for (condition, block) in elseif_clauses {
+					branches.push(self.branch(condition, block)?);
+				}
+				let mut otherwise = else_clause.as_ref().map(|block| self.block(block, span)).transpose()?;
+				// Each `else if` is the `else` of the `if` before it
+				for (condition, then) in branches.into_iter().rev() {
+					otherwise = Some(Expr {
+						kind: ExprKind::If(Box::new(condition), Box::new(then), otherwise.map(Box::new)),
+						type_: type_.clone(),
+						span: Span::new_ref(span, |_| ()),
+					});
+				}
+				return Ok(otherwise.unwrap());
+			}
+			E::Match(scrutinee, arms) => {
+				let scrutinee = self.sub(scrutinee)?;
+				let arms = arms.iter().map(|arm| self.arm(arm)).collect::<Return<_>>()?;
+				ExprKind::Match(Box::new(scrutinee), arms)
+			}
+			E::While(label, condition, body) => {
+				self.frame().scopes.push(HashMap::new());
+				let condition = self.condition(condition)?;
+				let id = self.enter_loop(label);
+				let body = self.block(body, span)?;
+				self.frame().loops.pop();
+				self.frame().scopes.pop();
+				let exit = self.new_expr(ExprKind::Break(id, None), Type::Never, span);
+				let branch = ExprKind::If(Box::new(condition), Box::new(body), Some(Box::new(exit)));
+				ExprKind::Loop(id, Box::new(self.new_expr(branch, Type::Empty, span)))
+			}
+			E::Let(..) => unreachable!("`let` is only parsed as the condition of `if` and `while`"),
+			E::Loop(label, body) => {
+				let id = self.enter_loop(label);
+				let body = self.block(body, span)?;
+				self.frame().loops.pop();
+				ExprKind::Loop(id, Box::new(body))
+			}
+			E::For(label, pattern, iterator, body) => return self.for_loop(expr, span, label, pattern, iterator, body),
+			E::Break(label, value) => {
+				let value = value.as_ref().map(|value| self.sub(value)).transpose()?;
+				ExprKind::Break(self.find_loop(label), value.map(Box::new))
+			}
+			E::Continue(label) => ExprKind::Continue(self.find_loop(label)),
+			E::Return(value) => {
+				let value = match value {
+					Some(value) => self.sub(value)?,
+					None => self.new_expr(ExprKind::Const(Const::Empty), Type::Empty, span),
+				};
+				ExprKind::Return(Box::new(value))
+			}
+			E::Ident(path) => self.ident(expr, path)?,
+		};
+		Ok(Expr {
+			kind,
+			type_,
+			span: Span::new_ref(span, |_| ()),
+		})
+	}
+
+	fn new_expr<S>(&self, kind: ExprKind, type_: Type, span: &Span<S>) -> Expr {
+		Expr {
+			kind,
+			type_,
+			span: Span::new_ref(span, |_| ()),
+		}
+	}
+
+	fn all(&mut self, exprs: &[BoxedSpan<ast::Expr>]) -> Return<Vec<Expr>> {
+		exprs.iter().map(|e| self.sub(e)).collect()
+	}
+
+	/// The struct or variant at the path
+	fn variant(&self, path: &[Span<String>]) -> Return<(TypeId, usize)> {
+		let item = self.db.resolve_path(self.module(), path)?;
+		Ok(self.db.variant_of(&item).unwrap())
+	}
+
+	/// An operator, which calls the method of its trait with the operands
+	fn operator(&mut self, expr: &ast::Expr, operands: &[&BoxedSpan<ast::Expr>]) -> Return<ExprKind> {
+		let operands = operands.iter().map(|o| self.sub(o)).collect::<Return<Vec<Expr>>>()?;
+		Ok(match self.callee(expr, 0) {
+			Some(callee) => ExprKind::Call(callee, operands),
+			// An operand diverges, so the method is never called
+			None => ExprKind::Block(operands.into_iter().map(Statement::Expr).collect(), None),
+		})
+	}
+
+	/// The condition of an `if`, a `while`, or an operand of `&&` and `||`, which can be a `let`
+	fn condition(&mut self, condition: &BoxedSpan<ast::Expr>) -> Return<Expr> {
+		match &***condition {
+			ast::Expr::Let(pattern, value) => {
+				let value = self.sub(value)?;
+				let pattern = self.pattern(pattern)?;
+				Ok(self.new_expr(ExprKind::Let(pattern, Box::new(value)), Type::bool(), condition))
+			}
+			_ => self.sub(condition),
+		}
+	}
+
+	/// A block run when its condition holds, which sees the variables the condition binds
+	fn branch(&mut self, condition: &BoxedSpan<ast::Expr>, block: &Block) -> Return<(Expr, Expr)> {
+		self.frame().scopes.push(HashMap::new());
+		let condition = self.condition(condition)?;
+		let block = self.block(block, &condition.span)?;
+		self.frame().scopes.pop();
+		Ok((condition, block))
+	}
+
+	fn arm(&mut self, arm: &ast::MatchArm) -> Return<Arm> {
+		self.frame().scopes.push(HashMap::new());
+		let pattern = self.pattern(&arm.pattern)?;
+		let guard = arm.guard.as_ref().map(|guard| self.condition(guard)).transpose()?;
+		let body = self.sub(&arm.body)?;
+		self.frame().scopes.pop();
+		Ok(Arm { pattern, guard, body })
+	}
+
+	fn enter_loop(&mut self, label: &Option<ast::Label>) -> LoopId {
+		let frame = self.frame();
+		let id = frame.next_loop;
+		frame.next_loop += 1;
+		frame.loops.push((label.as_ref().map(|l| l.to_string()), id));
+		id
+	}
+
+	fn find_loop(&mut self, label: &Option<ast::Label>) -> LoopId {
+		let loops = &self.frame().loops;
+		let found = match label {
+			None => loops.last(),
+			Some(label) => loops.iter().rev().find(|(l, _)| l.as_deref() == Some(label.as_str())),
+		};
+		found.expect("`break` or `continue` outside of a loop").1
+	}
+
+	/// `for PATTERN in iterator { body }`, which is
+	/// `{ let iter = iterator; loop { match Iterator::next(iter) { Some(PATTERN) => body, _ => break } } }`
+	fn for_loop<S>(
+		&mut self,
+		expr: &ast::Expr,
+		span: &Span<S>,
+		label: &Option<ast::Label>,
+		pattern: &Span<ast::Pattern>,
+		iterator: &BoxedSpan<ast::Expr>,
+		body: &Block,
+	) -> Return<Expr> {
+		let iterator = self.sub(iterator)?;
+		let next = match self.callee(expr, 0) {
+			Some(next) => next,
+			None => return Ok(iterator),
+		};
+		let iter = self.new_local("iter", iterator.type_.clone(), true);
+		self.frame().scopes.push(HashMap::new());
+		let pattern = self.pattern(pattern)?;
+		let id = self.enter_loop(label);
+		let body = self.block(body, span)?;
+		self.frame().loops.pop();
+		self.frame().scopes.pop();
+		let (option, some) = self.lang_variant(lang::OPTION, "Some");
+		let option_type = Type::Adt(self.db.types[option].path.clone(), vec![pattern.type_.clone()]);
+		let local = self.new_expr(ExprKind::Local(iter), iterator.type_.clone(), span);
+		let next = self.new_expr(ExprKind::Call(next, vec![local]), option_type.clone(), span);
+		let arms = vec![
+			Arm {
+				pattern: Pattern {
+					kind: PatternKind::Variant(option, some, vec![(0, pattern)]),
+					type_: option_type.clone(),
+				},
+				guard: None,
+				body,
+			},
+			Arm {
+				pattern: Pattern {
+					kind: PatternKind::Wildcard,
+					type_: option_type,
+				},
+				guard: None,
+				body: self.new_expr(ExprKind::Break(id, None), Type::Never, span),
+			},
+		];
+		let matched = self.new_expr(ExprKind::Match(Box::new(next), arms), Type::Empty, span);
+		let looped = self.new_expr(ExprKind::Loop(id, Box::new(matched)), Type::Empty, span);
+		let init = Pattern {
+			kind: PatternKind::Binding(iter, None),
+			type_: iterator.type_.clone(),
+		};
+		let statements = vec![Statement::Let(init, Some(iterator), None)];
+		Ok(self.new_expr(ExprKind::Block(statements, Some(Box::new(looped))), Type::Empty, span))
+	}
+
+	/// `operand?`, which is
+	/// `match Try::branch(operand) { Continue(value) => value, Break(residual) => return FromResidual::from_residual(residual) }`
+	fn try_operator<S>(&mut self, expr: &ast::Expr, span: &Span<S>, type_: Type, operand: &BoxedSpan<ast::Expr>) -> Return<Expr> {
+		let operand = self.sub(operand)?;
+		let (branch, from_residual) = match (self.callee(expr, 0), self.callee(expr, 1)) {
+			(Some(branch), Some(from_residual)) => (branch, from_residual),
+			_ => return Ok(operand),
+		};
+		let (residual_type, return_type) = match &from_residual {
+			Callee::Method(_, _, generics) => (generics[0].clone(), generics[1].clone()),
+			_ => unreachable!("`from_residual` is a trait method"),
+		};
+		let (control_flow, continue_) = self.lang_variant(lang::CONTROL_FLOW, "Continue");
+		let break_ = self.db.types[control_flow].variant_index("Break").unwrap();
+		let flow_type = Type::Adt(
+			self.db.types[control_flow].path.clone(),
+			vec![residual_type.clone(), type_.clone()],
+		);
+		let value = self.new_local("value", type_.clone(), false);
+		let residual = self.new_local("residual", residual_type.clone(), false);
+		let binding = |local: LocalId, type_: &Type| Pattern {
+			kind: PatternKind::Binding(local, None),
+			type_: type_.clone(),
+		};
+		let variant = |variant: usize, field: Pattern| Pattern {
+			kind: PatternKind::Variant(control_flow, variant, vec![(0, field)]),
+			type_: flow_type.clone(),
+		};
+		let residual_value = self.new_expr(ExprKind::Local(residual), residual_type.clone(), span);
+		let early = self.new_expr(ExprKind::Call(from_residual, vec![residual_value]), return_type, span);
+		let arms = vec![
+			Arm {
+				pattern: variant(continue_, binding(value, &type_)),
+				guard: None,
+				body: self.new_expr(ExprKind::Local(value), type_.clone(), span),
+			},
+			Arm {
+				pattern: variant(break_, binding(residual, &residual_type)),
+				guard: None,
+				body: self.new_expr(ExprKind::Return(Box::new(early)), Type::Never, span),
+			},
+		];
+		let branched = self.new_expr(ExprKind::Call(branch, vec![operand]), flow_type.clone(), span);
+		Ok(self.new_expr(ExprKind::Match(Box::new(branched), arms), type_, span))
+	}
+
+	/// The position of a field of a tuple or a struct, `None` if the value diverges
+	fn field_index(&self, type_: &Type, name: &str) -> Option<usize> {
+		match type_ {
+			Type::Tuple(_) => Some(name.parse().unwrap()),
+			Type::Adt(path, _) => {
+				let id = self.db.type_by_path(path).unwrap();
+				self.db.types[id].field_index(0, name)
+			}
+			_ => None,
+		}
+	}
+
+	fn local<S>(&self, id: LocalId, span: &Span<S>) -> Expr {
+		let type_ = self.frames.last().unwrap().locals[id].type_.clone();
+		self.new_expr(ExprKind::Local(id), type_, span)
+	}
+
+	/// A new variable, declared by a statement, holding the value
+	fn temporary(&mut self, name: &str, value: Expr, statements: &mut Vec<Statement>) -> LocalId {
+		let id = self.new_local(name, value.type_.clone(), false);
+		let pattern = Pattern {
+			kind: PatternKind::Binding(id, None),
+			type_: value.type_.clone(),
+		};
+		statements.push(Statement::Let(pattern, Some(value), None));
+		id
+	}
+
+	/// `place = value` where the place is an element or a field of a variable, which is assigned a copy of its value
+	/// with it replaced: `a[i].0 = v` is `a = IndexSet::index_set(a, i, (v, a[i].1))`.
+	/// The value, then the indices and the elements the place is in are evaluated once
+	fn assign_part<S>(&mut self, place: &BoxedSpan<ast::Expr>, value: &BoxedSpan<ast::Expr>, span: &Span<S>) -> Return<Expr> {
+		let value = self.sub(value)?;
+		if value.type_ == Type::Never {
+			return Ok(value);
+		}
+		let mut statements = Vec::new();
+		let value = self.temporary("value", value, &mut statements);
+		// The elements and fields, from the one of the variable to the place
+		let mut parts = Vec::new();
+		let mut part = place;
+		while let ast::Expr::Field(base, _) | ast::Expr::Index(base, _) = &***part {
+			parts.push(&***part);
+			part = base;
+		}
+		parts.reverse();
+		let root = match &***part {
+			ast::Expr::Ident(path) => self.lookup(&path[0]).unwrap(),
+			x => unreachable!("Invalid place: {:?}", x),
+		};
+		// The variable and the elements the place is in, with what replaces their part
+		let mut containers = Vec::new();
+		let mut container = root;
+		for (i, part) in parts.iter().enumerate() {
+			let (read, replaced) = match part {
+				ast::Expr::Field(_, name) => {
+					let type_ = &self.frames.last().unwrap().locals[container].type_;
+					let index = self.field_index(type_, name).unwrap();
+					(ExprKind::Field(Box::new(self.local(container, span)), index), Replaced::Field(index))
+				}
+				ast::Expr::Index(_, index) => {
+					let index = self.sub(index)?;
+					if index.type_ == Type::Never {
+						statements.push(Statement::Expr(index));
+						return Ok(self.new_expr(ExprKind::Block(statements, None), Type::Never, span));
+					}
+					let index = self.temporary("index", index, &mut statements);
+					let (read, replace) = (self.callee(part, 0).unwrap(), self.callee(part, 1).unwrap());
+					let args = vec![self.local(container, span), self.local(index, span)];
+					(ExprKind::Call(read, args), Replaced::Index(replace, index))
+				}
+				x => unreachable!("Invalid place: {:?}", x),
+			};
+			containers.push((container, replaced));
+			if i < parts.len() - 1 {
+				let read = self.new_expr(read, self.type_of(&self.checked.exprs, *part), span);
+				container = self.temporary("part", read, &mut statements);
+			}
+		}
+		let mut new = self.local(value, span);
+		for (container, replaced) in containers.into_iter().rev() {
+			let kind = match replaced {
+				Replaced::Index(replace, index) => {
+					ExprKind::Call(replace, vec![self.local(container, span), self.local(index, span), new])
+				}
+				Replaced::Field(index) => self.replace_field(container, index, new, span)?,
+			};
+			new = self.new_expr(kind, self.frames.last().unwrap().locals[container].type_.clone(), span);
+		}
+		let assign = self.new_expr(ExprKind::Assign(root, Box::new(new)), Type::Empty, span);
+		statements.push(Statement::Expr(assign));
+		Ok(self.new_expr(ExprKind::Block(statements, None), Type::Empty, span))
+	}
+
+	/// A copy of the tuple or the struct in the variable, with the field at the index replaced by the value
+	fn replace_field<S>(&self, container: LocalId, index: usize, value: Expr, span: &Span<S>) -> Return<ExprKind> {
+		let (types, adt) = match &self.frames.last().unwrap().locals[container].type_ {
+			Type::Tuple(types) => (types.clone(), None),
+			Type::Adt(path, generics) => {
+				let id = self.db.type_by_path(path).unwrap();
+				let entry = &self.db.types[id];
+				let map = entry.generic_names().into_iter().zip(generics.iter().cloned()).collect();
+				let types = self.db.variant_fields(id, 0)?.iter().map(|t| t.subst(&map)).collect();
+				(types, Some(id))
+			}
+			t => unreachable!("Field of {:?}", t),
+		};
+		let mut value = Some(value);
+		let fields = types.into_iter().enumerate().map(|(i, type_)| match i == index {
+			true => value.take().unwrap(),
+			false => self.new_expr(ExprKind::Field(Box::new(self.local(container, span)), i), type_, span),
+		});
+		Ok(match adt {
+			Some(id) => ExprKind::Adt(id, 0, fields.enumerate().collect()),
+			None => ExprKind::Tuple(fields.collect()),
+		})
+	}
+
+	/// A type of `core` the language depends on, and the index of one of its variants
+	fn lang_variant(&self, path: &[&str], variant: &str) -> (TypeId, usize) {
+		let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+		let id = self.db.type_by_path(&path).unwrap_or_else(|| panic!("Missing lang type `{}`", path.join("::")));
+		(id, self.db.types[id].variant_index(variant).unwrap())
+	}
+
+	fn ident(&mut self, expr: &ast::Expr, path: &[Span<String>]) -> Return<ExprKind> {
+		if let [name] = path {
+			if let Some(id) = self.lookup(name) {
+				return Ok(ExprKind::Local(id));
+			}
+		}
+		let segments: Vec<&str> = path.iter().map(|s| s.as_str()).collect();
+		Ok(match self.db.lookup_path(self.module(), &segments) {
+			Some(Item::Fn(_)) => match self.callee(expr, 0) {
+				Some(Callee::Fn(id, generics)) => ExprKind::Fn(id, generics),
+				_ => unreachable!("the checker records the use of functions"),
+			},
+			// Constants are replaced by their value, statics are stored once
+			Some(Item::Const(id)) => match self.db.const_value(id)? {
+				ConstValue::Const(c) if self.db.consts[id].def.kind == ConstKind::Const => ExprKind::Const(c),
+				_ => ExprKind::Global(id),
+			},
+			Some(item) => {
+				let (id, variant) = self.db.variant_of(&item).unwrap();
+				ExprKind::Adt(id, variant, Vec::new())
+			}
+			// Builtins take the arguments of the function they are in
+			None => {
+				let frame = &self.frames[0];
+				let args = (0..self.db.fns[self.fn_].def.args.len())
+					.map(|i| self.new_expr(ExprKind::Local(i), frame.locals[i].type_.clone(), &path[0]))
+					.collect();
+				ExprKind::Builtin(path[0].trim_start_matches("BUILTIN_").to_string(), args)
+			}
+		})
+	}
+
+	/// A closure, whose body is lowered on its own, with the variables it captures after its arguments
+	fn closure(
+		&mut self,
+		type_: &Type,
+		params: &[(Span<ast::Pattern>, Option<Span<ast::Type>>)],
+		body: &BoxedSpan<ast::Expr>,
+		captures: &[String],
+	) -> Return<ExprKind> {
+		let (arg_types, return_type) = match type_ {
+			Type::Fn(args, ret) => (args.clone(), (**ret).clone()),
+			t => unreachable!("Closure of type {:?}", t),
+		};
+		let captured: Vec<LocalId> = captures.iter().map(|name| self.lookup(name).unwrap()).collect();
+		let mut frame = Frame::default();
+		for ((pattern, _), type_) in params.iter().zip(arg_types) {
+			frame.locals.push(Local {
+				name: self.simple_binding(pattern).unwrap_or("arg").to_string(),
+				type_,
+				mutable: false,
+			});
+		}
+		let mut scope = HashMap::new();
+		for (name, outer) in captures.iter().zip(&captured) {
+			scope.insert(name.clone(), frame.locals.len());
+			frame.locals.push(Local {
+				name: name.clone(),
+				type_: self.frames.last().unwrap().locals[*outer].type_.clone(),
+				mutable: false,
+			});
+		}
+		frame.scopes = vec![scope, HashMap::new()];
+		self.frames.push(frame);
+		// Arguments that aren't a plain name are matched against their pattern first
+		let mut statements = Vec::new();
+		for (i, (pattern, _)) in params.iter().enumerate() {
+			match self.simple_binding(pattern) {
+				Some(name) => {
+					self.frame().scopes[1].insert(name.to_string(), i);
+				}
+				None => {
+					let type_ = self.frame().locals[i].type_.clone();
+					let arg = self.new_expr(ExprKind::Local(i), type_, pattern);
+					let pattern = self.pattern(pattern)?;
+					statements.push(Statement::Let(pattern, Some(arg), None));
+				}
+			}
+		}
+		let mut value = self.sub(body)?;
+		if !statements.is_empty() {
+			let type_ = value.type_.clone();
+			value = self.new_expr(ExprKind::Block(statements, Some(Box::new(value))), type_, body);
+		}
+		let frame = self.frames.pop().unwrap();
+		self.bodies.push(Body {
+			fn_: self.fn_,
+			closure: true,
+			locals: frame.locals,
+			args: params.len(),
+			captures: captured.len(),
+			return_type,
+			value,
+		});
+		Ok(ExprKind::Closure(self.bodies.len() - 1, captured))
+	}
+
+	/// The name a pattern binds if it's only a name, which isn't a unit struct or variant
+	fn simple_binding<'p>(&self, pattern: &'p ast::Pattern) -> Option<&'p str> {
+		match pattern {
+			ast::Pattern::Binding {
+				name,
+				subpattern: None,
+				mutable,
+			} if mutable.is_some() || self.db.unit_variant(self.module(), name).is_none() => Some(name.as_str()),
+			_ => None,
+		}
+	}
+
+	fn pattern(&mut self, pattern: &Span<ast::Pattern>) -> Return<Pattern> {
+		use ast::Pattern as P;
+		let type_ = self.type_of(&self.checked.patterns, &**pattern);
+		let kind = match &**pattern {
+			P::Wildcard => PatternKind::Wildcard,
+			P::Binding {
+				mutable: None,
+				name,
+				subpattern: None,
+			} if self.db.unit_variant(self.module(), name).is_some() => {
+				let (id, variant) = self.db.unit_variant(self.module(), name).unwrap();
+				PatternKind::Variant(id, variant, Vec::new())
+			}
+			P::Binding {
+				name,
+				subpattern,
+				mutable,
+			} => {
+				let subpattern = subpattern.as_ref().map(|p| self.pattern(p)).transpose()?;
+				let id = match self.alternative {
+					true => self.frame().scopes.last().unwrap()[name.as_str()],
+					false => {
+						let id = self.new_local(name, type_.clone(), mutable.is_some());
+						self.frame().scopes.last_mut().unwrap().insert(name.to_string(), id);
+						id
+					}
+				};
+				PatternKind::Binding(id, subpattern.map(Box::new))
+			}
+			P::Literal(literal, negated) => PatternKind::Const(literal_value(self.checked, literal, *negated)),
+			P::Tuple(patterns) => PatternKind::Tuple(self.patterns(patterns)?),
+			P::Array(patterns) => PatternKind::Array(self.patterns(patterns)?),
+			P::Path(path) => {
+				let (id, variant) = self.variant(path)?;
+				PatternKind::Variant(id, variant, Vec::new())
+			}
+			P::TupleStruct(path, patterns) => {
+				let (id, variant) = self.variant(path)?;
+				PatternKind::Variant(id, variant, self.patterns(patterns)?.into_iter().enumerate().collect())
+			}
+			P::Struct(path, fields, _) => {
+				let (id, variant) = self.variant(path)?;
+				let mut patterns = Vec::new();
+				for (name, pattern) in fields {
+					let index = self.db.types[id].field_index(variant, name).unwrap();
+					patterns.push((index, self.pattern(pattern)?));
+				}
+				PatternKind::Variant(id, variant, patterns)
+			}
+			P::Or(alternatives) => {
+				let outer = self.alternative;
+				let mut patterns = Vec::new();
+				for (i, alternative) in alternatives.iter().enumerate() {
+					self.alternative = outer || i > 0;
+					patterns.push(self.pattern(alternative)?);
+				}
+				self.alternative = outer;
+				PatternKind::Or(patterns)
+			}
+		};
+		Ok(Pattern { kind, type_ })
+	}
+
+	fn patterns(&mut self, patterns: &[Span<ast::Pattern>]) -> Return<Vec<Pattern>> {
+		patterns.iter().map(|p| self.pattern(p)).collect()
+	}
+}
+
+/// The constant a literal is, with the type the checker gave it
+pub fn literal_value(checked: &CheckedFn, literal: &Literal, negated: bool) -> Const {
+	let type_ = checked.literals.get(&key(literal)).copied();
+	match literal {
+		Literal::Int(i, _) => match type_.unwrap_or(Primitive::Int(IntType::I64)) {
+			Primitive::Int(t) => Const::Int(if negated { -(*i as i128) } else { *i as i128 }, t),
+			Primitive::Float(t) => Const::Float(t.round(if negated { -(*i as f64) } else { *i as f64 }), t),
+			x => unreachable!("Int literal of type {:?}", x),
+		},
+		Literal::Float(x, _) => match type_.unwrap_or(Primitive::Float(FloatType::F64)) {
+			Primitive::Float(t) => Const::Float(t.round(if negated { -x } else { *x }), t),
+			x => unreachable!("Float literal of type {:?}", x),
+		},
+		Literal::String(s) => Const::Str(s.as_str().into()),
+		Literal::Char(c) => Const::Char(*c),
+		Literal::Bool(b) => Const::Bool(*b),
+	}
+}
@@ -0,0 +1,389 @@
+//! The typed tree the checked AST is lowered to: names are resolved, every node has its type,
+//! operators and methods are calls to the function they resolve to, and `for`, `while` and `?` are
+//! turned into loops and matches
+
+use std::{fmt::Write, rc::Rc};
+
+use crate::{
+	checker::{
+		db::{ConstId, FnId, TraitId, TypeDB, TypeId, LOCAL_CRATE},
+		types::{FloatType, IntType, Primitive, Type},
+	},
+	span::Span,
+};
+
+mod lower;
+
+pub use lower::{literal_value, lower};
+
+pub type LocalId = usize;
+pub type BodyId = usize;
+pub type LoopId = usize;
+
+/// The bodies of every function of the crate and its extern libs, and of the closures they define
+pub struct Hir<'a, 'b> {
+	pub db: &'b TypeDB<'a>,
+	pub bodies: Vec<Body>,
+	/// The body of each function, by [`FnId`]
+	pub fns: Vec<BodyId>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Local {
+	pub name: String,
+	pub type_: Type,
+	pub mutable: bool,
+}
+
+#[derive(Debug)]
+pub struct Body {
+	/// The function it is, or that the closure is defined in
+	pub fn_: FnId,
+	pub closure: bool,
+	/// Every variable of the body, starting with its arguments, then the variables a closure captures
+	pub locals: Vec<Local>,
+	pub args: usize,
+	pub captures: usize,
+	pub return_type: Type,
+	pub value: Expr,
+}
+
+/// A value of a primitive type, or `()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Const {
+	Empty,
+	Int(i128, IntType),
+	Float(f64, FloatType),
+	Str(Rc<str>),
+	Char(char),
+	Bool(bool),
+}
+
+impl std::fmt::Display for Const {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Empty => write!(f, "()"),
+			Self::Int(i, t) => write!(f, "{}_{}", i, t.name()),
+			Self::Float(x, FloatType::F32) => write!(f, "{:?}_f32", *x as f32),
+			Self::Float(x, FloatType::F64) => write!(f, "{:?}_f64", x),
+			Self::Str(s) => write!(f, "{:?}", s),
+			Self::Char(c) => write!(f, "{:?}", c),
+			Self::Bool(b) => write!(f, "{}", b),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct Expr {
+	pub kind: ExprKind,
+	pub type_: Type,
+	pub span: Span<()>,
+}
+
+#[derive(Debug)]
+pub enum ExprKind {
+	Const(Const),
+	Local(LocalId),
+	/// A function used as a value, with the types given to its generics
+	Fn(FnId, Vec<Type>),
+	/// A `static`, or a `const` that isn't a primitive, whose value was computed when checking
+	Global(ConstId),
+	Call(Callee, Vec<Expr>),
+	/// A builtin, given the arguments of the function it's in
+	Builtin(String, Vec<Expr>),
+	/// A value of a struct or of an enum, with the position of each field in the order they are written
+	Adt(TypeId, usize, Vec<(usize, Expr)>),
+	Tuple(Vec<Expr>),
+	Array(Vec<Expr>),
+	/// An element of a tuple or a field of a struct, by its position
+	Field(Box<Expr>, usize),
+	Cast(Box<Expr>, Primitive),
+	/// A closure, with the variables it copies from the body it's defined in
+	Closure(BodyId, Vec<LocalId>),
+	Assign(LocalId, Box<Expr>),
+	Block(Vec<Statement>, Option<Box<Expr>>),
+	/// The condition can be made of `let`s, `&&` and `||`, the variables of its `let`s are bound in the first branch
+	If(Box<Expr>, Box<Expr>, Option<Box<Expr>>),
+	/// `let PATTERN = value`, true when the value matches
+	Let(Pattern, Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Or(Box<Expr>, Box<Expr>),
+	Match(Box<Expr>, Vec<Arm>),
+	Loop(LoopId, Box<Expr>),
+	Break(LoopId, Option<Box<Expr>>),
+	Continue(LoopId),
+	Return(Box<Expr>),
+}
+
+#[derive(Debug)]
+pub enum Callee {
+	Fn(FnId, Vec<Type>),
+	/// A method of a trait, with the generics of the trait, `Self`, then the ones of the method
+	Method(TraitId, String, Vec<Type>),
+	/// A function or a closure used as a value
+	Value(Box<Expr>),
+}
+
+#[derive(Debug)]
+pub enum Statement {
+	Expr(Expr),
+	/// The pattern, its value, if any, and the block run when it doesn't match
+	Let(Pattern, Option<Expr>, Option<Box<Expr>>),
+}
+
+#[derive(Debug)]
+pub struct Arm {
+	pub pattern: Pattern,
+	pub guard: Option<Expr>,
+	pub body: Expr,
+}
+
+#[derive(Debug)]
+pub struct Pattern {
+	pub kind: PatternKind,
+	/// The type of the value it matches
+	pub type_: Type,
+}
+
+#[derive(Debug)]
+pub enum PatternKind {
+	Wildcard,
+	Binding(LocalId, Option<Box<Pattern>>),
+	Const(Const),
+	Tuple(Vec<Pattern>),
+	Array(Vec<Pattern>),
+	/// A variant, with the patterns of the fields it mentions by their position
+	Variant(TypeId, usize, Vec<(usize, Pattern)>),
+	/// Alternatives, which bind the same variables
+	Or(Vec<Pattern>),
+}
+
+/// A readable name for a function called with the given generics, like `<i64 as Add<i64>>::add`
+pub fn callee_name(db: &TypeDB, callee: &Callee) -> String {
+	match callee {
+		Callee::Fn(id, generics) => with_generics(db.fn_path(*id), generics),
+		Callee::Method(trait_, name, generics) => method_name(db, *trait_, name, generics),
+		Callee::Value(_) => unreachable!("values aren't named"),
+	}
+}
+
+/// The name of a trait method, given the generics of the trait, `Self`, then the ones of the method
+pub fn method_name(db: &TypeDB, trait_: TraitId, name: &str, generics: &[Type]) -> String {
+	let count = db.traits[trait_].def.generics.iter().flatten().count();
+	let mut path = format!("<{} as {}", generics[count], db.traits[trait_].path.join("::"));
+	if count > 0 {
+		let trait_generics: Vec<String> = generics[..count].iter().map(|t| t.to_string()).collect();
+		write!(path, "<{}>", trait_generics.join(", ")).unwrap();
+	}
+	with_generics(format!("{}>::{}", path, name), &generics[count + 1..])
+}
+
+pub fn with_generics(mut path: String, generics: &[Type]) -> String {
+	if !generics.is_empty() {
+		let generics: Vec<String> = generics.iter().map(|t| t.to_string()).collect();
+		write!(path, "::<{}>", generics.join(", ")).unwrap();
+	}
+	path
+}
+
+/// The name of a body, closures being named after the function they are defined in
+pub fn body_name(db: &TypeDB, id: BodyId, fn_: FnId, closure: bool) -> String {
+	match closure {
+		true => format!("{}::{{closure#{}}}", db.fn_path(fn_), id),
+		false => db.fn_path(fn_),
+	}
+}
+
+impl Hir<'_, '_> {
+	/// The bodies of the crate being compiled, written like Lama, with the type of every variable
+	pub fn dump(&self) -> String {
+		let mut out = String::new();
+		for (id, body) in self.bodies.iter().enumerate() {
+			if self.db.fns[body.fn_].module[0] != LOCAL_CRATE {
+				continue;
+			}
+			let mut printer = Printer {
+				hir: self,
+				body,
+				out: String::new(),
+				indent: 0,
+			};
+			printer.body(id);
+			out += &printer.out;
+		}
+		out
+	}
+}
+
+struct Printer<'h, 'a, 'b> {
+	hir: &'h Hir<'a, 'b>,
+	body: &'h Body,
+	out: String,
+	indent: usize,
+}
+
+impl Printer<'_, '_, '_> {
+	fn line(&mut self, line: &str) {
+		for _ in 0..self.indent {
+			self.out.push('\t');
+		}
+		self.out += line;
+		self.out.push('\n');
+	}
+
+	fn local(&self, id: LocalId) -> String {
+		format!("{}#{}", self.body.locals[id].name, id)
+	}
+
+	fn body(&mut self, id: BodyId) {
+		let body = self.body;
+		let args: Vec<String> = (0..body.args)
+			.map(|i| format!("{}: {}", self.local(i), body.locals[i].type_))
+			.collect();
+		let name = body_name(self.hir.db, id, body.fn_, body.closure);
+		self.line(&format!("fn {}({}) -> {} {{", name, args.join(", "), body.return_type));
+		self.indent += 1;
+		for (i, local) in body.locals.iter().enumerate().skip(body.args) {
+			let kind = match i < body.args + body.captures {
+				true => "capture",
+				false if local.mutable => "let mut",
+				false => "let",
+			};
+			self.line(&format!("{} {}: {};", kind, self.local(i), local.type_));
+		}
+		let value = self.expr(&body.value);
+		self.line(&value);
+		self.indent -= 1;
+		self.line("}");
+		self.line("");
+	}
+
+	fn list(&mut self, exprs: &[Expr]) -> String {
+		exprs.iter().map(|e| self.expr(e)).collect::<Vec<String>>().join(", ")
+	}
+
+	/// The expression, whose first line is left for the caller to indent
+	fn expr(&mut self, expr: &Expr) -> String {
+		let db = self.hir.db;
+		match &expr.kind {
+			ExprKind::Const(c) => c.to_string(),
+			ExprKind::Local(id) => self.local(*id),
+			ExprKind::Fn(id, generics) => with_generics(db.fn_path(*id), generics),
+			ExprKind::Global(id) => db.consts[*id].path.join("::"),
+			ExprKind::Call(Callee::Value(callee), args) => format!("({})({})", self.expr(callee), self.list(args)),
+			ExprKind::Call(callee, args) => format!("{}({})", callee_name(db, callee), self.list(args)),
+			ExprKind::Builtin(name, args) => format!("BUILTIN_{}({})", name, self.list(args)),
+			ExprKind::Adt(id, variant, fields) => {
+				let fields: Vec<String> = fields
+					.iter()
+					.map(|(i, value)| format!("{}: {}", i, self.expr(value)))
+					.collect();
+				adt(db.types[*id].variant_name(*variant), &fields)
+			}
+			ExprKind::Tuple(elements) if elements.len() == 1 => format!("({},)", self.expr(&elements[0])),
+			ExprKind::Tuple(elements) => format!("({})", self.list(elements)),
+			ExprKind::Array(elements) => format!("[{}]", self.list(elements)),
+			ExprKind::Field(base, index) => format!("{}.{}", self.expr(base), index),
+			ExprKind::Cast(value, target) => format!("({} as {})", self.expr(value), target.name()),
+			ExprKind::Closure(id, captures) => {
+				let captures: Vec<String> = captures.iter().map(|c| self.local(*c)).collect();
+				format!("closure#{}[{}]", id, captures.join(", "))
+			}
+			ExprKind::Assign(id, value) => format!("{} = {}", self.local(*id), self.expr(value)),
+			ExprKind::Block(statements, value) => {
+				self.indent += 1;
+				let mut lines = vec!["{".to_string()];
+				for statement in statements {
+					let line = match statement {
+						Statement::Expr(expr) => format!("{};", self.expr(expr)),
+						Statement::Let(pattern, value, else_block) => {
+							let mut line = format!("let {}", self.pattern(pattern));
+							if let Some(value) = value {
+								write!(line, " = {}", self.expr(value)).unwrap();
+							}
+							if let Some(block) = else_block {
+								write!(line, " else {}", self.expr(block)).unwrap();
+							}
+							line + ";"
+						}
+					};
+					lines.push(self.indented(line));
+				}
+				if let Some(value) = value {
+					let value = self.expr(value);
+					lines.push(self.indented(value));
+				}
+				self.indent -= 1;
+				lines.push(self.indented("}".to_string()));
+				lines.join("\n")
+			}
+			ExprKind::If(condition, then, otherwise) => {
+				let mut s = format!("if {} {}", self.expr(condition), self.expr(then));
+				if let Some(otherwise) = otherwise {
+					write!(s, " else {}", self.expr(otherwise)).unwrap();
+				}
+				s
+			}
+			ExprKind::Let(pattern, value) => format!("let {} = {}", self.pattern(pattern), self.expr(value)),
+			ExprKind::And(a, b) => format!("({} && {})", self.expr(a), self.expr(b)),
+			ExprKind::Or(a, b) => format!("({} || {})", self.expr(a), self.expr(b)),
+			ExprKind::Match(scrutinee, arms) => {
+				let mut lines = vec![format!("match {} {{", self.expr(scrutinee))];
+				self.indent += 1;
+				for arm in arms {
+					let mut line = self.pattern(&arm.pattern);
+					if let Some(guard) = &arm.guard {
+						write!(line, " if {}", self.expr(guard)).unwrap();
+					}
+					let line = format!("{} => {},", line, self.expr(&arm.body));
+					lines.push(self.indented(line));
+				}
+				self.indent -= 1;
+				lines.push(self.indented("}".to_string()));
+				lines.join("\n")
+			}
+			ExprKind::Loop(id, body) => format!("'{}: loop {}", id, self.expr(body)),
+			ExprKind::Break(id, None) => format!("break '{}", id),
+			ExprKind::Break(id, Some(value)) => format!("break '{} {}", id, self.expr(value)),
+			ExprKind::Continue(id) => format!("continue '{}", id),
+			ExprKind::Return(value) => format!("return {}", self.expr(value)),
+		}
+	}
+
+	fn indented(&self, line: String) -> String {
+		"\t".repeat(self.indent) + &line
+	}
+
+	fn pattern(&self, pattern: &Pattern) -> String {
+		let all = |patterns: &[Pattern]| patterns.iter().map(|p| self.pattern(p)).collect::<Vec<String>>().join(", ");
+		match &pattern.kind {
+			PatternKind::Wildcard => "_".to_string(),
+			PatternKind::Binding(id, None) => self.local(*id),
+			PatternKind::Binding(id, Some(subpattern)) => format!("{} @ {}", self.local(*id), self.pattern(subpattern)),
+			PatternKind::Const(c) => c.to_string(),
+			PatternKind::Tuple(patterns) if patterns.len() == 1 => format!("({},)", self.pattern(&patterns[0])),
+			PatternKind::Tuple(patterns) => format!("({})", all(patterns)),
+			PatternKind::Array(patterns) => format!("[{}]", all(patterns)),
+			PatternKind::Variant(id, variant, fields) => {
+				let fields: Vec<String> = fields
+					.iter()
+					.map(|(i, p)| format!("{}: {}", i, self.pattern(p)))
+					.collect();
+				adt(self.hir.db.types[*id].variant_name(*variant), &fields)
+			}
+			PatternKind::Or(alternatives) => alternatives
+				.iter()
+				.map(|p| self.pattern(p))
+				.collect::<Vec<String>>()
+				.join(" | "),
+		}
+	}
+}
+
+/// A struct or variant with its fields, given by their index
+fn adt(name: String, fields: &[String]) -> String {
+	match fields.is_empty() {
+		true => name,
+		false => format!("{} {{ {} }}", name, fields.join(", ")),
+	}
+}
@@ -14,6 +14,9 @@ mod modules;
 mod parser;
 
 mod checker;
+mod hir;
+mod mir;
+mod mono;
 
 mod error;
 mod fs;
@@ -26,10 +29,20 @@ use modules::ModuleTree;
 use error::Return;
 use fs::{File, Fs};
 
+/// The stack of the thread the compiler runs on, enough for the interpreter to reach
+/// [`backend::MAX_CALL_DEPTH`] nested calls
+const STACK_SIZE: usize = 1 << 30;
+
 fn main() {
-	std::process::exit(match wrapped_main() {
-		Ok(()) => 0,
-		Err(error::ReturnValue { value }) => value + 1,
+	let compiler = std::thread::Builder::new()
+		.stack_size(STACK_SIZE)
+		.spawn(wrapped_main)
+		.expect("the compiler thread can be started");
+	std::process::exit(match compiler.join() {
+		Ok(Ok(())) => 0,
+		Ok(Err(error::ReturnValue { value })) => value + 1,
+		// A bug in the compiler, already reported by the thread, exits like Rust programs that panic
+		Err(_) => 101,
 	})
 }
 
@@ -40,12 +53,11 @@ lazy_static! {
 
 fn wrapped_main() -> Return<()> {
 	let opt = options::Options::from_args();
-	println!("{:?}", opt);
 	let fs = Fs::default();
 	if let Some(p) = opt.input_file.clone() { // TODO unnecesary clone
 		let p: PathBuf = p;
 		let file = File::Path(p.clone());
-		let m = parser::parse_module(file.clone(), &fs);
+		let m = parser::parse_module(file.clone(), &fs)?;
 		let extern_libs = load_extern_libs(&fs, opt.no_std, opt.external.clone())?; // TODO unnecesary clone
 		let module = modules::build_tree(
 			&fs,
@@ -54,16 +66,27 @@ fn wrapped_main() -> Return<()> {
 			m,
 			false,
 		)?;
-		println!("{}:\n\t{:?}\n\n", file, module);
 		let module_tree = modules::ModuleTree::new(
 			extern_libs,
 			module,
 		);
+		let checked = checker::check(&module_tree)?;
+		if !opt.emit.is_empty() {
+			return emit(&checked, &opt);
+		}
+		let mir = build_mir(&checked, &opt)?;
 		let (opt, backend) = opt.into_codegen_options();
-		let _string = backend.get_codegen().codegen(module_tree, opt)?;
-
-		// println!("{:?}", module_tree.root);
-	// TODO pass module to codegen
+		let out = opt.out.clone();
+		let string = backend.get_codegen().codegen(&module_tree, &mir, opt)?;
+		if let Some(out) = out {
+			if let Err(e) = std::fs::write(&out, string) {
+				error::NonLocatedError::new(
+					error::ErrorCode::OutputFileError,
+					format!("Can't write to {}: {}", out.display(), e),
+				)
+				.display()?;
+			}
+		}
 	} else {
 		// TODO Start repl
 	}
@@ -76,22 +99,42 @@ fn wrapped_main() -> Return<()> {
 	Ok(())
 }
 
+/// Print the stages of compilation asked for with `--emit`
+fn emit(checked: &checker::Checked, opt: &options::Options) -> Return<()> {
+	for stage in &opt.emit {
+		match stage {
+			options::Emit::mono => print!("{}", mono::Mono::collect(checked, opt.lib)?.dump(&checked.db)?),
+			options::Emit::hir => print!("{}", hir::lower(checked)?.dump()),
+			options::Emit::mir => print!("{}", build_mir(checked, opt)?.dump()),
+		}
+	}
+	Ok(())
+}
+
+/// The MIR of the checked crate, optimised at the level asked for
+fn build_mir<'a, 'b>(checked: &'b checker::Checked<'a>, opt: &options::Options) -> Return<mir::Mir<'a, 'b>> {
+	let hir = hir::lower(checked)?;
+	let mut mir = mir::build(&hir, mono::Mono::collect(checked, opt.lib)?)?;
+	mir::opt::PassManager::new(opt.opt_level, opt.release).run(&mut mir)?;
+	Ok(mir)
+}
+
 // TODO build dependency tree for the checker to know what it can check first
 fn load_extern_libs(fs: &Fs, no_std: bool, extern_lib_list: Vec<(String, String)>) -> Return<HashMap<String, ModuleTree>> {
 	let mut extern_libs = HashMap::default();
-	load_extern_lib(&fs, &mut extern_libs, "core".into(), SYSROOT.join("core").join("lib.lama"), true)?;
+	load_extern_lib(fs, &mut extern_libs, "core".into(), SYSROOT.join("core").join("lib.lama"), true)?;
 	if !no_std {
-		load_extern_lib(&fs, &mut extern_libs, "std".into(), SYSROOT.join("std").join("lib.lama"), true)?;
+		load_extern_lib(fs, &mut extern_libs, "std".into(), SYSROOT.join("std").join("lib.lama"), true)?;
 	}
 	for (name, path) in extern_lib_list {
-		load_extern_lib(&fs, &mut extern_libs, name, PathBuf::from(path), false)?;
+		load_extern_lib(fs, &mut extern_libs, name, PathBuf::from(path), false)?;
 	}
 	Ok(extern_libs)
 }
 
 fn load_extern_lib(fs: &Fs, extern_libs: &mut HashMap<String, ModuleTree>, name: String, entry_point: PathBuf, allow_builtins: bool) -> Return<()> {
 	let file = File::Path(entry_point);
-	let ast = parser::parse_module(file.clone(), &fs);
+	let ast = parser::parse_module(file.clone(), fs)?;
 	let module = modules::build_tree(
 		fs,
 		&file,
@@ -99,7 +142,6 @@ fn load_extern_lib(fs: &Fs, extern_libs: &mut HashMap<String, ModuleTree>, name:
 		ast,
 		allow_builtins,
 	)?;
-	println!("{}:\n\t{:?}\n\n", file, module);
 	extern_libs.insert(name.clone(), modules::ModuleTree::new(
 		Default::default(),
 		module,
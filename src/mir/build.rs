@@ -0,0 +1,492 @@
+use crate::{
+	checker::{db::TypeDB, types::Type},
+	error::Return,
+	hir::{self, Const, ExprKind, Hir, LoopId, PatternKind},
+	mono::Mono,
+};
+
+use super::{
+	instantiate::instantiate, Block, BlockId, Body, Callee, LocalDecl, LocalId, Mir, Operand, Rvalue, Statement,
+	Terminator, RETURN_PLACE,
+};
+
+/// Build the control flow graph of every body, then a copy of it for each instance of its function
+pub fn build<'a, 'b>(hir: &Hir<'a, 'b>, mono: Mono) -> Return<Mir<'a, 'b>> {
+	let bodies: Vec<Body> = hir.bodies.iter().map(|body| Builder::new(hir.db, body).build()).collect();
+	instantiate(hir.db, &bodies, &hir.fns, mono)
+}
+
+/// A block whose terminator is set once it's done
+struct PartialBlock {
+	statements: Vec<Statement>,
+	terminator: Option<Terminator>,
+}
+
+/// A loop being built, with the blocks `continue` and `break` go to, and where its value goes
+struct Loop {
+	id: LoopId,
+	head: BlockId,
+	exit: BlockId,
+	dest: LocalId,
+}
+
+struct Builder<'a, 'b, 'h> {
+	db: &'b TypeDB<'a>,
+	body: &'h hir::Body,
+	locals: Vec<LocalDecl>,
+	/// The variables that are assigned after being bound, whose value has to be copied to be used later
+	assigned: Vec<bool>,
+	blocks: Vec<PartialBlock>,
+	/// The block statements are added to. Code after something that diverges goes to a block nothing jumps to
+	current: BlockId,
+	loops: Vec<Loop>,
+}
+
+impl<'a, 'b, 'h> Builder<'a, 'b, 'h> {
+	fn new(db: &'b TypeDB<'a>, body: &'h hir::Body) -> Self {
+		let mut locals = vec![LocalDecl {
+			name: None,
+			type_: body.return_type.clone(),
+		}];
+		locals.extend(body.locals.iter().map(|local| LocalDecl {
+			name: Some(local.name.clone()),
+			type_: local.type_.clone(),
+		}));
+		let mut assigned = vec![false; locals.len()];
+		visit(&body.value, &mut |expr| {
+			if let ExprKind::Assign(id, _) = expr.kind {
+				assigned[id + 1] = true;
+			}
+		});
+		Self {
+			db,
+			body,
+			locals,
+			assigned,
+			blocks: Vec::new(),
+			current: 0,
+			loops: Vec::new(),
+		}
+	}
+
+	fn build(mut self) -> Body {
+		self.current = self.new_block();
+		self.assign(RETURN_PLACE, &self.body.value);
+		self.terminate(Terminator::Return);
+		let blocks = remove_unreachable(self.blocks);
+		Body {
+			fn_: self.body.fn_,
+			closure: self.body.closure,
+			locals: self.locals,
+			args: self.body.args,
+			captures: self.body.captures,
+			blocks,
+		}
+	}
+
+	fn new_block(&mut self) -> BlockId {
+		self.blocks.push(PartialBlock {
+			statements: Vec::new(),
+			terminator: None,
+		});
+		self.blocks.len() - 1
+	}
+
+	fn new_temp(&mut self, type_: &Type) -> LocalId {
+		self.locals.push(LocalDecl {
+			name: None,
+			type_: type_.clone(),
+		});
+		self.locals.len() - 1
+	}
+
+	fn push(&mut self, place: LocalId, value: Rvalue) {
+		self.blocks[self.current].statements.push(Statement { place, value });
+	}
+
+	/// End the current block, the code after it goes to a new one
+	fn terminate(&mut self, terminator: Terminator) {
+		self.blocks[self.current].terminator = Some(terminator);
+		self.current = self.new_block();
+	}
+
+	/// End the current block with a jump to `target`, which becomes the current block
+	fn goto(&mut self, target: BlockId) {
+		self.blocks[self.current].terminator = Some(Terminator::Goto(target));
+		self.current = target;
+	}
+
+	/// The MIR local of a HIR variable, after the return place
+	fn local(id: hir::LocalId) -> LocalId {
+		id + 1
+	}
+
+	/// The value of an expression as an operand, computed in a temporary if it isn't a constant or a variable
+	fn operand(&mut self, expr: &hir::Expr) -> Operand {
+		match &expr.kind {
+			ExprKind::Const(c) => Operand::Const(c.clone()),
+			ExprKind::Local(id) if !self.assigned[Self::local(*id)] => Operand::Local(Self::local(*id)),
+			_ => {
+				let temp = self.new_temp(&expr.type_);
+				self.assign(temp, expr);
+				Operand::Local(temp)
+			}
+		}
+	}
+
+	fn operands<'e>(&mut self, exprs: impl IntoIterator<Item = &'e hir::Expr>) -> Vec<Operand> {
+		exprs.into_iter().map(|e| self.operand(e)).collect()
+	}
+
+	/// Run an expression whose value isn't used
+	fn effect(&mut self, expr: &hir::Expr) {
+		match &expr.kind {
+			ExprKind::Const(_) | ExprKind::Local(_) | ExprKind::Fn(..) | ExprKind::Global(_) => (),
+			ExprKind::Assign(id, value) => self.assign(Self::local(*id), value),
+			_ => {
+				let temp = self.new_temp(&expr.type_);
+				self.assign(temp, expr);
+			}
+		}
+	}
+
+	/// Put the value of the expression in `dest`
+	fn assign(&mut self, dest: LocalId, expr: &hir::Expr) {
+		let value = match &expr.kind {
+			ExprKind::Const(c) => Rvalue::Use(Operand::Const(c.clone())),
+			ExprKind::Local(id) => Rvalue::Use(Operand::Local(Self::local(*id))),
+			ExprKind::Fn(id, generics) => Rvalue::Fn(*id, generics.clone()),
+			ExprKind::Global(id) => Rvalue::Global(*id),
+			ExprKind::Call(callee, args) => {
+				let callee = match callee {
+					hir::Callee::Fn(id, generics) => Callee::Fn(*id, generics.clone()),
+					hir::Callee::Method(trait_, name, generics) => Callee::Method(*trait_, name.clone(), generics.clone()),
+					hir::Callee::Value(callee) => Callee::Value(self.operand(callee)),
+				};
+				let args = self.operands(args);
+				return self.call(dest, callee, args, expr);
+			}
+			ExprKind::Builtin(name, args) => {
+				let args = self.operands(args);
+				return self.call(dest, Callee::Builtin(name.clone()), args, expr);
+			}
+			ExprKind::Adt(id, variant, fields) => {
+				// The fields are evaluated in the order they are written
+				let mut operands: Vec<(usize, Operand)> = fields.iter().map(|(i, e)| (*i, self.operand(e))).collect();
+				operands.sort_by_key(|(i, _)| *i);
+				Rvalue::Adt(*id, *variant, operands.into_iter().map(|(_, o)| o).collect())
+			}
+			ExprKind::Tuple(elements) => Rvalue::Tuple(self.operands(elements)),
+			ExprKind::Array(elements) => Rvalue::Array(self.operands(elements)),
+			ExprKind::Field(base, index) => Rvalue::Field(self.operand(base), *index),
+			ExprKind::Cast(value, target) => Rvalue::Cast(self.operand(value), *target),
+			ExprKind::Closure(id, captures) => {
+				let captures = captures.iter().map(|c| Operand::Local(Self::local(*c))).collect();
+				Rvalue::Closure(*id, captures)
+			}
+			ExprKind::Assign(id, value) => {
+				self.assign(Self::local(*id), value);
+				Rvalue::Use(Operand::Const(Const::Empty))
+			}
+			ExprKind::Block(statements, value) => {
+				for statement in statements {
+					self.statement(statement);
+				}
+				match value {
+					Some(value) => return self.assign(dest, value),
+					None => Rvalue::Use(Operand::Const(Const::Empty)),
+				}
+			}
+			ExprKind::If(condition, then, otherwise) => {
+				let (then_block, else_block, join) = (self.new_block(), self.new_block(), self.new_block());
+				self.condition(condition, then_block, else_block);
+				self.current = then_block;
+				self.assign(dest, then);
+				self.goto(join);
+				self.current = else_block;
+				match otherwise {
+					Some(otherwise) => self.assign(dest, otherwise),
+					None => self.push(dest, Rvalue::Use(Operand::Const(Const::Empty))),
+				}
+				self.goto(join);
+				return;
+			}
+			ExprKind::Let(..) | ExprKind::And(..) | ExprKind::Or(..) => {
+				let (then_block, else_block, join) = (self.new_block(), self.new_block(), self.new_block());
+				self.condition(expr, then_block, else_block);
+				for (block, value) in [(then_block, true), (else_block, false)] {
+					self.current = block;
+					self.push(dest, Rvalue::Use(Operand::Const(Const::Bool(value))));
+					self.goto(join);
+				}
+				return;
+			}
+			ExprKind::Match(scrutinee, arms) => return self.match_(dest, scrutinee, arms),
+			ExprKind::Loop(id, body) => {
+				let (head, exit) = (self.new_block(), self.new_block());
+				self.goto(head);
+				self.loops.push(Loop {
+					id: *id,
+					head,
+					exit,
+					dest,
+				});
+				self.effect(body);
+				self.goto(head);
+				self.loops.pop();
+				self.current = exit;
+				return;
+			}
+			ExprKind::Break(id, value) => {
+				let target = self.loops.iter().rev().find(|l| l.id == *id).unwrap();
+				let (exit, loop_dest) = (target.exit, target.dest);
+				match value {
+					Some(value) => self.assign(loop_dest, value),
+					None => self.push(loop_dest, Rvalue::Use(Operand::Const(Const::Empty))),
+				}
+				self.goto(exit);
+				self.current = self.new_block();
+				return;
+			}
+			ExprKind::Continue(id) => {
+				let head = self.loops.iter().rev().find(|l| l.id == *id).unwrap().head;
+				self.goto(head);
+				self.current = self.new_block();
+				return;
+			}
+			ExprKind::Return(value) => {
+				self.assign(RETURN_PLACE, value);
+				self.terminate(Terminator::Return);
+				return;
+			}
+		};
+		self.push(dest, value);
+	}
+
+	fn call(&mut self, dest: LocalId, callee: Callee, args: Vec<Operand>, expr: &hir::Expr) {
+		let target = self.new_block();
+		self.terminate(Terminator::Call {
+			callee,
+			args,
+			dest,
+			target,
+			span: expr.span.clone(),
+		});
+		self.current = target;
+		// Functions that give back `!` never do
+		if expr.type_ == Type::Never {
+			self.terminate(Terminator::Unreachable);
+		}
+	}
+
+	fn statement(&mut self, statement: &hir::Statement) {
+		match statement {
+			hir::Statement::Expr(expr) => self.effect(expr),
+			hir::Statement::Let(_, None, _) => (),
+			hir::Statement::Let(hir::Pattern {
+				kind: PatternKind::Binding(id, None),
+				..
+			}, Some(value), None) => self.assign(Self::local(*id), value),
+			hir::Statement::Let(pattern, Some(value), else_block) => {
+				let value = self.operand(value);
+				let fail = self.new_block();
+				self.pattern(pattern, value, fail);
+				let next = self.current;
+				self.current = fail;
+				// Without an `else`, the pattern always matches
+				if let Some(else_block) = else_block {
+					self.effect(else_block);
+				}
+				self.terminate(Terminator::Unreachable);
+				self.current = next;
+			}
+		}
+	}
+
+	/// Jump to `then` if the condition holds, binding the variables of its `let`s, or to `otherwise`
+	fn condition(&mut self, condition: &hir::Expr, then: BlockId, otherwise: BlockId) {
+		match &condition.kind {
+			ExprKind::Let(pattern, value) => {
+				let value = self.operand(value);
+				self.pattern(pattern, value, otherwise);
+				self.goto(then);
+			}
+			ExprKind::And(a, b) => {
+				let next = self.new_block();
+				self.condition(a, next, otherwise);
+				self.current = next;
+				self.condition(b, then, otherwise);
+			}
+			ExprKind::Or(a, b) => {
+				let next = self.new_block();
+				self.condition(a, then, next);
+				self.current = next;
+				self.condition(b, then, otherwise);
+			}
+			_ => {
+				let value = self.operand(condition);
+				self.terminate(Terminator::Switch(value, vec![(Const::Bool(false), otherwise)], then));
+			}
+		}
+	}
+
+	/// Run the first arm whose pattern matches and whose guard holds
+	fn match_(&mut self, dest: LocalId, scrutinee: &hir::Expr, arms: &[hir::Arm]) {
+		let value = self.operand(scrutinee);
+		let join = self.new_block();
+		for arm in arms {
+			let next = self.new_block();
+			self.pattern(&arm.pattern, value.clone(), next);
+			if let Some(guard) = &arm.guard {
+				let body = self.new_block();
+				self.condition(guard, body, next);
+				self.current = body;
+			}
+			self.assign(dest, &arm.body);
+			self.goto(join);
+			self.current = next;
+		}
+		// The checker made sure an arm matches
+		self.terminate(Terminator::Unreachable);
+		self.current = join;
+	}
+
+	/// Bind the variables of the pattern to the parts of the value, jumping to `fail` if it doesn't match
+	fn pattern(&mut self, pattern: &hir::Pattern, value: Operand, fail: BlockId) {
+		match &pattern.kind {
+			PatternKind::Wildcard => (),
+			PatternKind::Binding(id, subpattern) => {
+				if let Some(subpattern) = subpattern {
+					self.pattern(subpattern, value.clone(), fail);
+				}
+				self.push(Self::local(*id), Rvalue::Use(value));
+			}
+			PatternKind::Const(c) => {
+				let next = self.new_block();
+				self.blocks[self.current].terminator = Some(Terminator::Switch(value, vec![(c.clone(), next)], fail));
+				self.current = next;
+			}
+			PatternKind::Tuple(patterns) | PatternKind::Array(patterns) => {
+				for (i, p) in patterns.iter().enumerate() {
+					self.field(p, &value, i, fail);
+				}
+			}
+			PatternKind::Variant(id, variant, fields) => {
+				// Structs and enums with a single variant are always the right one
+				if self.db.types[*id].variants.len() > 1 {
+					let next = self.new_block();
+					self.blocks[self.current].terminator =
+						Some(Terminator::SwitchVariant(value.clone(), vec![(*variant, next)], fail));
+					self.current = next;
+				}
+				for (i, p) in fields {
+					self.field(p, &value, *i, fail);
+				}
+			}
+			PatternKind::Or(alternatives) => {
+				let matched = self.new_block();
+				for (i, alternative) in alternatives.iter().enumerate() {
+					let next = match i == alternatives.len() - 1 {
+						true => fail,
+						false => self.new_block(),
+					};
+					self.pattern(alternative, value.clone(), next);
+					self.goto(matched);
+					self.current = next;
+				}
+				self.current = matched;
+			}
+		}
+	}
+
+	/// Match a field of the value against its pattern
+	fn field(&mut self, pattern: &hir::Pattern, value: &Operand, index: usize, fail: BlockId) {
+		match &pattern.kind {
+			PatternKind::Wildcard => (),
+			PatternKind::Binding(id, None) => self.push(Self::local(*id), Rvalue::Field(value.clone(), index)),
+			_ => {
+				let temp = self.new_temp(&pattern.type_);
+				self.push(temp, Rvalue::Field(value.clone(), index));
+				self.pattern(pattern, Operand::Local(temp), fail);
+			}
+		}
+	}
+}
+
+/// Call `f` on the expression and every expression in it, except in the bodies of closures
+pub fn visit(expr: &hir::Expr, f: &mut impl FnMut(&hir::Expr)) {
+	f(expr);
+	let mut all = |exprs: &mut dyn Iterator<Item = &hir::Expr>| exprs.for_each(|e| visit(e, f));
+	match &expr.kind {
+		ExprKind::Const(_)
+		| ExprKind::Local(_)
+		| ExprKind::Fn(..)
+		| ExprKind::Global(_)
+		| ExprKind::Closure(..)
+		| ExprKind::Continue(_)
+		| ExprKind::Break(_, None) => (),
+		ExprKind::Call(callee, args) => {
+			if let hir::Callee::Value(callee) = callee {
+				all(&mut std::iter::once(&**callee));
+			}
+			all(&mut args.iter());
+		}
+		ExprKind::Builtin(_, args) | ExprKind::Tuple(args) | ExprKind::Array(args) => all(&mut args.iter()),
+		ExprKind::Adt(_, _, fields) => all(&mut fields.iter().map(|(_, e)| e)),
+		ExprKind::Field(e, _)
+		| ExprKind::Cast(e, _)
+		| ExprKind::Assign(_, e)
+		| ExprKind::Let(_, e)
+		| ExprKind::Loop(_, e)
+		| ExprKind::Break(_, Some(e))
+		| ExprKind::Return(e) => all(&mut std::iter::once(&**e)),
+		ExprKind::Block(statements, value) => {
+			for statement in statements {
+				match statement {
+					hir::Statement::Expr(e) => all(&mut std::iter::once(e)),
+					hir::Statement::Let(_, value, else_block) => all(&mut value.iter().chain(else_block.as_deref())),
+				}
+			}
+			all(&mut value.iter().map(|e| &**e));
+		}
+		ExprKind::If(condition, then, otherwise) => {
+			all(&mut std::iter::once(&**condition).chain([&**then]).chain(otherwise.as_deref()))
+		}
+		ExprKind::And(a, b) | ExprKind::Or(a, b) => all(&mut std::iter::once(&**a).chain([&**b])),
+		ExprKind::Match(scrutinee, arms) => {
+			all(&mut std::iter::once(&**scrutinee));
+			for arm in arms {
+				all(&mut arm.guard.iter().chain([&arm.body]));
+			}
+		}
+	}
+}
+
+/// Keep the blocks that can be reached from the first one, numbered in the order a depth-first walk reaches them
+fn remove_unreachable(mut blocks: Vec<PartialBlock>) -> Vec<Block> {
+	let mut ids: Vec<Option<BlockId>> = vec![None; blocks.len()];
+	let mut order = Vec::new();
+	let mut stack = vec![0];
+	while let Some(id) = stack.pop() {
+		if ids[id].is_none() {
+			ids[id] = Some(order.len());
+			order.push(id);
+			let terminator = blocks[id].terminator.as_ref().expect("reachable blocks are terminated");
+			stack.extend(terminator.successors().into_iter().rev());
+		}
+	}
+	order
+		.into_iter()
+		.map(|id| {
+			let block = &mut blocks[id];
+			let mut terminator = block.terminator.take().unwrap();
+			for target in terminator.successors_mut() {
+				*target = ids[*target].unwrap();
+			}
+			Block {
+				statements: std::mem::take(&mut block.statements),
+				terminator,
+			}
+		})
+		.collect()
+}
@@ -0,0 +1,111 @@
+//! Monomorphisation of the MIR: each instance the program needs gets a copy of the body of its function, and of
+//! the closures it defines, with the types given to the generics. Every call is then to an instance, trait
+//! methods being resolved to the method of the impl for the types they are called with
+
+use hashbrown::HashMap;
+
+use crate::{
+	checker::{
+		db::{FnId, TypeDB},
+		infer::InferCtx,
+		types::Type,
+	},
+	error::Return,
+	hir::BodyId,
+	mono::{resolve_method, InstanceId, Mono},
+};
+
+use super::{Body, Callee, Mir, Rvalue, Terminator};
+
+/// The MIR of the instances of `mono`, given the generic bodies of the functions, by [`FnId`] in `fns`
+pub fn instantiate<'a, 'b>(db: &'b TypeDB<'a>, generic: &[Body], fns: &[BodyId], mono: Mono) -> Return<Mir<'a, 'b>> {
+	let mut instantiator = Instantiator {
+		db,
+		generic,
+		mono,
+		closures: Vec::new(),
+	};
+	let mut bodies = Vec::new();
+	// Instantiating a body can find new instances, which get instantiated in turn
+	while bodies.len() < instantiator.mono.instances.len() {
+		let instance = &instantiator.mono.instances[bodies.len()];
+		let fn_ = instance.fn_;
+		let (_, sig) = db.fn_signature(fn_)?;
+		let map = sig.generics.into_iter().zip(instance.generics.iter().cloned()).collect();
+		bodies.push(instantiator.body(fns[fn_], &map)?);
+	}
+	// The closures come after the instances, whose number is only known now
+	let offset = bodies.len();
+	bodies.append(&mut instantiator.closures);
+	for statement in bodies.iter_mut().flat_map(|b| &mut b.blocks).flat_map(|b| &mut b.statements) {
+		if let Rvalue::Closure(id, _) = &mut statement.value {
+			*id += offset;
+		}
+	}
+	Ok(Mir {
+		db,
+		bodies,
+		mono: instantiator.mono,
+	})
+}
+
+struct Instantiator<'a, 'b, 'g> {
+	db: &'b TypeDB<'a>,
+	generic: &'g [Body],
+	mono: Mono,
+	/// The instantiated closures, numbered from 0 until the instances are all known
+	closures: Vec<Body>,
+}
+
+impl Instantiator<'_, '_, '_> {
+	/// A copy of a generic body whose generics are given by `map`
+	fn body(&mut self, id: BodyId, map: &HashMap<String, Type>) -> Return<Body> {
+		let mut body = self.generic[id].clone();
+		for local in &mut body.locals {
+			local.type_ = self.type_(&local.type_, map)?;
+		}
+		for block in &mut body.blocks {
+			for statement in &mut block.statements {
+				match &mut statement.value {
+					Rvalue::Fn(fn_, generics) => {
+						let instance = self.instance(*fn_, generics, map)?;
+						statement.value = Rvalue::Instance(instance);
+					}
+					Rvalue::Closure(closure, _) => {
+						let instantiated = self.body(*closure, map)?;
+						self.closures.push(instantiated);
+						*closure = self.closures.len() - 1;
+					}
+					_ => (),
+				}
+			}
+			if let Terminator::Call { callee, .. } = &mut block.terminator {
+				let instance = match callee {
+					Callee::Fn(fn_, generics) => self.instance(*fn_, generics, map)?,
+					Callee::Method(trait_, name, generics) => {
+						let generics = self.types(generics, map)?;
+						let (fn_, generics) = resolve_method(self.db, *trait_, name, generics)?
+							.expect("the checker made sure the self type implements the trait");
+						self.mono.instance(fn_, generics)
+					}
+					Callee::Instance(_) | Callee::Value(_) | Callee::Builtin(_) => continue,
+				};
+				*callee = Callee::Instance(instance);
+			}
+		}
+		Ok(body)
+	}
+
+	fn instance(&mut self, fn_: FnId, generics: &[Type], map: &HashMap<String, Type>) -> Return<InstanceId> {
+		let generics = self.types(generics, map)?;
+		Ok(self.mono.instance(fn_, generics))
+	}
+
+	fn types(&self, types: &[Type], map: &HashMap<String, Type>) -> Return<Vec<Type>> {
+		types.iter().map(|t| self.type_(t, map)).collect()
+	}
+
+	fn type_(&self, type_: &Type, map: &HashMap<String, Type>) -> Return<Type> {
+		self.db.normalize(&mut InferCtx::default(), &[], &type_.without_vars().subst(map))
+	}
+}
@@ -0,0 +1,343 @@
+//! The control flow graph each body of the HIR is built into: basic blocks of assignments to
+//! local variables, ended by a jump, a switch, a call or a return. Each instance of a function gets its own
+//! copy, with the types given to its generics, which is what the backends run
+
+use std::fmt::Write;
+
+use crate::{
+	checker::{
+		db::{ConstId, FnId, TraitId, TypeDB, TypeId, LOCAL_CRATE},
+		types::{Primitive, Type},
+	},
+	hir::{body_name, method_name, with_generics, BodyId, Const},
+	mono::{InstanceId, Mono},
+	span::Span,
+};
+
+mod build;
+mod instantiate;
+pub mod opt;
+
+pub use build::build;
+
+pub type LocalId = usize;
+pub type BlockId = usize;
+
+/// The local holding the value a body gives back
+pub const RETURN_PLACE: LocalId = 0;
+
+pub struct Mir<'a, 'b> {
+	pub db: &'b TypeDB<'a>,
+	/// The body of each instance, by [`InstanceId`], then the bodies of the closures they define
+	pub bodies: Vec<Body>,
+	pub mono: Mono,
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalDecl {
+	/// The name of the variable, temporaries have none
+	pub name: Option<String>,
+	pub type_: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct Body {
+	/// The function it is, or that the closure is defined in
+	pub fn_: FnId,
+	pub closure: bool,
+	/// The return place, the arguments, the variables a closure captures, then the others
+	pub locals: Vec<LocalDecl>,
+	pub args: usize,
+	pub captures: usize,
+	/// The blocks, which run from the first one
+	pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+	pub statements: Vec<Statement>,
+	pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+	pub place: LocalId,
+	pub value: Rvalue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+	Local(LocalId),
+	Const(Const),
+}
+
+#[derive(Debug, Clone)]
+pub enum Rvalue {
+	Use(Operand),
+	/// A function used as a value, with the types given to its generics, until it's instantiated
+	Fn(FnId, Vec<Type>),
+	/// An instance of a function used as a value
+	Instance(InstanceId),
+	/// The value of a `static`, or of a `const` that isn't a primitive
+	Global(ConstId),
+	Tuple(Vec<Operand>),
+	Array(Vec<Operand>),
+	/// A value of a struct or of an enum, with its fields in the order they are declared
+	Adt(TypeId, usize, Vec<Operand>),
+	/// An element of a tuple or an array, or a field of a struct or of the variant an enum is known to be
+	Field(Operand, usize),
+	Cast(Operand, Primitive),
+	/// A closure, with the values of the variables it captures
+	Closure(BodyId, Vec<Operand>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+	Goto(BlockId),
+	/// Jump to the block of the first constant equal to the value, or to the last one
+	Switch(Operand, Vec<(Const, BlockId)>, BlockId),
+	/// Jump to the block of the variant of the enum, or to the last one
+	SwitchVariant(Operand, Vec<(usize, BlockId)>, BlockId),
+	/// Call the function, putting its result in `dest` before going to `target`
+	Call {
+		callee: Callee,
+		args: Vec<Operand>,
+		dest: LocalId,
+		target: BlockId,
+		span: Span<()>,
+	},
+	Return,
+	Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub enum Callee {
+	/// A function with the types given to its generics, until it's instantiated
+	Fn(FnId, Vec<Type>),
+	/// A method of a trait, with the generics of the trait, `Self`, then the ones of the method,
+	/// until the method of the impl it runs is instantiated
+	Method(TraitId, String, Vec<Type>),
+	Instance(InstanceId),
+	/// A function or a closure used as a value
+	Value(Operand),
+	/// A builtin of the libraries, like `ADD_INT`
+	Builtin(String),
+}
+
+impl Terminator {
+	pub fn successors(&self) -> Vec<BlockId> {
+		match self {
+			Self::Goto(target) | Self::Call { target, .. } => vec![*target],
+			Self::Switch(_, targets, otherwise) => targets.iter().map(|(_, b)| *b).chain([*otherwise]).collect(),
+			Self::SwitchVariant(_, targets, otherwise) => targets.iter().map(|(_, b)| *b).chain([*otherwise]).collect(),
+			Self::Return | Self::Unreachable => Vec::new(),
+		}
+	}
+
+	pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+		match self {
+			Self::Goto(target) | Self::Call { target, .. } => vec![target],
+			Self::Switch(_, targets, otherwise) => targets.iter_mut().map(|(_, b)| b).chain([otherwise]).collect(),
+			Self::SwitchVariant(_, targets, otherwise) => targets.iter_mut().map(|(_, b)| b).chain([otherwise]).collect(),
+			Self::Return | Self::Unreachable => Vec::new(),
+		}
+	}
+}
+
+impl Rvalue {
+	pub fn operands(&self) -> Vec<&Operand> {
+		match self {
+			Self::Use(operand) | Self::Field(operand, _) | Self::Cast(operand, _) => vec![operand],
+			Self::Tuple(operands) | Self::Array(operands) | Self::Adt(_, _, operands) | Self::Closure(_, operands) => {
+				operands.iter().collect()
+			}
+			Self::Fn(..) | Self::Instance(_) | Self::Global(_) => Vec::new(),
+		}
+	}
+
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Self::Use(operand) | Self::Field(operand, _) | Self::Cast(operand, _) => vec![operand],
+			Self::Tuple(operands) | Self::Array(operands) | Self::Adt(_, _, operands) | Self::Closure(_, operands) => {
+				operands.iter_mut().collect()
+			}
+			Self::Fn(..) | Self::Instance(_) | Self::Global(_) => Vec::new(),
+		}
+	}
+}
+
+impl Terminator {
+	pub fn operands(&self) -> Vec<&Operand> {
+		match self {
+			Self::Switch(operand, ..) | Self::SwitchVariant(operand, ..) => vec![operand],
+			Self::Call { callee, args, .. } => match callee {
+				Callee::Value(callee) => std::iter::once(callee).chain(args.iter()).collect(),
+				_ => args.iter().collect(),
+			},
+			Self::Goto(_) | Self::Return | Self::Unreachable => Vec::new(),
+		}
+	}
+
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		match self {
+			Self::Switch(operand, ..) | Self::SwitchVariant(operand, ..) => vec![operand],
+			Self::Call { callee, args, .. } => match callee {
+				Callee::Value(callee) => std::iter::once(callee).chain(args.iter_mut()).collect(),
+				_ => args.iter_mut().collect(),
+			},
+			Self::Goto(_) | Self::Return | Self::Unreachable => Vec::new(),
+		}
+	}
+}
+
+impl Body {
+	/// Every operand of the body, in its statements and terminators
+	pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+		let mut operands = Vec::new();
+		for block in &mut self.blocks {
+			for statement in &mut block.statements {
+				operands.extend(statement.value.operands_mut());
+			}
+			operands.extend(block.terminator.operands_mut());
+		}
+		operands
+	}
+
+	/// How many times each local is given a value, the arguments and captured variables being given one on entry
+	pub fn definitions(&self) -> Vec<usize> {
+		let mut counts = vec![0; self.locals.len()];
+		for count in &mut counts[RETURN_PLACE + 1..=self.args + self.captures] {
+			*count = 1;
+		}
+		for block in &self.blocks {
+			for statement in &block.statements {
+				counts[statement.place] += 1;
+			}
+			if let Terminator::Call { dest, .. } = block.terminator {
+				counts[dest] += 1;
+			}
+		}
+		counts
+	}
+
+	/// How many times each local is used as an operand
+	pub fn uses(&self) -> Vec<usize> {
+		let mut counts = vec![0; self.locals.len()];
+		for block in &self.blocks {
+			let statements = block.statements.iter().flat_map(|s| s.value.operands());
+			for operand in statements.chain(block.terminator.operands()) {
+				if let Operand::Local(id) = operand {
+					counts[*id] += 1;
+				}
+			}
+		}
+		counts
+	}
+}
+
+impl std::fmt::Display for Operand {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Local(id) => write!(f, "_{}", id),
+			Self::Const(c) => write!(f, "const {}", c),
+		}
+	}
+}
+
+impl Mir<'_, '_> {
+	/// The bodies of the crate being compiled, like rustc shows its MIR
+	pub fn dump(&self) -> String {
+		let mut out = String::new();
+		for (id, body) in self.bodies.iter().enumerate() {
+			if self.db.fns[body.fn_].module[0] == LOCAL_CRATE {
+				self.dump_body(&mut out, id, body).unwrap();
+			}
+		}
+		out
+	}
+
+	fn dump_body(&self, out: &mut String, id: BodyId, body: &Body) -> std::fmt::Result {
+		let db = self.db;
+		let args: Vec<String> = (1..=body.args).map(|i| format!("_{}: {}", i, body.locals[i].type_)).collect();
+		let name = match body.closure {
+			true => body_name(db, id, body.fn_, true),
+			false => self.mono.name(db, id),
+		};
+		writeln!(out, "fn {}({}) -> {} {{", name, args.join(", "), body.locals[RETURN_PLACE].type_)?;
+		for (i, local) in body.locals.iter().enumerate() {
+			if i == RETURN_PLACE || i > body.args {
+				let kind = if i > body.args && i <= body.args + body.captures { "capture" } else { "let" };
+				write!(out, "\t{} _{}: {};", kind, i, local.type_)?;
+				match &local.name {
+					Some(name) => writeln!(out, " // {}", name)?,
+					None => writeln!(out)?,
+				}
+			}
+		}
+		for (i, block) in body.blocks.iter().enumerate() {
+			writeln!(out, "\n\tbb{}: {{", i)?;
+			for statement in &block.statements {
+				writeln!(out, "\t\t_{} = {};", statement.place, self.rvalue(&statement.value))?;
+			}
+			writeln!(out, "\t\t{};", self.terminator(&block.terminator))?;
+			writeln!(out, "\t}}")?;
+		}
+		writeln!(out, "}}\n")
+	}
+
+	fn rvalue(&self, value: &Rvalue) -> String {
+		let list = |operands: &[Operand]| operands.iter().map(|o| o.to_string()).collect::<Vec<String>>().join(", ");
+		match value {
+			Rvalue::Use(operand) => operand.to_string(),
+			Rvalue::Fn(id, generics) => with_generics(self.db.fn_path(*id), generics),
+			Rvalue::Instance(id) => self.mono.name(self.db, *id),
+			Rvalue::Global(id) => self.db.consts[*id].path.join("::"),
+			Rvalue::Tuple(operands) if operands.len() == 1 => format!("({},)", operands[0]),
+			Rvalue::Tuple(operands) => format!("({})", list(operands)),
+			Rvalue::Array(operands) => format!("[{}]", list(operands)),
+			Rvalue::Adt(id, variant, operands) => {
+				let name = self.db.types[*id].variant_name(*variant);
+				match operands.is_empty() {
+					true => name,
+					false => format!("{}({})", name, list(operands)),
+				}
+			}
+			Rvalue::Field(operand, index) => format!("{}.{}", operand, index),
+			Rvalue::Cast(operand, target) => format!("{} as {}", operand, target.name()),
+			Rvalue::Closure(id, captures) => format!("closure#{}({})", id, list(captures)),
+		}
+	}
+
+	fn terminator(&self, terminator: &Terminator) -> String {
+		match terminator {
+			Terminator::Goto(target) => format!("goto -> bb{}", target),
+			Terminator::Switch(operand, targets, otherwise) => {
+				let targets: Vec<String> = targets.iter().map(|(c, b)| format!("{}: bb{}", c, b)).collect();
+				format!("switchInt({}) -> [{}, otherwise: bb{}]", operand, targets.join(", "), otherwise)
+			}
+			Terminator::SwitchVariant(operand, targets, otherwise) => {
+				let targets: Vec<String> = targets.iter().map(|(v, b)| format!("{}: bb{}", v, b)).collect();
+				format!("switchVariant({}) -> [{}, otherwise: bb{}]", operand, targets.join(", "), otherwise)
+			}
+			Terminator::Call {
+				callee,
+				args,
+				dest,
+				target,
+				..
+			} => {
+				let name = match callee {
+					Callee::Fn(id, generics) => with_generics(self.db.fn_path(*id), generics),
+					Callee::Method(trait_, name, generics) => method_name(self.db, *trait_, name, generics),
+					Callee::Instance(id) => self.mono.name(self.db, *id),
+					Callee::Value(operand) => format!("({})", operand),
+					Callee::Builtin(name) => format!("BUILTIN_{}", name),
+				};
+				let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+				format!("_{} = {}({}) -> bb{}", dest, name, args.join(", "), target)
+			}
+			Terminator::Return => "return".to_string(),
+			Terminator::Unreachable => "unreachable".to_string(),
+		}
+	}
+}
@@ -0,0 +1,86 @@
+use crate::{
+	backend::interpreter::{fold_builtin, fold_cast},
+	error::Return,
+	hir::Const,
+	mir::{Body, Callee, Mir, Operand, Rvalue, Statement, Terminator, RETURN_PLACE},
+};
+
+use super::Pass;
+
+/// Replaces the locals only ever given a constant with it, and computes the builtins and casts of constants,
+/// like `BUILTIN_ADD_I64(const 1_i64, const 2_i64)`. Those that would panic are left to panic when run
+pub struct ConstFold {
+	pub release: bool,
+}
+
+impl Pass for ConstFold {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		for body in &mut mir.bodies {
+			while self.fold(body) {}
+		}
+		Ok(())
+	}
+}
+
+impl ConstFold {
+	/// Fold what can be, giving back whether anything was
+	fn fold(&self, body: &mut Body) -> bool {
+		let constants = constants(body);
+		let mut changed = false;
+		for operand in body.operands_mut() {
+			if let Operand::Local(id) = operand {
+				if let Some(c) = &constants[*id] {
+					*operand = Operand::Const(c.clone());
+					changed = true;
+				}
+			}
+		}
+		for block in &mut body.blocks {
+			for statement in &mut block.statements {
+				if let Rvalue::Cast(Operand::Const(c), target) = &statement.value {
+					statement.value = Rvalue::Use(Operand::Const(fold_cast(c, *target)));
+					changed = true;
+				}
+			}
+			if let Terminator::Call {
+				callee: Callee::Builtin(name),
+				args,
+				dest,
+				target,
+				..
+			} = &block.terminator
+			{
+				let args: Option<Vec<Const>> = args
+					.iter()
+					.map(|arg| match arg {
+						Operand::Const(c) => Some(c.clone()),
+						Operand::Local(_) => None,
+					})
+					.collect();
+				if let Some(Ok(value)) = args.and_then(|args| fold_builtin(name, &args, self.release)) {
+					block.statements.push(Statement {
+						place: *dest,
+						value: Rvalue::Use(Operand::Const(value)),
+					});
+					block.terminator = Terminator::Goto(*target);
+					changed = true;
+				}
+			}
+		}
+		changed
+	}
+}
+
+/// The constant each local always holds, if it's given a value once, and that value is a constant
+fn constants(body: &Body) -> Vec<Option<Const>> {
+	let definitions = body.definitions();
+	let mut constants = vec![None; body.locals.len()];
+	for statement in body.blocks.iter().flat_map(|b| &b.statements) {
+		if let Rvalue::Use(Operand::Const(c)) = &statement.value {
+			if statement.place != RETURN_PLACE && definitions[statement.place] == 1 {
+				constants[statement.place] = Some(c.clone());
+			}
+		}
+	}
+	constants
+}
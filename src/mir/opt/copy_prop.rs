@@ -0,0 +1,52 @@
+use crate::{
+	error::Return,
+	mir::{Body, LocalId, Mir, Operand, Rvalue, RETURN_PLACE},
+};
+
+use super::Pass;
+
+/// Uses the local a copy was made of instead of the copy, when both are only given a value once.
+/// Building the MIR scopes locals so that the copy can't outlive the value it was made of
+pub struct CopyProp;
+
+impl Pass for CopyProp {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		for body in &mut mir.bodies {
+			propagate(body);
+		}
+		Ok(())
+	}
+}
+
+fn propagate(body: &mut Body) {
+	let definitions = body.definitions();
+	let mut sources: Vec<Option<LocalId>> = vec![None; body.locals.len()];
+	for statement in body.blocks.iter().flat_map(|b| &b.statements) {
+		if let Rvalue::Use(Operand::Local(source)) = statement.value {
+			let copy = statement.place;
+			if copy != RETURN_PLACE && source != RETURN_PLACE && definitions[copy] == 1 && definitions[source] == 1 {
+				sources[copy] = Some(source);
+			}
+		}
+	}
+	// A copy of a copy uses the first local. Locals read before being given a value can make cycles, left alone
+	let resolved: Vec<Option<LocalId>> = (0..sources.len())
+		.map(|id| {
+			let mut source = sources[id]?;
+			for _ in 0..sources.len() {
+				match sources[source] {
+					Some(next) => source = next,
+					None => return Some(source),
+				}
+			}
+			None
+		})
+		.collect();
+	for operand in body.operands_mut() {
+		if let Operand::Local(id) = operand {
+			if let Some(source) = resolved[*id] {
+				*id = source;
+			}
+		}
+	}
+}
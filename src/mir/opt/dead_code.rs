@@ -0,0 +1,75 @@
+use crate::{
+	error::Return,
+	mir::{Body, LocalId, Mir, Operand, Terminator, RETURN_PLACE},
+};
+
+use super::Pass;
+
+/// Removes the statements giving a value to locals that are never used, then the locals nothing refers to.
+/// Calls stay, they can have effects
+pub struct DeadCode;
+
+impl Pass for DeadCode {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		for body in &mut mir.bodies {
+			remove_statements(body);
+			remove_locals(body);
+		}
+		Ok(())
+	}
+}
+
+fn remove_statements(body: &mut Body) {
+	loop {
+		let uses = body.uses();
+		let mut changed = false;
+		for block in &mut body.blocks {
+			let before = block.statements.len();
+			block.statements.retain(|s| s.place == RETURN_PLACE || uses[s.place] > 0);
+			changed |= block.statements.len() != before;
+		}
+		if !changed {
+			break;
+		}
+	}
+}
+
+/// Renumber the locals, leaving out the ones that are neither given a value nor used
+fn remove_locals(body: &mut Body) {
+	let uses = body.uses();
+	let mut used: Vec<bool> = uses.iter().map(|count| *count > 0).collect();
+	for local in &mut used[RETURN_PLACE..=body.args + body.captures] {
+		*local = true;
+	}
+	for block in &body.blocks {
+		for statement in &block.statements {
+			used[statement.place] = true;
+		}
+		if let Terminator::Call { dest, .. } = block.terminator {
+			used[dest] = true;
+		}
+	}
+	let mut ids: Vec<Option<LocalId>> = vec![None; used.len()];
+	let mut locals = Vec::new();
+	for (id, local) in std::mem::take(&mut body.locals).into_iter().enumerate() {
+		if used[id] {
+			ids[id] = Some(locals.len());
+			locals.push(local);
+		}
+	}
+	body.locals = locals;
+	let id = |local: LocalId| ids[local].expect("used locals are kept");
+	for operand in body.operands_mut() {
+		if let Operand::Local(local) = operand {
+			*local = id(*local);
+		}
+	}
+	for block in &mut body.blocks {
+		for statement in &mut block.statements {
+			statement.place = id(statement.place);
+		}
+		if let Terminator::Call { dest, .. } = &mut block.terminator {
+			*dest = id(*dest);
+		}
+	}
+}
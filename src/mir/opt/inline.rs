@@ -0,0 +1,104 @@
+use crate::{
+	error::Return,
+	mir::{Body, Callee, Mir, Operand, Rvalue, Statement, Terminator, RETURN_PLACE},
+};
+
+use super::Pass;
+
+/// Replaces the calls to small functions, like `core::one`, with their body.
+/// Panics in a function of the libraries are still reported where it's called from
+pub struct Inline {
+	/// How many statements and terminators a function can have to be inlined
+	pub threshold: usize,
+}
+
+impl Pass for Inline {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		let db = mir.db;
+		for id in 0..mir.bodies.len() {
+			// The blocks added by inlining aren't looked at, which keeps recursive functions from growing forever
+			for block in 0..mir.bodies[id].blocks.len() {
+				// The body of an instance has its id
+				let callee = match mir.bodies[id].blocks[block].terminator {
+					Terminator::Call {
+						callee: Callee::Instance(instance),
+						..
+					} => instance,
+					_ => continue,
+				};
+				if callee == id || size(&mir.bodies[callee]) > self.threshold {
+					continue;
+				}
+				let in_lib = db.namespace(&db.fns[mir.bodies[callee].fn_].module).allow_builtins;
+				let callee = mir.bodies[callee].clone();
+				inline(&mut mir.bodies[id], block, callee, in_lib);
+			}
+		}
+		Ok(())
+	}
+}
+
+fn size(body: &Body) -> usize {
+	body.blocks.iter().map(|block| block.statements.len() + 1).sum()
+}
+
+/// Replace the call ending the block with the body of the function
+fn inline(body: &mut Body, block: usize, callee: Body, in_lib: bool) {
+	let (args, dest, target, span) = match &body.blocks[block].terminator {
+		Terminator::Call {
+			args,
+			dest,
+			target,
+			span,
+			..
+		} => (args.clone(), *dest, *target, span.clone()),
+		_ => unreachable!("only calls are inlined"),
+	};
+	let locals = body.locals.len();
+	let blocks = body.blocks.len();
+	body.locals.extend(callee.locals);
+	for mut inlined in callee.blocks {
+		for statement in &mut inlined.statements {
+			statement.place += locals;
+		}
+		for operand in inlined
+			.statements
+			.iter_mut()
+			.flat_map(|s| s.value.operands_mut())
+			.chain(inlined.terminator.operands_mut())
+		{
+			if let Operand::Local(id) = operand {
+				*id += locals;
+			}
+		}
+		for successor in inlined.terminator.successors_mut() {
+			*successor += blocks;
+		}
+		match &mut inlined.terminator {
+			Terminator::Call { dest, span: inner, .. } => {
+				*dest += locals;
+				// Like the frame it no longer has, the function doesn't show where it panics
+				if in_lib {
+					*inner = span.clone();
+				}
+			}
+			Terminator::Return => {
+				inlined.statements.push(Statement {
+					place: dest,
+					value: Rvalue::Use(Operand::Local(locals + RETURN_PLACE)),
+				});
+				inlined.terminator = Terminator::Goto(target);
+			}
+			_ => (),
+		}
+		body.blocks.push(inlined);
+	}
+	let caller = &mut body.blocks[block];
+	for (i, arg) in args.into_iter().enumerate() {
+		caller.statements.push(Statement {
+			place: locals + RETURN_PLACE + 1 + i,
+			value: Rvalue::Use(arg),
+		});
+	}
+	caller.terminator = Terminator::Goto(blocks);
+}
@@ -0,0 +1,71 @@
+//! Optimisations of the MIR, each a pass rewriting the bodies, which the pass manager runs in the order of the
+//! level asked for with `-O`. They keep what the program prints and where it panics, only its backtrace loses
+//! the functions that were inlined
+
+use crate::{error::Return, mir::Mir};
+
+mod const_fold;
+mod copy_prop;
+mod dead_code;
+mod inline;
+mod simplify;
+
+use const_fold::ConstFold;
+use copy_prop::CopyProp;
+use dead_code::DeadCode;
+use inline::Inline;
+use simplify::{SimplifyBranches, SimplifyCfg};
+
+/// The highest optimisation level
+pub const MAX_LEVEL: u8 = 3;
+
+/// How big, in statements and terminators, the functions inlined at levels 2 and 3 can be
+const INLINE_THRESHOLD: usize = 8;
+const AGGRESSIVE_INLINE_THRESHOLD: usize = 24;
+
+pub trait Pass {
+	fn run(&self, mir: &mut Mir) -> Return<()>;
+}
+
+pub struct PassManager {
+	passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+	/// The passes of a level: none at 0, the ones cleaning each body at 1, inlining small functions before
+	/// cleaning up at 2, and inlining bigger ones over more rounds at 3.
+	/// Folding follows the overflow behaviour of `--release`
+	pub fn new(level: u8, release: bool) -> Self {
+		let mut manager = Self { passes: Vec::new() };
+		let rounds = match level {
+			0 => return manager,
+			1 | 2 => 1,
+			_ => MAX_LEVEL as usize,
+		};
+		for _ in 0..rounds {
+			if level >= 2 {
+				manager.add(Inline {
+					threshold: if level >= 3 { AGGRESSIVE_INLINE_THRESHOLD } else { INLINE_THRESHOLD },
+				});
+			}
+			manager.add(ConstFold { release });
+			manager.add(SimplifyBranches);
+			manager.add(SimplifyCfg);
+			manager.add(CopyProp);
+			manager.add(DeadCode);
+			manager.add(SimplifyCfg);
+		}
+		manager
+	}
+
+	pub fn add<P: Pass + 'static>(&mut self, pass: P) {
+		self.passes.push(Box::new(pass));
+	}
+
+	pub fn run(&self, mir: &mut Mir) -> Return<()> {
+		for pass in &self.passes {
+			pass.run(mir)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,135 @@
+use crate::{
+	error::Return,
+	mir::{Block, BlockId, Body, Mir, Operand, Terminator},
+};
+
+use super::Pass;
+
+/// Turns the switches that go to one block, like an `if` on a constant condition, into jumps
+pub struct SimplifyBranches;
+
+impl Pass for SimplifyBranches {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		for block in mir.bodies.iter_mut().flat_map(|b| &mut b.blocks) {
+			let target = match &block.terminator {
+				Terminator::Switch(Operand::Const(c), targets, otherwise) => {
+					Some(targets.iter().find(|(value, _)| value == c).map_or(*otherwise, |(_, target)| *target))
+				}
+				// Every value goes to the same block
+				terminator @ (Terminator::Switch(..) | Terminator::SwitchVariant(..)) => {
+					let successors = terminator.successors();
+					match successors.iter().all(|target| *target == successors[0]) {
+						true => Some(successors[0]),
+						false => None,
+					}
+				}
+				_ => None,
+			};
+			if let Some(target) = target {
+				block.terminator = Terminator::Goto(target);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Skips the blocks that only jump to another, appends a block to the one jumping to it when it's its only
+/// predecessor, and removes the blocks that can't be reached
+pub struct SimplifyCfg;
+
+impl Pass for SimplifyCfg {
+	fn run(&self, mir: &mut Mir) -> Return<()> {
+		for body in &mut mir.bodies {
+			// Blocks that can't be reached would count as predecessors
+			body.blocks = remove_unreachable(std::mem::take(&mut body.blocks));
+			skip_gotos(body);
+			merge_blocks(body);
+			body.blocks = remove_unreachable(std::mem::take(&mut body.blocks));
+		}
+		Ok(())
+	}
+}
+
+/// Where going to a block ends up, following the empty blocks that jump to another
+fn destination(blocks: &[Block], mut id: BlockId) -> BlockId {
+	// An empty loop has to stay a loop
+	for _ in 0..blocks.len() {
+		match &blocks[id] {
+			Block {
+				statements,
+				terminator: Terminator::Goto(target),
+			} if statements.is_empty() => id = *target,
+			_ => return id,
+		}
+	}
+	id
+}
+
+fn skip_gotos(body: &mut Body) {
+	for id in 0..body.blocks.len() {
+		let targets: Vec<BlockId> = body.blocks[id]
+			.terminator
+			.successors()
+			.into_iter()
+			.map(|target| destination(&body.blocks, target))
+			.collect();
+		for (target, destination) in body.blocks[id].terminator.successors_mut().into_iter().zip(targets) {
+			*target = destination;
+		}
+	}
+}
+
+fn merge_blocks(body: &mut Body) {
+	let mut predecessors = vec![0; body.blocks.len()];
+	// The first block is entered without a jump
+	predecessors[0] = 1;
+	for block in &body.blocks {
+		for target in block.terminator.successors() {
+			predecessors[target] += 1;
+		}
+	}
+	for id in 0..body.blocks.len() {
+		while let Terminator::Goto(target) = body.blocks[id].terminator {
+			if target == id || predecessors[target] != 1 {
+				break;
+			}
+			// The block is left without predecessors, to be removed
+			let next = std::mem::replace(&mut body.blocks[target], Block {
+				statements: Vec::new(),
+				terminator: Terminator::Unreachable,
+			});
+			predecessors[target] = 0;
+			let block = &mut body.blocks[id];
+			block.statements.extend(next.statements);
+			block.terminator = next.terminator;
+		}
+	}
+}
+
+/// Keep the blocks that can be reached from the first one, numbered in the order a depth-first walk reaches them.
+/// The body starts at the block the first one jumps to if it's empty
+fn remove_unreachable(mut blocks: Vec<Block>) -> Vec<Block> {
+	let mut ids: Vec<Option<BlockId>> = vec![None; blocks.len()];
+	let mut order = Vec::new();
+	let mut stack = vec![destination(&blocks, 0)];
+	while let Some(id) = stack.pop() {
+		if ids[id].is_none() {
+			ids[id] = Some(order.len());
+			order.push(id);
+			stack.extend(blocks[id].terminator.successors().into_iter().rev());
+		}
+	}
+	order
+		.into_iter()
+		.map(|id| {
+			let mut block = std::mem::replace(&mut blocks[id], Block {
+				statements: Vec::new(),
+				terminator: Terminator::Unreachable,
+			});
+			for target in block.terminator.successors_mut() {
+				*target = ids[*target].unwrap();
+			}
+			block
+		})
+		.collect()
+}
@@ -22,8 +22,8 @@ impl ModuleTree {
 
 pub struct Module {
 	pub ast: AstModule,
-	children: HashMap<String, (bool, Module)>,
-	allow_builtins: bool,
+	pub children: HashMap<String, (bool, Module)>,
+	pub allow_builtins: bool,
 }
 
 impl Debug for Module {
@@ -59,8 +59,8 @@ pub fn build_tree(
 		let name = module.name.clone();
 		let name_span_str = Span::new_ref(&name, |s| s.as_str());
 		let name_str = name_span_str.as_ref();
-		let module = fs.find_child(file, &module_name, name_span_str)?;
-		let ast = crate::parser::parse_module(module, fs);
+		let module = fs.find_child(file, module_name, name_span_str)?;
+		let ast = crate::parser::parse_module(module, fs)?;
 		let module = build_tree(fs, file, name_str, ast, allow_builtins)?;
 		children.insert(name.into_inner(), (is_pub, module));
 	}
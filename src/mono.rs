@@ -0,0 +1,330 @@
+//! Monomorphisation: finds every instance the program needs of its functions, a function with concrete types
+//! given to its generics, which the MIR has a body for
+
+use std::fmt::Write;
+
+use hashbrown::HashMap;
+
+use crate::{
+	checker::{
+		db::{Callee, FnId, FnParent, FnSig, ImplSource, TraitId, TypeDB, Use, LOCAL_CRATE},
+		infer::InferCtx,
+		types::{write_fn, TraitRef, Type},
+		Checked,
+	},
+	error::{ErrorCode, NonLocatedError, Return},
+};
+
+/// How deeply the types given to generics can be nested, and how many types they can be made of.
+/// Bigger ones come from a function that instantiates itself with ever bigger types, which never ends
+const MAX_TYPE_DEPTH: usize = 64;
+const MAX_TYPE_SIZE: usize = 1024;
+
+/// How much of the name of an instance errors show
+const MAX_NAME_LENGTH: usize = 80;
+
+pub type InstanceId = usize;
+
+#[derive(Debug)]
+pub struct Instance {
+	pub fn_: FnId,
+	/// The types given to the generics of the function, in the order of [`FnSig::generics`]
+	pub generics: Vec<Type>,
+	/// The instance each use of a function in the body calls, in the order of [`CheckedFn::uses`](crate::checker::CheckedFn::uses)
+	pub calls: Vec<InstanceId>,
+}
+
+/// Every instance the program needs, in the order they are found
+#[derive(Debug, Default)]
+pub struct Mono {
+	pub instances: Vec<Instance>,
+	ids: HashMap<(FnId, Vec<Type>), InstanceId>,
+}
+
+impl Mono {
+	/// Find the instances reachable from `main`, or from the functions a lib gives to its users
+	pub fn collect(checked: &Checked, lib: bool) -> Return<Self> {
+		let db = &checked.db;
+		let mut mono = Self::default();
+		if lib {
+			for id in 0..db.fns.len() {
+				if is_lib_root(db, id)? {
+					mono.instance(id, Vec::new());
+				}
+			}
+		} else {
+			match db.main_fn() {
+				Some(main) => mono.instance(main, Vec::new()),
+				None => {
+					NonLocatedError::new(ErrorCode::NoMainError, "`main` function not found in crate".to_string())
+						.display()?;
+					unreachable!()
+				}
+			};
+		}
+		// Walking an instance can find new ones, which get walked in turn
+		let mut next = 0;
+		while next < mono.instances.len() {
+			let fn_ = mono.instances[next].fn_;
+			let (_, sig) = db.fn_signature(fn_)?;
+			let map: HashMap<String, Type> = sig
+				.generics
+				.into_iter()
+				.zip(mono.instances[next].generics.iter().cloned())
+				.collect();
+			let mut calls = Vec::new();
+			for use_ in &checked.fns[fn_].uses {
+				let (callee, generics) = resolve(db, &map, use_)?;
+				let id = mono.instance(callee, generics);
+				if mono.instances[id].generics.iter().any(|t| depth(t) > MAX_TYPE_DEPTH || size(t) > MAX_TYPE_SIZE) {
+					// The name is as big as the types, only its start tells where they come from
+					let mut name = mono.name(db, id);
+					if let Some((end, _)) = name.char_indices().nth(MAX_NAME_LENGTH) {
+						name.replace_range(end.., "...");
+					}
+					use_.span
+						.as_error(
+							ErrorCode::RecursionLimitError,
+							format!("reached the recursion limit while instantiating `{}`", name),
+						)
+						.with_note(&db.fns[callee].def.name, format!("`{}` defined here", db.fn_path(callee)))
+						.display()?;
+				}
+				calls.push(id);
+			}
+			mono.instances[next].calls = calls;
+			next += 1;
+		}
+		Ok(mono)
+	}
+
+	/// The instance of the function with these generics, adding it if it's new
+	pub fn instance(&mut self, fn_: FnId, generics: Vec<Type>) -> InstanceId {
+		let instances = &mut self.instances;
+		*self.ids.entry((fn_, generics.clone())).or_insert_with(|| {
+			instances.push(Instance {
+				fn_,
+				generics,
+				calls: Vec::new(),
+			});
+			instances.len() - 1
+		})
+	}
+
+	/// The signature of the instance, with the types given to the generics
+	pub fn signature(&self, db: &TypeDB, id: InstanceId) -> Return<FnSig> {
+		let instance = &self.instances[id];
+		let (_, sig) = db.fn_signature(instance.fn_)?;
+		let map: HashMap<String, Type> = sig.generics.iter().cloned().zip(instance.generics.iter().cloned()).collect();
+		let mut infer = InferCtx::default();
+		Ok(FnSig {
+			generics: Vec::new(),
+			args: sig
+				.args
+				.iter()
+				.map(|t| db.normalize(&mut infer, &[], &t.subst(&map)))
+				.collect::<Return<_>>()?,
+			ret: db.normalize(&mut infer, &[], &sig.ret.subst(&map))?,
+			bounds: Vec::new(),
+		})
+	}
+
+	/// A readable name for the instance, like `crate::swap::<int, bool>`
+	pub fn name(&self, db: &TypeDB, id: InstanceId) -> String {
+		let instance = &self.instances[id];
+		let mut name = db.fn_path(instance.fn_);
+		if !instance.generics.is_empty() {
+			let generics: Vec<String> = instance.generics.iter().map(|t| t.to_string()).collect();
+			write!(name, "::<{}>", generics.join(", ")).unwrap();
+		}
+		name
+	}
+
+	/// The symbol of the instance, unique to it and made of letters, digits and `_` only.
+	/// Names are prefixed by their length and types by a letter telling what they are, like `_LN5crate4swapEI3i644boolE`
+	pub fn symbol(&self, db: &TypeDB, id: InstanceId) -> String {
+		let instance = &self.instances[id];
+		let entry = &db.fns[instance.fn_];
+		let mut symbol = "_L".to_string();
+		match entry.parent {
+			FnParent::Module => {
+				symbol.push('N');
+				mangle_path(&mut symbol, &entry.module);
+			}
+			FnParent::Trait(t) => {
+				symbol.push('N');
+				mangle_path(&mut symbol, &db.traits[t].path);
+			}
+			FnParent::Impl(i) => {
+				let impl_ = &db.impls[i];
+				symbol.push('X');
+				mangle_type(&mut symbol, &impl_.self_type);
+				if let Some(trait_) = &impl_.trait_ref {
+					symbol.push('Y');
+					mangle_named(&mut symbol, &trait_.path, &trait_.generics);
+				}
+			}
+		}
+		mangle_path(&mut symbol, &[entry.def.name.to_string()]);
+		symbol.push('E');
+		mangle_generics(&mut symbol, &instance.generics);
+		symbol
+	}
+
+	/// Every instance on its own line, with its signature and symbol
+	pub fn dump(&self, db: &TypeDB) -> Return<String> {
+		let mut out = String::new();
+		for id in 0..self.instances.len() {
+			let sig = self.signature(db, id)?;
+			write!(out, "{}: ", self.name(db, id)).unwrap();
+			write_fn(&mut out, sig.args.iter().map(|t| t.to_string()), sig.ret.to_string()).unwrap();
+			writeln!(out, " => {}", self.symbol(db, id)).unwrap();
+		}
+		Ok(out)
+	}
+}
+
+/// Whether a lib gives the function to its users: it's not generic, and it's public or implements a trait
+fn is_lib_root(db: &TypeDB, id: FnId) -> Return<bool> {
+	let entry = &db.fns[id];
+	let exported = match entry.parent {
+		FnParent::Module => entry.def.pub_kw.is_some(),
+		FnParent::Impl(i) => entry.def.pub_kw.is_some() || db.impls[i].trait_.is_some(),
+		FnParent::Trait(_) => false,
+	};
+	Ok(entry.module[0] == LOCAL_CRATE && exported && db.fn_signature(id)?.1.generics.is_empty())
+}
+
+/// The function a use calls in an instance, whose generics are in `map`, and the types given to its generics
+fn resolve(db: &TypeDB, map: &HashMap<String, Type>, use_: &Use) -> Return<(FnId, Vec<Type>)> {
+	let mut infer = InferCtx::default();
+	let generics = use_
+		.generics
+		.iter()
+		.map(|t| db.normalize(&mut infer, &[], &t.without_vars().subst(map)))
+		.collect::<Return<Vec<Type>>>()?;
+	match &use_.callee {
+		Callee::Fn(id) => Ok((*id, generics)),
+		Callee::Method(trait_, name) => Ok(resolve_method(db, *trait_, name, generics)?
+			.expect("the checker made sure the self type implements the trait")),
+	}
+}
+
+/// The method of an impl that a call of a trait method runs, and the types given to its generics.
+/// The generics of the call are the ones of the trait, `Self`, then the ones of the method, all known
+pub fn resolve_method(
+	db: &TypeDB,
+	trait_: TraitId,
+	name: &str,
+	generics: Vec<Type>,
+) -> Return<Option<(FnId, Vec<Type>)>> {
+	let mut infer = InferCtx::default();
+	let trait_generics = db.traits[trait_].def.generics.iter().flatten().count();
+	let trait_ref = TraitRef {
+		path: db.traits[trait_].path.clone(),
+		generics: generics[..trait_generics].to_vec(),
+	};
+	let self_type = &generics[trait_generics];
+	let (impl_, impl_map) = match db.find_impl(&mut infer, &[], self_type, &trait_ref) {
+		Some(ImplSource::Impl(impl_, impl_map)) => (impl_, impl_map),
+		_ => return Ok(None),
+	};
+	let id = db.impl_method(impl_, name).expect("trait impls have every method of their trait");
+	match db.fns[id].parent {
+		// A default method of the trait has the same generics as its declaration
+		FnParent::Trait(_) => Ok(Some((id, generics))),
+		_ => {
+			let mut callee_generics = Vec::new();
+			for g in db.impls[impl_].generic_names() {
+				let type_ = infer.resolve(&impl_map[&g]);
+				callee_generics.push(db.normalize(&mut infer, &[], &type_.without_vars())?);
+			}
+			callee_generics.extend_from_slice(&generics[trait_generics + 1..]);
+			Ok(Some((id, callee_generics)))
+		}
+	}
+}
+
+/// How many types are nested in each other, `1` for types that don't contain any
+fn depth(type_: &Type) -> usize {
+	let inner = match type_ {
+		Type::Tuple(types) | Type::Adt(_, types) => types.iter().map(depth).max(),
+		Type::Array(element, _) => Some(depth(element)),
+		Type::Fn(args, ret) => args.iter().chain([&**ret]).map(depth).max(),
+		Type::Projection(self_type, trait_, _) => trait_.generics.iter().chain([&**self_type]).map(depth).max(),
+		_ => None,
+	};
+	inner.unwrap_or(0) + 1
+}
+
+/// How many types the type is made of, counting itself
+fn size(type_: &Type) -> usize {
+	let mut size = 0;
+	type_.walk(&mut |_| size += 1);
+	size
+}
+
+fn mangle_path<S: AsRef<str>>(out: &mut String, path: &[S]) {
+	for segment in path {
+		write!(out, "{}{}", segment.as_ref().len(), segment.as_ref()).unwrap();
+	}
+}
+
+fn mangle_generics(out: &mut String, generics: &[Type]) {
+	if !generics.is_empty() {
+		out.push('I');
+		for t in generics {
+			mangle_type(out, t);
+		}
+		out.push('E');
+	}
+}
+
+/// A trait or an ADT, with its generics
+fn mangle_named(out: &mut String, path: &[String], generics: &[Type]) {
+	out.push('N');
+	mangle_path(out, path);
+	mangle_generics(out, generics);
+	out.push('E');
+}
+
+fn mangle_type(out: &mut String, type_: &Type) {
+	match type_ {
+		// Inference variables are gone by now
+		Type::Empty | Type::Var(_) => out.push('u'),
+		Type::Never => out.push('z'),
+		Type::Primitive(p) => mangle_path(out, &[p.name()]),
+		Type::Tuple(types) => {
+			out.push('T');
+			for t in types {
+				mangle_type(out, t);
+			}
+			out.push('E');
+		}
+		Type::Array(element, len) => {
+			out.push('A');
+			mangle_type(out, element);
+			mangle_type(out, len);
+		}
+		Type::Length(len) => write!(out, "L{}_", len).unwrap(),
+		Type::Adt(path, generics) => mangle_named(out, path, generics),
+		Type::Fn(args, ret) => {
+			out.push('F');
+			for t in args {
+				mangle_type(out, t);
+			}
+			out.push('R');
+			mangle_type(out, ret);
+		}
+		Type::Generic(name) => {
+			out.push('G');
+			mangle_path(out, &[name]);
+		}
+		Type::Projection(self_type, trait_, name) => {
+			out.push('P');
+			mangle_type(out, self_type);
+			mangle_named(out, &trait_.path, &trait_.generics);
+			mangle_path(out, &[name]);
+		}
+	}
+}
@@ -2,7 +2,6 @@ use std::path::PathBuf;
 
 use super::backend;
 
-use backend::print_ast;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -27,7 +26,32 @@ pub struct Options {
 	pub external: Vec<(String, String)>,
 
 	#[structopt(name = "no-std", long)]
-	pub no_std: bool
+	pub no_std: bool,
+
+	/// Print what the given stages of compilation produce instead of running the backend, separated by commas
+	#[structopt(long, use_delimiter = true, possible_values(&Emit::variants()))]
+	pub emit: Vec<Emit>,
+
+	/// Build in release mode, where integer overflow wraps around instead of panicking
+	#[structopt(long)]
+	pub release: bool,
+
+	/// How much to optimise the MIR before the backend runs it, from 0, not at all, to 3, inlining the most
+	#[structopt(short = "O", default_value = "0", possible_values(&["0", "1", "2", "3"]))]
+	pub opt_level: u8,
+
+	/// The most objects, like `Ref`s and `Vec`s, the interpreter's heap can hold at once, running out of them panics
+	#[structopt(long)]
+	heap_limit: Option<usize>,
+	/// Print statistics about the interpreter's garbage collector when the program ends
+	#[structopt(long)]
+	gc_stats: bool,
+	/// Collect garbage on every allocation of the interpreter, which is slow but finds values the collector lost track of
+	#[structopt(long)]
+	gc_stress: bool,
+	/// Deny the program running in the interpreter any access to the filesystem
+	#[structopt(long)]
+	sandbox: bool,
 }
 
 fn externlib_from_str(s: &str) -> (String, String) {
@@ -42,9 +66,7 @@ fn validate_externlib(s: String) -> Result<(), String> {
 	if s.contains("=") {
 		Ok(())
 	} else {
-		Err(format!(
-			"--extern option should contain a `=` to separate the library name and its entry point"
-		))
+		Err("--extern option should contain a `=` to separate the library name and its entry point".to_string())
 	}
 }
 
@@ -54,6 +76,11 @@ impl Options {
 			CodegenOptions {
 				lib: self.lib,
 				out: self.out,
+				release: self.release,
+				heap_limit: self.heap_limit,
+				gc_stats: self.gc_stats,
+				gc_stress: self.gc_stress,
+				sandbox: self.sandbox,
 			},
 			self.backend,
 		)
@@ -63,6 +90,11 @@ impl Options {
 pub struct CodegenOptions {
 	pub lib: bool,
 	pub out: Option<PathBuf>,
+	pub release: bool,
+	pub heap_limit: Option<usize>,
+	pub gc_stats: bool,
+	pub gc_stress: bool,
+	pub sandbox: bool,
 }
 
 use structopt::clap::arg_enum;
@@ -73,10 +105,21 @@ arg_enum! {
 	pub enum Backend {
 		print_ast,
 		interpret,
-		// js
+		js,
+	}
+}
+
+arg_enum! {
+	#[derive(PartialEq, Debug)]
+	#[allow(non_camel_case_types)]
+	pub enum Emit {
+		mono,
+		hir,
+		mir,
 	}
 }
 
+#[allow(clippy::derivable_impls)] // arg_enum! doesn't accept attributes on the variants
 impl Default for Backend {
 	fn default() -> Self {
 		Self::interpret
@@ -87,7 +130,7 @@ impl Backend {
 	pub fn get_codegen(&self) -> Box<dyn backend::Backend> {
 		match self {
 			Backend::interpret => Box::new(backend::interpreter::Codegen),
-			// Self::js => Box::new(backend::js::Codegen),
+			Backend::js => Box::new(backend::js::Codegen),
 		    Backend::print_ast => Box::new(backend::print_ast::Codegen),
 		}
 	}
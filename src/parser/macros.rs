@@ -0,0 +1,556 @@
+//! The builtin macros, `format!` and the ones printing with it, and `#[derive(Debug)]`.
+//! They expand to calls to `core::fmt`, with the span of what they were expanded from.
+
+use std::{iter::Peekable, str::Chars};
+
+use crate::{
+	ast::{
+		ArrayLen, Block, Expr, FnDef, Fields, Generics, Literal, MatchArm, Pattern, Statement, Trait, TraitImpl,
+		Type, WhereClause,
+	},
+	error::{ErrorCode, Return},
+	span::{BoxedSpan, Span},
+};
+
+/// Report an error, which stops the parser
+fn fail<S, T>(span: &Span<S>, code: ErrorCode, message: String) -> Return<T> {
+	span.as_error(code, message).display()?;
+	unreachable!("errors always stop the parser")
+}
+
+/// Makes the nodes of an expansion, all at the span it was expanded from
+struct Builder {
+	at: Span<()>,
+}
+
+impl Builder {
+	fn new<T>(at: &Span<T>) -> Self {
+		Self {
+			at: Span::new_ref(at, |_| ()),
+		}
+	}
+
+	fn span<T>(&self, content: T) -> Span<T> {
+		Span::new_ref(&self.at, |_| content)
+	}
+
+	fn expr(&self, expr: Expr) -> BoxedSpan<Expr> {
+		self.span(Box::new(expr))
+	}
+
+	fn path(&self, path: &str) -> Vec<Span<String>> {
+		path.split("::").map(|segment| self.span(segment.to_string())).collect()
+	}
+
+	fn ident(&self, path: &str) -> BoxedSpan<Expr> {
+		self.expr(Expr::Ident(self.path(path)))
+	}
+
+	fn call(&self, path: &str, args: Vec<BoxedSpan<Expr>>) -> BoxedSpan<Expr> {
+		self.expr(Expr::FnCall(self.ident(path), args))
+	}
+
+	fn method(&self, receiver: BoxedSpan<Expr>, name: &str, args: Vec<BoxedSpan<Expr>>) -> BoxedSpan<Expr> {
+		self.expr(Expr::MethodCall(receiver, self.span(name.to_string()), args))
+	}
+
+	fn string(&self, s: &str) -> BoxedSpan<Expr> {
+		self.expr(Expr::Literal(Literal::String(s.to_string())))
+	}
+
+	fn option(&self, value: Option<BoxedSpan<Expr>>) -> BoxedSpan<Expr> {
+		match value {
+			Some(value) => self.call("core::option::Option::Some", vec![value]),
+			None => self.ident("core::option::Option::None"),
+		}
+	}
+
+	fn usize(&self, value: Option<usize>) -> BoxedSpan<Expr> {
+		self.option(value.map(|value| self.expr(Expr::Literal(Literal::Int(value as u128, None)))))
+	}
+
+	fn binding(&self, name: &str) -> Span<Pattern> {
+		self.span(Pattern::Binding {
+			mutable: None,
+			name: self.span(name.to_string()),
+			subpattern: None,
+		})
+	}
+
+	fn statement(&self, statement: Statement) -> BoxedSpan<Statement> {
+		self.span(Box::new(statement))
+	}
+
+	fn type_(&self, name: &str) -> Span<Type> {
+		self.span(Type::Other {
+			name: self.span(name.to_string()),
+			generics: Vec::new(),
+		})
+	}
+
+	fn debug_trait(&self) -> Span<Trait> {
+		self.span(Trait {
+			name: self.span("core::fmt::Debug".to_string()),
+			generics: Vec::new(),
+		})
+	}
+}
+
+/// Which argument a `{}` writes
+enum Argument {
+	Next,
+	Index(usize),
+	/// A named argument, or else a variable
+	Name(String),
+}
+
+/// The options of a `{}`, after its `:`
+struct Spec {
+	fill: char,
+	align: Option<&'static str>,
+	width: Option<usize>,
+	precision: Option<usize>,
+	debug: bool,
+}
+
+impl Spec {
+	fn is_default(&self) -> bool {
+		self.align.is_none() && self.width.is_none() && self.precision.is_none()
+	}
+}
+
+enum Piece {
+	Text(String),
+	Argument(Argument, Spec),
+}
+
+/// Split a format string into text and `{}`s
+fn parse_format<S>(span: &Span<S>, s: &str) -> Return<Vec<Piece>> {
+	let error = |message: &str| fail(span, ErrorCode::FormatError, format!("invalid format string: {}", message));
+	let mut pieces = Vec::new();
+	let mut text = String::new();
+	let mut chars = s.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'{' if chars.peek() == Some(&'{') => {
+				chars.next();
+				text.push('{');
+			}
+			'}' if chars.peek() == Some(&'}') => {
+				chars.next();
+				text.push('}');
+			}
+			'}' => return error("unmatched `}` found"),
+			'{' => {
+				let mut inner = String::new();
+				loop {
+					match chars.next() {
+						Some('}') => break,
+						Some('{') => return error("expected `}`, found `{`"),
+						Some(c) => inner.push(c),
+						None => return error("expected `}` but string was terminated"),
+					}
+				}
+				if !text.is_empty() {
+					pieces.push(Piece::Text(std::mem::take(&mut text)));
+				}
+				let (argument, spec) = inner.split_once(':').unwrap_or((&inner, ""));
+				let argument = match argument.trim() {
+					"" => Argument::Next,
+					index if index.chars().all(|c| c.is_ascii_digit()) => Argument::Index(index.parse().unwrap()),
+					name if is_ident(name) => Argument::Name(name.to_string()),
+					name => return error(&format!("invalid argument name `{}`", name)),
+				};
+				let spec = match parse_spec(spec) {
+					Some(spec) => spec,
+					None => return error(&format!("unknown format spec `{{:{}}}`", spec)),
+				};
+				pieces.push(Piece::Argument(argument, spec));
+			}
+			c => text.push(c),
+		}
+	}
+	if !text.is_empty() {
+		pieces.push(Piece::Text(text));
+	}
+	Ok(pieces)
+}
+
+fn is_ident(s: &str) -> bool {
+	let mut chars = s.chars();
+	chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parse `[[fill]align][width][.precision][?]`
+fn parse_spec(spec: &str) -> Option<Spec> {
+	let align = |c: char| match c {
+		'<' => Some("Left"),
+		'^' => Some("Center"),
+		'>' => Some("Right"),
+		_ => None,
+	};
+	let mut result = Spec {
+		fill: ' ',
+		align: None,
+		width: None,
+		precision: None,
+		debug: false,
+	};
+	let mut chars = spec.chars().peekable();
+	let mut lookahead = spec.chars();
+	match (lookahead.next(), lookahead.next().and_then(align)) {
+		(Some(fill), Some(alignment)) => {
+			result.fill = fill;
+			result.align = Some(alignment);
+			chars.nth(1);
+		}
+		(Some(c), _) if align(c).is_some() => {
+			result.align = align(c);
+			chars.next();
+		}
+		_ => (),
+	}
+	// A leading `0` is a flag in Rust, which isn't supported
+	if chars.peek() == Some(&'0') {
+		return None;
+	}
+	result.width = parse_count(&mut chars);
+	if chars.peek() == Some(&'.') {
+		chars.next();
+		result.precision = Some(parse_count(&mut chars)?);
+	}
+	if chars.peek() == Some(&'?') {
+		chars.next();
+		result.debug = true;
+	}
+	chars.next().is_none().then_some(result)
+}
+
+fn parse_count(chars: &mut Peekable<Chars>) -> Option<usize> {
+	let mut digits = String::new();
+	while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+		digits.push(*c);
+		chars.next();
+	}
+	digits.parse().ok()
+}
+
+/// Expand a macro call, like `println!("{} {x:>4}", a)`
+pub(super) fn expand_macro<S>(span: &Span<S>, name: &Span<String>, args: Vec<BoxedSpan<Expr>>) -> Return<Expr> {
+	let print = match name.as_str() {
+		"format" => None,
+		"print" => Some("std::io::print"),
+		"println" => Some("std::io::println"),
+		"eprintln" => Some("std::io::eprintln"),
+		"panic" => Some("core::panic::panic"),
+		_ => {
+			return fail(
+				name,
+				ErrorCode::UnknownNameError,
+				format!("cannot find macro `{}` in this scope", &**name),
+			)
+		}
+	};
+	let at = Builder::new(span);
+	let text = match (print, args.is_empty()) {
+		(Some("std::io::println" | "std::io::eprintln"), true) => at.string(""),
+		(Some("core::panic::panic"), true) => at.string("explicit panic"),
+		(_, true) => {
+			return fail(
+				span,
+				ErrorCode::FormatError,
+				format!("`{}!` requires at least a format string argument", &**name),
+			)
+		}
+		(_, false) => format(span, args)?,
+	};
+	Ok(match print {
+		Some(path) => Expr::FnCall(at.ident(path), vec![text]),
+		None => *text.into_inner(),
+	})
+}
+
+/// Expand the arguments of `format!` to a block writing them with a `core::fmt::Formatter`
+fn format<S>(span: &Span<S>, mut args: Vec<BoxedSpan<Expr>>) -> Return<BoxedSpan<Expr>> {
+	let at = Builder::new(span);
+	let format_string = args.remove(0);
+	let pieces = match &**format_string {
+		Expr::Literal(Literal::String(s)) => parse_format(&format_string, s)?,
+		_ => {
+			return fail(
+				&format_string,
+				ErrorCode::FormatError,
+				"format argument must be a string literal".to_string(),
+			)
+		}
+	};
+	// Every argument is evaluated once, before any is written
+	let mut statements = Vec::new();
+	let mut positional = Vec::new();
+	let mut named = Vec::new();
+	for arg in args {
+		let arg_at = Builder::new(&arg);
+		let (variable, value) = match *arg.into_inner() {
+			Expr::Assign(place, _, value) => match &**place {
+				Expr::Ident(path) if path.len() == 1 => {
+					named.push((path[0].to_string(), Builder::new(&place), false));
+					(format!("${}", &*path[0]), value)
+				}
+				_ => return fail(&place, ErrorCode::FormatError, "expected a name for a named argument".to_string()),
+			},
+			_ if !named.is_empty() => {
+				return fail(
+					&arg_at.at,
+					ErrorCode::FormatError,
+					"positional arguments cannot follow named arguments".to_string(),
+				)
+			}
+			value => {
+				let value = arg_at.expr(value);
+				positional.push((arg_at, false));
+				(format!("${}", positional.len() - 1), value)
+			}
+		};
+		statements.push(at.statement(Statement::Let(at.binding(&variable), None, Some(value), None)));
+	}
+	statements.push(at.statement(Statement::Let(
+		at.binding("$f"),
+		None,
+		Some(at.call("core::fmt::Formatter::new", Vec::new())),
+		None,
+	)));
+	let implicit = pieces
+		.iter()
+		.filter(|piece| matches!(piece, Piece::Argument(Argument::Next, _)))
+		.count();
+	let mut next = 0;
+	for piece in pieces {
+		let (argument, spec) = match piece {
+			Piece::Text(text) => {
+				let write = at.method(at.ident("$f"), "write_str", vec![at.string(&text)]);
+				statements.push(at.statement(Statement::NonReturning(*write.into_inner())));
+				continue;
+			}
+			Piece::Argument(argument, spec) => (argument, spec),
+		};
+		let index = match argument {
+			Argument::Next => {
+				next += 1;
+				Some(next - 1)
+			}
+			Argument::Index(index) => Some(index),
+			Argument::Name(_) => None,
+		};
+		let (value, arg_at) = match (index, argument) {
+			(Some(index), argument) => match positional.get_mut(index) {
+				Some((arg_at, used)) => {
+					*used = true;
+					(format!("${}", index), Builder::new(&arg_at.at))
+				}
+				None if matches!(argument, Argument::Next) => {
+					return fail(
+						&format_string,
+						ErrorCode::FormatError,
+						format!(
+							"{} positional argument{} in format string, but {}",
+							implicit,
+							if implicit == 1 { "" } else { "s" },
+							arguments(positional.len())
+						),
+					);
+				}
+				None => {
+					return fail(
+						&format_string,
+						ErrorCode::FormatError,
+						format!(
+							"invalid reference to positional argument {} ({})",
+							index,
+							arguments(positional.len())
+						),
+					)
+				}
+			},
+			(None, Argument::Name(name)) => match named.iter_mut().find(|(n, ..)| *n == name) {
+				Some((_, arg_at, used)) => {
+					*used = true;
+					(format!("${}", name), Builder::new(&arg_at.at))
+				}
+				// Other names are variables in scope
+				None => (name, Builder::new(&format_string)),
+			},
+			_ => unreachable!(),
+		};
+		let formatter = match spec.is_default() {
+			true => at.ident("$f"),
+			false => at.method(
+				at.ident("$f"),
+				"with",
+				vec![
+					at.usize(spec.width),
+					at.expr(Expr::Literal(Literal::Char(spec.fill))),
+					at.option(spec.align.map(|align| at.ident(&format!("core::fmt::Alignment::{}", align)))),
+					at.usize(spec.precision),
+				],
+			),
+		};
+		let function = if spec.debug { "core::fmt::debug" } else { "core::fmt::display" };
+		let write = arg_at.call(function, vec![arg_at.ident(&value), formatter]);
+		statements.push(arg_at.statement(Statement::NonReturning(*write.into_inner())));
+	}
+	if let Some((arg_at, _)) = positional.iter().find(|(_, used)| !used) {
+		return fail(&arg_at.at, ErrorCode::FormatError, "argument never used".to_string());
+	}
+	if let Some((_, arg_at, _)) = named.iter().find(|(.., used)| !used) {
+		return fail(&arg_at.at, ErrorCode::FormatError, "named argument never used".to_string());
+	}
+	let finish = at.method(at.ident("$f"), "finish", Vec::new());
+	statements.push(at.statement(Statement::Returning(*finish.into_inner())));
+	Ok(at.expr(Expr::Block(statements)))
+}
+
+/// How many arguments there are, to tell in errors
+fn arguments(count: usize) -> String {
+	match count {
+		0 => "no arguments were given".to_string(),
+		1 => "there is 1 argument".to_string(),
+		count => format!("there are {} arguments", count),
+	}
+}
+
+/// Implement `core::fmt::Debug` for a struct or an enum, writing it like it's written in the source.
+/// The variants are the fields of a struct, or the names and fields of the variants of an enum
+pub(super) fn derive_debug(
+	derive: &Span<String>,
+	name: &Span<String>,
+	generics: &Option<Generics>,
+	where_clause: &Option<WhereClause>,
+	variants: &[(Option<&Span<String>>, &Fields)],
+) -> TraitImpl {
+	let at = Builder::new(derive);
+	let arms = variants
+		.iter()
+		.map(|(variant, fields)| {
+			let mut path = vec![name.clone()];
+			path.extend(variant.cloned());
+			let shown = path.last().unwrap().to_string();
+			let (pattern, body) = debug_fields(&at, path, shown, fields);
+			MatchArm {
+				pattern,
+				guard: None,
+				body: at.expr(Expr::Block(body)),
+			}
+		})
+		.collect();
+	let body = vec![at.statement(Statement::Returning(Expr::Match(at.ident("self"), arms)))];
+	// Every generic that is a type, and not the length of an array, has to be `Debug` too
+	let mut lengths = Vec::new();
+	for (_, fields) in variants {
+		match fields {
+			Fields::Unit => (),
+			Fields::Tuple(types) => types.iter().for_each(|t| array_lengths(t, &mut lengths)),
+			Fields::Named(fields) => fields.iter().for_each(|(_, t)| array_lengths(t, &mut lengths)),
+		}
+	}
+	let mut bounds = where_clause.clone().unwrap_or_default();
+	for generic in generics.iter().flatten() {
+		if !lengths.contains(&generic.as_str()) {
+			bounds.push((at.type_(generic), vec![at.debug_trait()]));
+		}
+	}
+	TraitImpl {
+		generics: generics.clone(),
+		trait_: at.debug_trait(),
+		type_: at.span(Type::Other {
+			name: name.clone(),
+			generics: generics.iter().flatten().map(|generic| at.type_(generic)).collect(),
+		}),
+		where_clause: Some(bounds),
+		fn_defs: vec![FnDef {
+			pub_kw: None,
+			name: at.span("fmt".to_string()),
+			generics: None,
+			where_clause: None,
+			args: vec![
+				(at.span("self".to_string()), at.type_("Self")),
+				(at.span("f".to_string()), at.type_("core::fmt::Formatter")),
+			],
+			return_type: at.span(Type::Empty),
+			body: at.span(body),
+		}],
+		types: Vec::new(),
+	}
+}
+
+/// The pattern binding the fields of a variant, and the statements writing them
+fn debug_fields(at: &Builder, path: Vec<Span<String>>, shown: String, fields: &Fields) -> (Span<Pattern>, Block) {
+	let write = |text: String| {
+		let write = at.method(at.ident("f"), "write_str", vec![at.string(&text)]);
+		at.statement(Statement::NonReturning(*write.into_inner()))
+	};
+	// A field is written at the span of its type, which is where it's missing `Debug`
+	let debug = |variable: &str, type_: &Span<Type>| {
+		let at = Builder::new(type_);
+		let debug = at.call("core::fmt::debug", vec![at.ident(variable), at.ident("f")]);
+		at.statement(Statement::NonReturning(*debug.into_inner()))
+	};
+	let mut body = Vec::new();
+	let pattern = match fields {
+		Fields::Tuple(types) if !types.is_empty() => {
+			let mut patterns = Vec::new();
+			for (i, type_) in types.iter().enumerate() {
+				let variable = format!("${}", i);
+				body.push(write(if i == 0 { format!("{}(", shown) } else { ", ".to_string() }));
+				body.push(debug(&variable, type_));
+				patterns.push(at.binding(&variable));
+			}
+			body.push(write(")".to_string()));
+			Pattern::TupleStruct(path, patterns)
+		}
+		Fields::Named(fields) if !fields.is_empty() => {
+			let mut patterns = Vec::new();
+			for (i, (field, type_)) in fields.iter().enumerate() {
+				let variable = format!("${}", &**field);
+				let separator = if i == 0 { format!("{} {{ ", shown) } else { ", ".to_string() };
+				body.push(write(format!("{}{}: ", separator, &**field)));
+				body.push(debug(&variable, type_));
+				patterns.push((field.clone(), at.binding(&variable)));
+			}
+			body.push(write(" }".to_string()));
+			Pattern::Struct(path, patterns, false)
+		}
+		Fields::Unit => {
+			body.push(write(shown));
+			Pattern::Path(path)
+		}
+		Fields::Tuple(_) => {
+			body.push(write(shown));
+			Pattern::TupleStruct(path, Vec::new())
+		}
+		Fields::Named(_) => {
+			body.push(write(shown));
+			Pattern::Struct(path, Vec::new(), false)
+		}
+	};
+	(at.span(pattern), body)
+}
+
+/// The generics used as the length of arrays in a type
+fn array_lengths<'t>(type_: &'t Type, lengths: &mut Vec<&'t str>) {
+	match type_ {
+		Type::Empty | Type::Never => (),
+		Type::Array(element, len) => {
+			if let ArrayLen::Named(name) = &**len {
+				lengths.push(name);
+			}
+			array_lengths(element, lengths)
+		}
+		Type::Tuple(types) | Type::Other { generics: types, .. } => {
+			types.iter().for_each(|t| array_lengths(t, lengths))
+		}
+		Type::Fn(args, ret) => {
+			args.iter().for_each(|t| array_lengths(t, lengths));
+			array_lengths(ret, lengths)
+		}
+	}
+}
@@ -2,22 +2,28 @@ use std::fmt::Display;
 
 use pest::{
 	iterators::{Pair, Pairs},
+	pratt_parser::PrattParser,
 	Parser,
 };
 
 use crate::{
 	ast::{
-		AstModule, Block, Expr, FnArg, FnDef, FnSignatureDef, Generics, Impl, Literal, Mod,
-		Statement, Trait, TraitDef, Type, TypeInTrait, WhereClause,
+		ArrayLen, AstModule, Block, ConstDef, ConstKind, EnumDef, Expr, FnArg, FnDef, FnSignatureDef, Fields, Generics,
+		Impl, Label, Literal, MatchArm, Mod, Pattern, Statement, StructDef, Trait, TraitDef, Type, TypeInTrait, Variant,
+		WhereClause,
 	},
+	error::{ErrorCode, NonLocatedError, Return},
 	fs::{File, Fs},
-	span::{BoxedSpan, Span},
+	span::{BoxedSpan, RangedPosition, Span},
 };
 
+mod macros;
+
 #[derive(Parser)]
 #[grammar = "parser/grammar.pest"]
 struct LamaParser;
 
+#[allow(dead_code)] // Prints the parse tree, while debugging the grammar
 fn tree<T: Display>(pairs: Pairs<Rule>, space: T) {
 	for pair in pairs {
 		println!("{}{:?} [{}]", space, pair.as_rule(), pair.as_str());
@@ -25,15 +31,25 @@ fn tree<T: Display>(pairs: Pairs<Rule>, space: T) {
 	}
 }
 
-pub fn parse_module(file: File, fs: &Fs) -> AstModule {
+pub fn parse_module(file: File, fs: &Fs) -> Return<AstModule> {
 	let s = fs.load_file(&file);
-	let pairs = LamaParser::parse(Rule::module, &s).unwrap_or_else(|e| panic!("{}", e));
+	let pairs = match LamaParser::parse(Rule::module, &s) {
+		Ok(pairs) => pairs,
+		Err(e) => {
+			NonLocatedError::new(ErrorCode::SyntaxError, e.with_path(&file.to_string()).to_string())
+				.display()?;
+			unreachable!()
+		}
+	};
 	// tree(pairs.clone(), "");
 	let mut mod_items = Vec::new();
 	let mut fn_items = Vec::new();
 	let mut trait_defs = Vec::new();
 	let mut trait_impls = Vec::new();
 	let mut impls = Vec::new();
+	let mut structs = Vec::new();
+	let mut enums = Vec::new();
+	let mut consts = Vec::new();
 	for pair in pairs {
 		match pair.as_rule() {
 			Rule::EOI => (),
@@ -63,7 +79,7 @@ pub fn parse_module(file: File, fs: &Fs) -> AstModule {
 				let body = Span::new(
 					body.as_span(),
 					file.clone(),
-					parse_block(body.into_inner(), &file),
+					parse_block(body.into_inner(), &file)?,
 				);
 				fn_items.push(FnDef {
 					pub_kw,
@@ -85,23 +101,23 @@ pub fn parse_module(file: File, fs: &Fs) -> AstModule {
 				let mut inner = pair.into_inner();
 				let pub_kw = parse_pub(&mut inner, Rule::trait_kw, file.clone());
 				let name = parse_name(&mut inner, file.clone());
-				let (generics, next) = parse_def_generics(&mut inner, &file);
-				let possible_where = next.unwrap();
-				let (where_clause, next) = if possible_where.as_rule() == Rule::where_clause {
-					(
-						Some(parse_where_clause(possible_where, &file)),
-						inner.next().unwrap(),
-					)
-				} else {
-					(None, possible_where)
+				// The body of the trait may be empty
+				let (generics, next) = match inner.peek() {
+					Some(_) => parse_def_generics(&mut inner, &file),
+					None => (None, None),
+				};
+				let (where_clause, next) = match next {
+					Some(pair) if pair.as_rule() == Rule::where_clause => {
+						(Some(parse_where_clause(pair, &file)), inner.next())
+					}
+					next => (None, next),
 				};
 				let mut fn_signatures = Vec::new();
 				let mut fn_defs = Vec::new();
 				let mut types = Vec::new();
 
-				parse_trait_item(next, &file, &mut types, &mut fn_signatures, &mut fn_defs);
-				for pair in inner {
-					parse_trait_item(pair, &file, &mut types, &mut fn_signatures, &mut fn_defs);
+				for pair in next.into_iter().chain(inner) {
+					parse_trait_item(pair, &file, &mut types, &mut fn_signatures, &mut fn_defs)?;
 				}
 				trait_defs.push(TraitDef {
 					pub_kw,
@@ -122,13 +138,14 @@ pub fn parse_module(file: File, fs: &Fs) -> AstModule {
 				inner.next().unwrap(); // Ignore for kw
 				let type_ = parse_type(inner.next().unwrap(), file.clone());
 
-				let where_clause = if inner.peek().unwrap().as_rule() == Rule::where_clause {
+				// The body of the impl may be empty
+				let where_clause = if inner.peek().is_some_and(|pair| pair.as_rule() == Rule::where_clause) {
 					Some(parse_where_clause(inner.next().unwrap(), &file))
 				} else {
 					None
 				};
-				let trait_impl = parse_impl_inner(inner, &file, type_, generics, where_clause)
-					.to_trait_impl(trait_);
+				let trait_impl = parse_impl_inner(inner, &file, type_, generics, where_clause)?
+					.into_trait_impl(trait_);
 				trait_impls.push(trait_impl);
 			}
 			Rule::impl_item => {
@@ -139,24 +156,107 @@ pub fn parse_module(file: File, fs: &Fs) -> AstModule {
 				// inner.next().unwrap(); // Ignore for kw
 				let type_ = parse_type(next.unwrap(), file.clone());
 
-				let where_clause = if inner.peek().unwrap().as_rule() == Rule::where_clause {
+				// The body of the impl may be empty
+				let where_clause = if inner.peek().is_some_and(|pair| pair.as_rule() == Rule::where_clause) {
 					Some(parse_where_clause(inner.next().unwrap(), &file))
 				} else {
 					None
 				};
-				let impl_ = parse_impl_inner(inner, &file, type_, generics, where_clause);
+				let impl_ = parse_impl_inner(inner, &file, type_, generics, where_clause)?;
 				impls.push(impl_);
 			}
+			Rule::struct_item => {
+				let mut inner = pair.into_inner();
+				let derives = parse_attributes(&mut inner, &file)?;
+				let pub_kw = parse_pub(&mut inner, Rule::struct_kw, file.clone());
+				let name = parse_name(&mut inner, file.clone());
+				// Unit structs may have nothing after their name
+				let (generics, next) = match inner.peek() {
+					Some(_) => parse_def_generics(&mut inner, &file),
+					None => (None, None),
+				};
+				let (where_clause, next) = match next {
+					Some(pair) if pair.as_rule() == Rule::where_clause => {
+						(Some(parse_where_clause(pair, &file)), inner.next())
+					}
+					next => (None, next),
+				};
+				let def = StructDef {
+					pub_kw,
+					name,
+					generics,
+					where_clause,
+					fields: parse_fields(next, &file),
+				};
+				for derive in derives {
+					let variants = [(None, &def.fields)];
+					trait_impls.push(macros::derive_debug(&derive, &def.name, &def.generics, &def.where_clause, &variants));
+				}
+				structs.push(def)
+			}
+			Rule::enum_item => {
+				let mut inner = pair.into_inner();
+				let derives = parse_attributes(&mut inner, &file)?;
+				let pub_kw = parse_pub(&mut inner, Rule::enum_kw, file.clone());
+				let name = parse_name(&mut inner, file.clone());
+				let (generics, next) = match inner.peek() {
+					Some(_) => parse_def_generics(&mut inner, &file),
+					None => (None, None),
+				};
+				let mut variants = Vec::new();
+				let where_clause = match next {
+					Some(pair) if pair.as_rule() == Rule::where_clause => Some(parse_where_clause(pair, &file)),
+					Some(pair) => {
+						variants.push(parse_variant(pair, &file));
+						None
+					}
+					None => None,
+				};
+				variants.extend(inner.map(|pair| parse_variant(pair, &file)));
+				let def = EnumDef {
+					pub_kw,
+					name,
+					generics,
+					where_clause,
+					variants,
+				};
+				for derive in derives {
+					let variants: Vec<_> = def.variants.iter().map(|v| (Some(&v.name), &v.fields)).collect();
+					trait_impls.push(macros::derive_debug(&derive, &def.name, &def.generics, &def.where_clause, &variants));
+				}
+				enums.push(def)
+			}
+			Rule::const_item => {
+				let mut inner = pair.into_inner();
+				let (pub_kw, kw) = parse_maybe_rule(&mut inner, Rule::pub_kw);
+				let kind = match kw.unwrap().as_rule() {
+					Rule::static_kw => ConstKind::Static,
+					_ => ConstKind::Const,
+				};
+				let name = parse_name(&mut inner, file.clone());
+				let type_ = parse_type(inner.next().unwrap(), file.clone());
+				let value = eval_expr(inner.next().unwrap().into_inner(), &file)?;
+				consts.push(ConstDef {
+					pub_kw: pub_kw.map(|pair| Span::new(pair.as_span(), file.clone(), ())),
+					kind,
+					name,
+					type_,
+					value,
+				})
+			}
 			x => unreachable!("Unknown item: {:?}", x),
 		}
 	}
-	AstModule {
+	Ok(AstModule {
 		mods: mod_items,
 		fns: fn_items,
 		trait_defs,
 		trait_impls,
 		impls,
-	}
+		structs,
+		enums,
+		consts,
+	})
 }
 
 fn parse_impl_inner(
@@ -165,7 +265,7 @@ fn parse_impl_inner(
 	type_: Span<Type>,
 	generics: Option<Generics>,
 	where_clause: Option<WhereClause>,
-) -> Impl {
+) -> Return<Impl> {
 	let mut fn_defs = Vec::new();
 	let mut types = Vec::new();
 	for item in pairs {
@@ -181,7 +281,7 @@ fn parse_impl_inner(
 					let name = parse_name(&mut inner, file.clone());
 					let generics = inner
 						.map(|pair| {
-							Span::new(pair.as_span(), file.clone(), pair.as_str().to_string())
+							Span::new(pair.as_span(), file.clone(), pair.as_str().trim().to_string())
 						})
 						.collect();
 					Span::new(span, file.clone(), TypeInTrait { name, generics })
@@ -193,8 +293,8 @@ fn parse_impl_inner(
 				let mut inner = item.into_inner();
 				let pub_kw = parse_pub(&mut inner, Rule::fn_kw, file.clone());
 				let name = parse_name(&mut inner, file.clone());
-				let (generics, next) = parse_def_generics(&mut inner, &file);
-				let args = parse_fn_def_args(next.unwrap(), &file);
+				let (generics, next) = parse_def_generics(&mut inner, file);
+				let args = parse_fn_def_args(next.unwrap(), file);
 				let return_type = {
 					let next = inner.next().unwrap();
 					let span = next.as_span();
@@ -206,7 +306,7 @@ fn parse_impl_inner(
 				let possible_where = inner.next().unwrap();
 				let (where_clause, body) = if possible_where.as_rule() == Rule::where_clause {
 					(
-						Some(parse_where_clause(possible_where, &file)),
+						Some(parse_where_clause(possible_where, file)),
 						inner.next().unwrap(),
 					)
 				} else {
@@ -215,7 +315,7 @@ fn parse_impl_inner(
 				let body = Span::new(
 					body.as_span(),
 					file.clone(),
-					parse_block(body.into_inner(), &file),
+					parse_block(body.into_inner(), file)?,
 				);
 				fn_defs.push(FnDef {
 					pub_kw,
@@ -230,13 +330,13 @@ fn parse_impl_inner(
 			_ => unreachable!(),
 		}
 	}
-	Impl {
+	Ok(Impl {
 		generics,
 		type_,
 		where_clause,
 		fn_defs,
 		types,
-	}
+	})
 }
 
 fn parse_trait_item(
@@ -245,7 +345,7 @@ fn parse_trait_item(
 	types: &mut Vec<(Span<TypeInTrait>, Vec<Span<Trait>>)>,
 	fn_signatures: &mut Vec<FnSignatureDef>,
 	fn_defs: &mut Vec<FnDef>,
-) {
+) -> Return<()> {
 	match pair.as_rule() {
 		Rule::trait_type => {
 			let mut inner = pair.into_inner();
@@ -256,7 +356,7 @@ fn parse_trait_item(
 				let mut inner = inner.into_inner();
 				let name = parse_name(&mut inner, file.clone());
 				let generics = inner
-					.map(|pair| Span::new(pair.as_span(), file.clone(), pair.as_str().to_string()))
+					.map(|pair| Span::new(pair.as_span(), file.clone(), pair.as_str().trim().to_string()))
 					.collect();
 				Span::new(span, file.clone(), TypeInTrait { name, generics })
 			};
@@ -267,8 +367,8 @@ fn parse_trait_item(
 			let mut inner = pair.into_inner();
 			let pub_kw = parse_pub(&mut inner, Rule::fn_kw, file.clone());
 			let name = parse_name(&mut inner, file.clone());
-			let (generics, next) = parse_def_generics(&mut inner, &file);
-			let args = parse_fn_def_args(next.unwrap(), &file);
+			let (generics, next) = parse_def_generics(&mut inner, file);
+			let args = parse_fn_def_args(next.unwrap(), file);
 			let return_type = {
 				let next = inner.next().unwrap();
 				let span = next.as_span();
@@ -280,7 +380,7 @@ fn parse_trait_item(
 			let (where_clause, body) = if let Some(possible_where) = inner.next() {
 				if possible_where.as_rule() == Rule::where_clause {
 					(
-						Some(parse_where_clause(possible_where, &file)),
+						Some(parse_where_clause(possible_where, file)),
 						inner.next(),
 					)
 				} else {
@@ -289,13 +389,14 @@ fn parse_trait_item(
 			} else {
 				(None, None)
 			};
-			let body = body.map(|body| {
-				Span::new(
+			let body = match body {
+				Some(body) => Some(Span::new(
 					body.as_span(),
 					file.clone(),
-					parse_block(body.into_inner(), &file),
-				)
-			});
+					parse_block(body.into_inner(), file)?,
+				)),
+				None => None,
+			};
 
 			if let Some(body) = body {
 				fn_defs.push(FnDef {
@@ -320,9 +421,35 @@ fn parse_trait_item(
 		}
 		_ => unreachable!(),
 	}
+	Ok(())
 }
 
 /// Parse a possible pub keyword and ignore the next pair
+/// Parse the attributes of an item, giving back the traits it derives
+fn parse_attributes(inner: &mut Pairs<Rule>, file: &File) -> Return<Vec<Span<String>>> {
+	let mut derives = Vec::new();
+	while inner.peek().is_some_and(|pair| pair.as_rule() == Rule::attribute) {
+		let mut attribute = inner.next().unwrap().into_inner();
+		let name = parse_name(&mut attribute, file.clone());
+		if name.as_str() != "derive" {
+			name.as_error(ErrorCode::UnknownNameError, format!("cannot find attribute `{}` in this scope", &*name))
+				.display()?;
+			unreachable!()
+		}
+		for pair in attribute {
+			let derive = Span::new(pair.as_span(), file.clone(), pair.as_str().trim().to_string());
+			if derive.as_str() != "Debug" {
+				derive
+					.as_error(ErrorCode::UnknownNameError, format!("cannot find derive macro `{}` in this scope", &*derive))
+					.display()?;
+				unreachable!()
+			}
+			derives.push(derive);
+		}
+	}
+	Ok(derives)
+}
+
 fn parse_pub(inner: &mut Pairs<Rule>, next_rule: Rule, file: File) -> Option<Span<()>> {
 	parse_maybe_rule_matching_next(inner, Rule::pub_kw, next_rule)
 		.unwrap() // Assume there must be a next expression must match thanks to the PEG definition in grammar.pest
@@ -344,7 +471,7 @@ fn parse_type(typ: Pair<Rule>, file: File) -> Span<Type> {
 		Rule::ident =>
 		/* Other type */
 		{
-			let name = Span::new(first.as_span(), file.clone(), first.as_str().to_string());
+			let name = Span::new(first.as_span(), file.clone(), first.as_str().trim().to_string());
 			let generics = if let Some(p) = inner.next() {
 				parse_generics(p, &file)
 			} else {
@@ -353,7 +480,30 @@ fn parse_type(typ: Pair<Rule>, file: File) -> Span<Type> {
 
 			Type::Other { name, generics }
 		}
+		Rule::fn_type => {
+			let mut inner = first.into_inner();
+			inner.next().unwrap(); // Ignore the fn_kw
+			let args = parse_generics(inner.next().unwrap(), &file);
+			Type::Fn(args, Box::new(parse_fn_return(inner.next().unwrap(), &file)))
+		}
 		Rule::empty_type => Type::Empty,
+		Rule::never_type => Type::Never,
+		Rule::tuple_type => Type::Tuple(
+			first
+				.into_inner()
+				.map(|t| parse_type(t, file.clone()))
+				.collect(),
+		),
+		Rule::array_type => {
+			let mut inner = first.into_inner();
+			let element = parse_type(inner.next().unwrap(), file.clone());
+			let len = inner.next().unwrap();
+			let content = match len.as_rule() {
+				Rule::array_len => ArrayLen::Literal(len.as_str().replace('_', "").parse().unwrap_or(usize::MAX)),
+				_ => ArrayLen::Named(len.as_str().trim().to_string()),
+			};
+			Type::Array(Box::new(element), Span::new(len.as_span(), file.clone(), content))
+		}
 		_ => unreachable!(),
 	};
 	Span::new(span, file, content)
@@ -368,11 +518,19 @@ fn parse_trait(trait_: Pair<Rule>, file: File) -> Span<Trait> {
 		Rule::ident =>
 		/* Other type */
 		{
-			let name = Span::new(first.as_span(), file.clone(), first.as_str().to_string());
-			let generics = if let Some(p) = inner.next() {
-				parse_generics(p, &file)
-			} else {
-				Vec::new()
+			let name = Span::new(first.as_span(), file.clone(), first.as_str().trim().to_string());
+			let generics = match inner.next() {
+				Some(p) if p.as_rule() == Rule::fn_type_arguments => {
+					// The arguments are a tuple, `Fn()` takes `()`
+					let args = Span::new(p.as_span(), file.clone(), Type::Tuple(parse_generics(p, &file)));
+					let args = match &*args {
+						Type::Tuple(types) if types.is_empty() => Span::new_ref(&args, |_| Type::Empty),
+						_ => args,
+					};
+					vec![args, parse_fn_return(inner.next().unwrap(), &file)]
+				}
+				Some(p) => parse_generics(p, &file),
+				None => Vec::new(),
 			};
 
 			Trait { name, generics }
@@ -382,11 +540,20 @@ fn parse_trait(trait_: Pair<Rule>, file: File) -> Span<Trait> {
 	Span::new(span, file, content)
 }
 
+/// Parse the return type of a function type, which is `()` if it isn't written
+fn parse_fn_return(pair: Pair<Rule>, file: &File) -> Span<Type> {
+	let span = pair.as_span();
+	pair.into_inner()
+		.next()
+		.map(|x| parse_type(x, file.clone()))
+		.unwrap_or(Span::new(span, file.clone(), Type::Empty))
+}
+
 /// Parse definition generics
-fn parse_def_generics<'a>(
-	inner: &'a mut Pairs<Rule>,
+fn parse_def_generics<'i>(
+	inner: &mut Pairs<'i, Rule>,
 	file: &File,
-) -> (Option<Generics>, Option<Pair<'a, Rule>>) {
+) -> (Option<Generics>, Option<Pair<'i, Rule>>) {
 	let (generics, next) = parse_maybe_rule(inner, Rule::def_generics);
 	(
 		generics.map(|x| {
@@ -396,7 +563,7 @@ fn parse_def_generics<'a>(
 				generics.push(Span::new(
 					pair.as_span(),
 					file.clone(),
-					pair.as_str().to_string(),
+					pair.as_str().trim().to_string(),
 				))
 			}
 			generics
@@ -430,9 +597,20 @@ fn parse_where_clause(inner: Pair<Rule>, file: &File) -> WhereClause {
 }
 
 /// Parse function def arguments
-fn parse_fn_def_args<'a>(arguments: Pair<Rule>, file: &File) -> Vec<FnArg> {
+fn parse_fn_def_args(arguments: Pair<Rule>, file: &File) -> Vec<FnArg> {
 	let mut r = Vec::new();
 	for argument in arguments.into_inner() {
+		if argument.as_rule() == Rule::self_arg {
+			// `self` is short for `self: Self`
+			let name = Span::new(argument.as_span(), file.clone(), "self".to_string());
+			let typ = Span::new(
+				argument.as_span(),
+				file.clone(),
+				Type::Other { name: Span::new(argument.as_span(), file.clone(), "Self".to_string()), generics: vec![] },
+			);
+			r.push((name, typ));
+			continue;
+		}
 		let mut inner = argument.into_inner();
 		let name = parse_name(&mut inner, file.clone());
 		let typ = parse_type(inner.next().unwrap(), file.clone());
@@ -440,6 +618,26 @@ fn parse_fn_def_args<'a>(arguments: Pair<Rule>, file: &File) -> Vec<FnArg> {
 	}
 	r
 }
+/// Parse the fields of a struct or of an enum variant, which has none if there isn't a pair
+fn parse_fields(fields: Option<Pair<Rule>>, file: &File) -> Fields {
+	match fields {
+		None => Fields::Unit,
+		Some(pair) if pair.as_rule() == Rule::tuple_fields => {
+			Fields::Tuple(pair.into_inner().map(|t| parse_type(t, file.clone())).collect())
+		}
+		Some(pair) => Fields::Named(parse_fn_def_args(pair.into_inner().next().unwrap(), file)),
+	}
+}
+
+fn parse_variant(pair: Pair<Rule>, file: &File) -> Variant {
+	let mut inner = pair.into_inner();
+	let name = parse_name(&mut inner, file.clone());
+	Variant {
+		name,
+		fields: parse_fields(inner.next(), file),
+	}
+}
+
 /// Parse a possible pair and ignore the next pair
 fn parse_maybe_rule_matching_next<'a>(
 	inner: &'a mut Pairs<Rule>,
@@ -455,10 +653,10 @@ fn parse_maybe_rule_matching_next<'a>(
 }
 
 /// Parse a possible pair and return the next one
-fn parse_maybe_rule<'a>(
-	inner: &'a mut Pairs<Rule>,
+fn parse_maybe_rule<'i>(
+	inner: &mut Pairs<'i, Rule>,
 	rule: Rule,
-) -> (Option<Pair<'a, Rule>>, Option<Pair<'a, Rule>>) {
+) -> (Option<Pair<'i, Rule>>, Option<Pair<'i, Rule>>) {
 	let possible_pub = inner.next().unwrap();
 	match possible_pub.as_rule() {
 		x if x == rule => (Some(possible_pub), inner.next()),
@@ -500,29 +698,44 @@ fn parse_maybe_rule<'a>(
 // }
 
 lazy_static! {
-	static ref PREC_CLIMBER: pest::prec_climber::PrecClimber<Rule> = {
-		use pest::prec_climber::{Assoc::*, Operator, PrecClimber};
+	static ref PRATT_PARSER: PrattParser<Rule> = {
+		use pest::pratt_parser::{Assoc::*, Op};
 		use Rule::*;
 
-		PrecClimber::new(vec![
-			Operator::new(add, Left) | Operator::new(subtract, Left),
-			Operator::new(multiply, Left) | Operator::new(divide, Left),
-			Operator::new(power, Right),
-		])
+		PrattParser::new()
+			.op(Op::infix(assign, Right))
+			.op(Op::infix(range, Left) | Op::infix(range_inclusive, Left))
+			.op(Op::infix(or, Left))
+			.op(Op::infix(and, Left))
+			.op(Op::infix(eq, Left)
+				| Op::infix(ne, Left)
+				| Op::infix(lt, Left)
+				| Op::infix(le, Left)
+				| Op::infix(gt, Left)
+				| Op::infix(ge, Left))
+			.op(Op::infix(add, Left) | Op::infix(subtract, Left))
+			.op(Op::infix(multiply, Left) | Op::infix(divide, Left))
+			.op(Op::infix(power, Right))
 	};
 }
 
-fn eval_expr(pairs: pest::iterators::Pairs<Rule>, file: &File) -> BoxedSpan<Expr> {
-	PREC_CLIMBER.climb(
-		pairs,
-		|pair: pest::iterators::Pair<Rule>| match pair.as_rule() {
+fn eval_expr(pairs: pest::iterators::Pairs<Rule>, file: &File) -> Return<BoxedSpan<Expr>> {
+	PRATT_PARSER
+		.map_primary(|pair: pest::iterators::Pair<Rule>| match pair.as_rule() {
 			Rule::expr => eval_expr(pair.into_inner(), file),
 			Rule::term => parse_term(pair.into_inner(), file),
 			x => unreachable!("Unexpected rule: {:?} {:?}", x, pair.as_str()),
-		},
-		|lhs: BoxedSpan<Expr>, op: pest::iterators::Pair<Rule>, rhs: BoxedSpan<Expr>| match op
-			.as_rule()
-		{
+		})
+		.map_infix(|lhs: Return<BoxedSpan<Expr>>, op: pest::iterators::Pair<Rule>, rhs: Return<BoxedSpan<Expr>>| {
+			let (lhs, rhs) = (lhs?, rhs?);
+			Ok(match op.as_rule() {
+			Rule::assign => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Assign(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::range => range(&op, "Range", lhs, rhs, file),
+			Rule::range_inclusive => range(&op, "RangeInclusive", lhs, rhs, file),
 			Rule::add => BoxedSpan::boxed_from_inner(
 				&[lhs.as_range(), rhs.as_range()],
 				file.clone(),
@@ -548,71 +761,242 @@ fn eval_expr(pairs: pest::iterators::Pairs<Rule>, file: &File) -> BoxedSpan<Expr
 				file.clone(),
 				Expr::Pow(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
 			),
+			Rule::eq => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Eq(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::ne => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Ne(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::lt => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Lt(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::le => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Le(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::gt => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Gt(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::ge => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Ge(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::and => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::And(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
+			Rule::or => BoxedSpan::boxed_from_inner(
+				&[lhs.as_range(), rhs.as_range()],
+				file.clone(),
+				Expr::Or(lhs, Span::new(op.as_span(), file.clone(), ()), rhs),
+			),
 			_ => unreachable!(),
-		},
+			})
+		})
+		.parse(pairs)
+}
+
+/// The call to the constructor of the range type `name` that a range operator stands for
+fn range(op: &Pair<Rule>, name: &str, start: BoxedSpan<Expr>, end: BoxedSpan<Expr>, file: &File) -> BoxedSpan<Expr> {
+	let path = ["core", "ops", name, "new"]
+		.iter()
+		.map(|segment| Span::new(op.as_span(), file.clone(), segment.to_string()))
+		.collect();
+	let constructor = Span::new(op.as_span(), file.clone(), Box::new(Expr::Ident(path)));
+	BoxedSpan::boxed_from_inner(
+		&[start.as_range(), end.as_range()],
+		file.clone(),
+		Expr::FnCall(constructor, vec![start, end]),
 	)
 }
 
-fn parse_term(pairs: pest::iterators::Pairs<Rule>, file: &File) -> BoxedSpan<Expr> {
+/// Split a number literal into its digits, without `_` separators, and its suffix
+fn parse_number(pair: Pair<Rule>, file: &File) -> (String, Option<Span<String>>) {
+	let text = pair.as_str();
+	let suffix = pair
+		.into_inner()
+		.next()
+		.map(|suffix| Span::new(suffix.as_span(), file.clone(), suffix.as_str().to_string()));
+	let digits = &text[..text.len() - suffix.as_ref().map(|s| s.len()).unwrap_or(0)];
+	(digits.replace('_', ""), suffix)
+}
+
+/// Decode an escape sequence, like `\n` or `\u{1F600}`
+fn parse_escape(pair: Pair<Rule>, file: &File) -> Return<char> {
+	let escape = &pair.as_str()[1..];
+	Ok(match escape {
+		"n" => '\n',
+		"t" => '\t',
+		"r" => '\r',
+		"0" => '\0',
+		"\\" => '\\',
+		"\"" => '"',
+		"'" => '\'',
+		_ => {
+			let message = match escape.strip_prefix("u{").and_then(|hex| hex.strip_suffix('}')) {
+				Some(hex) if !hex.is_empty() && hex.len() <= 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+					match char::from_u32(u32::from_str_radix(hex, 16).unwrap()) {
+						Some(c) => return Ok(c),
+						None => "invalid unicode character escape",
+					}
+				}
+				Some(_) => "invalid unicode character escape",
+				None => "unknown character escape",
+			};
+			Span::new(pair.as_span(), file.clone(), ())
+				.as_error(ErrorCode::LiteralError, format!("{} `\\{}`", message, escape))
+				.display()?;
+			unreachable!()
+		}
+	})
+}
+
+fn parse_term(pairs: pest::iterators::Pairs<Rule>, file: &File) -> Return<BoxedSpan<Expr>> {
 	let mut unary_operators = vec![];
-	let mut calls = vec![];
+	let mut postfix = vec![];
+	let mut casts = vec![];
 	let mut middle = None;
 	for pair in pairs {
 		match pair.as_rule() {
 			Rule::unary => unary_operators.push(pair.into_inner().next().unwrap()),
-			Rule::fn_call => calls.push(pair),
-			Rule::value => middle = Some(parse_value(pair.into_inner(), file)),
-			Rule::expr => middle = Some(eval_expr(pair.into_inner(), &file)),
+			Rule::fn_call | Rule::index | Rule::method_call | Rule::field | Rule::try_operator => postfix.push(pair),
+			Rule::cast => casts.push(pair),
+			Rule::value => middle = Some(parse_value(pair.into_inner(), file)?),
+			Rule::expr => middle = Some(eval_expr(pair.into_inner(), file)?),
 			x => unreachable!("Unexpected rule in term: {:?} {:?}", x, pair.as_str()),
 		}
 	}
 	let mut middle = middle.unwrap();
-	for call in calls {
-		let mut args = vec![];
-		let span = call.as_span();
-		for pair in call.into_inner() {
-			args.push(eval_expr(pair.into_inner(), file))
-		}
-		middle = BoxedSpan::boxed(span, file.clone(), Expr::FnCall(middle, args));
+	for pair in postfix {
+		let range = [middle.as_range(), RangedPosition::from(pair.as_span())];
+		let expr = match pair.as_rule() {
+			Rule::fn_call => {
+				let mut args = vec![];
+				for pair in pair.into_inner() {
+					args.push(eval_expr(pair.into_inner(), file)?)
+				}
+				Expr::FnCall(middle, args)
+			}
+			Rule::index => Expr::Index(middle, eval_expr(pair.into_inner().next().unwrap().into_inner(), file)?),
+			Rule::method_call => {
+				let mut inner = pair.into_inner();
+				let name = parse_name(&mut inner, file.clone());
+				let mut args = vec![];
+				for pair in inner.next().unwrap().into_inner() {
+					args.push(eval_expr(pair.into_inner(), file)?)
+				}
+				Expr::MethodCall(middle, name, args)
+			}
+			Rule::try_operator => Expr::Try(middle),
+			_ => Expr::Field(middle, parse_name(&mut pair.into_inner(), file.clone())),
+		};
+		middle = BoxedSpan::boxed_from_inner(&range, file.clone(), expr);
 	}
-	for op in unary_operators {
+	// The operator closest to the value applies first
+	for op in unary_operators.into_iter().rev() {
+		let range = [RangedPosition::from(op.as_span()), middle.as_range()];
 		middle = match op.as_rule() {
-			Rule::not => BoxedSpan::boxed(op.as_span(), file.clone(), Expr::Not(middle)),
+			Rule::not => BoxedSpan::boxed_from_inner(&range, file.clone(), Expr::Not(middle)),
+			Rule::negative => BoxedSpan::boxed_from_inner(&range, file.clone(), Expr::Neg(middle)),
 			x => unreachable!("Unexpected rule in unary: {:?} {:?}", x, op.as_str()),
 		}
 	}
-	middle
+	// Casts apply after the unary operators, `-x as u8` is `(-x) as u8`
+	for cast in casts {
+		let type_ = parse_type(cast.into_inner().next().unwrap(), file.clone());
+		let range = [middle.as_range(), type_.as_range()];
+		middle = BoxedSpan::boxed_from_inner(&range, file.clone(), Expr::Cast(middle, type_));
+	}
+	Ok(middle)
 }
 
-fn parse_value(mut pairs: pest::iterators::Pairs<Rule>, file: &File) -> BoxedSpan<Expr> {
+fn parse_value(mut pairs: pest::iterators::Pairs<Rule>, file: &File) -> Return<BoxedSpan<Expr>> {
 	let pair = pairs.next().unwrap();
-	BoxedSpan::boxed(
+	Ok(BoxedSpan::boxed(
 		pair.as_span(),
 		file.clone(),
 		match pair.as_rule() {
-			Rule::int => Expr::Literal(Literal::Int(pair.as_str().parse().unwrap())),
-			Rule::float => Expr::Literal(Literal::Float(pair.as_str().parse().unwrap())),
-			Rule::string_content => {
-				let s = pair.as_str();
-				Expr::Literal(Literal::String(s.to_string()))
+			Rule::int => {
+				let (digits, suffix) = parse_number(pair, file);
+				let (radix, digits) = match digits.get(..2) {
+					Some("0x") => (16, &digits[2..]),
+					Some("0o") => (8, &digits[2..]),
+					Some("0b") => (2, &digits[2..]),
+					_ => (10, &digits[..]),
+				};
+				// A value that doesn't fit in a u128 is out of range for every type, the checker reports it
+				let value = u128::from_str_radix(digits, radix).unwrap_or(u128::MAX);
+				Expr::Literal(Literal::Int(value, suffix))
+			}
+			Rule::float => {
+				let (digits, suffix) = parse_number(pair, file);
+				// Too big values become infinity, which the checker reports
+				Expr::Literal(Literal::Float(digits.parse().unwrap(), suffix))
+			}
+			Rule::bool => Expr::Literal(Literal::Bool(pair.as_str() == "true")),
+			Rule::tuple => Expr::Tuple(
+				pair.into_inner()
+					.map(|pair| eval_expr(pair.into_inner(), file))
+					.collect::<Return<_>>()?,
+			),
+			Rule::array => Expr::Array(
+				pair.into_inner()
+					.map(|pair| eval_expr(pair.into_inner(), file))
+					.collect::<Return<_>>()?,
+			),
+			Rule::string => {
+				let pair = pair.into_inner().next().unwrap();
+				let s = match pair.as_rule() {
+					Rule::raw_string => pair.into_inner().next().unwrap().as_str().to_string(),
+					_ => {
+						let mut s = String::new();
+						for part in pair.into_inner() {
+							match part.as_rule() {
+								Rule::escape => s.push(parse_escape(part, file)?),
+								_ => s += part.as_str(),
+							}
+						}
+						s
+					}
+				};
+				Expr::Literal(Literal::String(s))
+			}
+			Rule::char => {
+				let pair = pair.into_inner().next().unwrap();
+				let c = match pair.as_rule() {
+					Rule::escape => parse_escape(pair, file)?,
+					_ => pair.as_str().chars().next().unwrap(),
+				};
+				Expr::Literal(Literal::Char(c))
 			}
 			Rule::if_statement => {
 				let mut inner = pair.into_inner();
-				let condition = eval_expr(inner.next().unwrap().into_inner(), &file);
-				let block = parse_block(inner.next().unwrap().into_inner(), &file);
+				let condition = parse_condition(inner.next().unwrap(), file)?;
+				let block = parse_block(inner.next().unwrap().into_inner(), file)?;
 				let mut elseif_clauses = vec![];
 				let mut else_clause = None;
 				for clause in inner {
 					match clause.as_rule() {
 						Rule::elseif_clause => {
 							let mut inner = clause.into_inner();
-							let condition = eval_expr(inner.next().unwrap().into_inner(), &file);
-							let block = parse_block(inner.next().unwrap().into_inner(), &file);
+							let condition = parse_condition(inner.next().unwrap(), file)?;
+							let block = parse_block(inner.next().unwrap().into_inner(), file)?;
 							elseif_clauses.push((condition, block))
 						}
 						Rule::else_clause => {
 							let mut inner = clause.into_inner();
-							let block = parse_block(inner.next().unwrap().into_inner(), &file);
+							let block = parse_block(inner.next().unwrap().into_inner(), file)?;
 							else_clause = Some(block);
 						}
 						_ => unreachable!(),
@@ -620,10 +1004,244 @@ fn parse_value(mut pairs: pest::iterators::Pairs<Rule>, file: &File) -> BoxedSpa
 				}
 				Expr::If(condition, block, elseif_clauses, else_clause)
 			}
-			Rule::ident => Expr::Ident(parse_path(pair, &file)),
+			Rule::closure => {
+				let mut inner = pair.into_inner();
+				let mut params = Vec::new();
+				for param in inner.next().unwrap().into_inner() {
+					let mut param = param.into_inner();
+					let pattern = parse_pattern(param.next().unwrap(), file)?;
+					params.push((pattern, param.next().map(|t| parse_type(t, file.clone()))));
+				}
+				let mut body = inner.next().unwrap();
+				let mut return_type = None;
+				if body.as_rule() == Rule::generic_type {
+					return_type = Some(parse_type(body, file.clone()));
+					body = inner.next().unwrap();
+				}
+				let body = match body.as_rule() {
+					Rule::block => {
+						BoxedSpan::boxed(body.as_span(), file.clone(), Expr::Block(parse_block(body.into_inner(), file)?))
+					}
+					_ => eval_expr(body.into_inner(), file)?,
+				};
+				Expr::Closure(params, return_type, body)
+			}
+			Rule::match_expr => {
+				let mut inner = pair.into_inner();
+				let scrutinee = eval_expr(inner.next().unwrap().into_inner(), file)?;
+				let arms = inner
+					.map(|arm| {
+						let mut inner = arm.into_inner();
+						let pattern = parse_pattern(inner.next().unwrap(), file)?;
+						let mut guard = None;
+						let mut body = inner.next().unwrap();
+						if body.as_rule() == Rule::match_guard {
+							guard = Some(eval_expr(body.into_inner().next().unwrap().into_inner(), file)?);
+							body = inner.next().unwrap();
+						}
+						let body = match body.as_rule() {
+							Rule::block => {
+								BoxedSpan::boxed(body.as_span(), file.clone(), Expr::Block(parse_block(body.into_inner(), file)?))
+							}
+							_ => eval_expr(body.into_inner(), file)?,
+						};
+						Ok(MatchArm { pattern, guard, body })
+					})
+					.collect::<Return<_>>()?;
+				Expr::Match(scrutinee, arms)
+			}
+			Rule::while_loop => {
+				let mut inner = pair.into_inner();
+				let label = parse_label(&mut inner, file);
+				let condition = parse_condition(inner.next().unwrap(), file)?;
+				let block = parse_block(inner.next().unwrap().into_inner(), file)?;
+				Expr::While(label, condition, block)
+			}
+			Rule::infinite_loop => {
+				let mut inner = pair.into_inner();
+				let label = parse_label(&mut inner, file);
+				let block = parse_block(inner.next().unwrap().into_inner(), file)?;
+				Expr::Loop(label, block)
+			}
+			Rule::for_loop => {
+				let mut inner = pair.into_inner();
+				let label = parse_label(&mut inner, file);
+				let pattern = parse_pattern(inner.next().unwrap(), file)?;
+				let iterator = eval_expr(inner.next().unwrap().into_inner(), file)?;
+				let block = parse_block(inner.next().unwrap().into_inner(), file)?;
+				Expr::For(label, pattern, iterator, block)
+			}
+			Rule::break_expr => {
+				let mut inner = pair.into_inner();
+				let label = parse_label(&mut inner, file);
+				let value = match inner.next() {
+					Some(x) => Some(eval_expr(x.into_inner(), file)?),
+					None => None,
+				};
+				Expr::Break(label, value)
+			}
+			Rule::continue_expr => {
+				let mut inner = pair.into_inner();
+				Expr::Continue(parse_label(&mut inner, file))
+			}
+			Rule::struct_literal => {
+				let mut inner = pair.into_inner();
+				let path = parse_path(inner.next().unwrap(), file);
+				let fields = inner
+					.map(|field| {
+						let mut inner = field.into_inner();
+						let name = parse_name(&mut inner, file.clone());
+						Ok((name, eval_expr(inner.next().unwrap().into_inner(), file)?))
+					})
+					.collect::<Return<_>>()?;
+				Expr::Struct(path, fields)
+			}
+			Rule::macro_call => {
+				let span = Span::new(pair.as_span(), file.clone(), ());
+				let mut inner = pair.into_inner();
+				let name = parse_name(&mut inner, file.clone());
+				let args = inner
+					.map(|pair| eval_expr(pair.into_inner(), file))
+					.collect::<Return<_>>()?;
+				macros::expand_macro(&span, &name, args)?
+			}
+			Rule::ident => Expr::Ident(parse_path(pair, file)),
+			Rule::return_expr => Expr::Return(match pair.into_inner().next() {
+				Some(x) => Some(eval_expr(x.into_inner(), file)?),
+				None => None,
+			}),
 			x => unreachable!("Unexpected value: {:?}", x),
 		},
-	)
+	))
+}
+
+/// Parse a possible loop label
+fn parse_label(inner: &mut Pairs<Rule>, file: &File) -> Option<Label> {
+	match inner.peek() {
+		Some(pair) if pair.as_rule() == Rule::label => {
+			let pair = inner.next().unwrap();
+			Some(Span::new(pair.as_span(), file.clone(), pair.as_str().to_string()))
+		}
+		_ => None,
+	}
+}
+
+/// Parse a pattern, as found in `let` statements, `for` loops or `match` arms
+/// The condition of `if` and `while`, which can be a `let` matching a pattern
+fn parse_condition(pair: Pair<Rule>, file: &File) -> Return<BoxedSpan<Expr>> {
+	match pair.as_rule() {
+		Rule::let_condition => {
+			let span = pair.as_span();
+			let mut inner = pair.into_inner();
+			let pattern = parse_pattern(inner.next().unwrap(), file)?;
+			let value = eval_expr(inner.next().unwrap().into_inner(), file)?;
+			Ok(BoxedSpan::boxed(span, file.clone(), Expr::Let(pattern, value)))
+		}
+		_ => eval_expr(pair.into_inner(), file),
+	}
+}
+
+fn parse_pattern(pair: Pair<Rule>, file: &File) -> Return<Span<Pattern>> {
+	let span = pair.as_span();
+	if pair.as_rule() == Rule::or_pattern {
+		let mut alternatives = pair
+			.into_inner()
+			.map(|p| parse_pattern(p, file))
+			.collect::<Return<Vec<_>>>()?;
+		return Ok(if alternatives.len() == 1 {
+			alternatives.pop().unwrap()
+		} else {
+			Span::new(span, file.clone(), Pattern::Or(alternatives))
+		});
+	}
+	let pattern = pair.into_inner().next().unwrap();
+	let content = match pattern.as_rule() {
+		Rule::wildcard_pattern => Pattern::Wildcard,
+		Rule::literal_pattern => {
+			let mut inner = pattern.into_inner();
+			let negated = inner.peek().unwrap().as_rule() == Rule::negative;
+			if negated {
+				inner.next();
+			}
+			match *parse_value(inner, file)?.into_inner() {
+				Expr::Literal(literal) => Pattern::Literal(literal, negated),
+				x => unreachable!("Unexpected literal pattern: {:?}", x),
+			}
+		}
+		Rule::binding_pattern => {
+			let mut inner = pattern.into_inner();
+			let mutable = if inner.peek().unwrap().as_rule() == Rule::mut_kw {
+				Some(Span::new(inner.next().unwrap().as_span(), file.clone(), ()))
+			} else {
+				None
+			};
+			let name = parse_name(&mut inner, file.clone());
+			let subpattern = match inner.next() {
+				Some(p) => Some(Box::new(parse_pattern(p, file)?)),
+				None => None,
+			};
+			Pattern::Binding {
+				mutable,
+				name,
+				subpattern,
+			}
+		}
+		Rule::tuple_pattern | Rule::array_pattern => {
+			let tuple = pattern.as_rule() == Rule::tuple_pattern;
+			let patterns = pattern
+				.into_inner()
+				.map(|p| parse_pattern(p, file))
+				.collect::<Return<_>>()?;
+			if tuple {
+				Pattern::Tuple(patterns)
+			} else {
+				Pattern::Array(patterns)
+			}
+		}
+		Rule::paren_pattern => return parse_pattern(pattern.into_inner().next().unwrap(), file),
+		Rule::path_pattern => Pattern::Path(parse_path(pattern.into_inner().next().unwrap(), file)),
+		Rule::tuple_struct_pattern => {
+			let mut inner = pattern.into_inner();
+			let path = parse_path(inner.next().unwrap(), file);
+			Pattern::TupleStruct(path, inner.map(|p| parse_pattern(p, file)).collect::<Return<_>>()?)
+		}
+		Rule::struct_pattern => {
+			let mut inner = pattern.into_inner();
+			let path = parse_path(inner.next().unwrap(), file);
+			let mut fields = Vec::new();
+			let mut rest = false;
+			for field in inner {
+				if field.as_rule() == Rule::rest_pattern {
+					rest = true;
+					continue;
+				}
+				let field_span = field.as_span();
+				let mut inner = field.into_inner();
+				let mutable = match inner.peek().unwrap().as_rule() {
+					Rule::mut_kw => Some(Span::new(inner.next().unwrap().as_span(), file.clone(), ())),
+					_ => None,
+				};
+				let name = parse_name(&mut inner, file.clone());
+				// `x` is short for `x: x`
+				let pattern = match inner.next() {
+					Some(p) => parse_pattern(p, file)?,
+					None => Span::new(
+						field_span,
+						file.clone(),
+						Pattern::Binding {
+							mutable,
+							name: name.clone(),
+							subpattern: None,
+						},
+					),
+				};
+				fields.push((name, pattern));
+			}
+			Pattern::Struct(path, fields, rest)
+		}
+		x => unreachable!("Unexpected pattern: {:?}", x),
+	};
+	Ok(Span::new(span, file.clone(), content))
 }
 
 fn parse_path(pair: Pair<Rule>, file: &File) -> Vec<Span<String>> {
@@ -633,19 +1251,44 @@ fn parse_path(pair: Pair<Rule>, file: &File) -> Vec<Span<String>> {
 		.collect()
 }
 
-fn parse_block(pairs: pest::iterators::Pairs<Rule>, file: &File) -> Block {
+fn parse_block(pairs: pest::iterators::Pairs<Rule>, file: &File) -> Return<Block> {
 	let mut statements = vec![];
 	for statement in pairs {
 		match statement.as_rule() {
 			Rule::statement => statements.push(
-				eval_expr(statement.into_inner(), file).map(|x| Box::new(Statement::Returning(*x))),
+				eval_expr(statement.into_inner(), file)?.map(|x| Box::new(Statement::Returning(*x))),
 			),
 			Rule::non_returning_statement => statements.push(
-				eval_expr(statement.into_inner(), file)
+				eval_expr(statement.into_inner(), file)?
+					.map(|x| Box::new(Statement::NonReturning(*x))),
+			),
+			Rule::block_like_statement => statements.push(
+				parse_value(statement.into_inner(), file)?
 					.map(|x| Box::new(Statement::NonReturning(*x))),
 			),
+			Rule::let_statement => {
+				let span = statement.as_span();
+				let mut inner = statement.into_inner();
+				let pattern = parse_pattern(inner.next().unwrap(), file)?;
+				let mut type_ = None;
+				let mut value = None;
+				let mut else_block = None;
+				for pair in inner {
+					match pair.as_rule() {
+						Rule::generic_type => type_ = Some(parse_type(pair, file.clone())),
+						Rule::expr => value = Some(eval_expr(pair.into_inner(), file)?),
+						Rule::let_else => else_block = Some(parse_block(pair.into_inner().next().unwrap().into_inner(), file)?),
+						x => unreachable!("Unexpected rule in let: {:?}", x),
+					}
+				}
+				statements.push(BoxedSpan::boxed(
+					span,
+					file.clone(),
+					Statement::Let(pattern, type_, value, else_block),
+				))
+			}
 			_ => unreachable!(),
 		}
 	}
-	statements
+	Ok(statements)
 }
@@ -1,7 +1,4 @@
-use std::{
-	fmt::{Debug, Display},
-	path::PathBuf,
-};
+use std::fmt::{Debug, Display};
 
 use crate::{
 	error::{Error, ErrorCode},
@@ -127,6 +124,15 @@ impl<T> Span<T> {
 		self.range
 	}
 
+	/// Line and column where this span starts
+	pub fn start(&self) -> (usize, usize) {
+		self.range.start
+	}
+
+	pub fn file(&self) -> &File {
+		&self.file
+	}
+
 	pub fn as_error<S: ToString>(&self, error_code: ErrorCode, message: S) -> Error {
 		Error::new(
 			error_code,
@@ -139,10 +145,6 @@ impl<T> Span<T> {
 		)
 	}
 
-	pub fn into_error<S: ToString>(self, error_code: ErrorCode, message: S) -> Error {
-		Error::new(error_code, self.map(|_| ()), message.to_string())
-	}
-
 	pub fn into_inner(self) -> T {
 		self.content
 	}
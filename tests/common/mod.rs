@@ -0,0 +1,140 @@
+//! Running the compiler on Lama programs, with the interpreter or with the js backend and node
+
+#![allow(dead_code)] // Each test crate uses only some of the helpers
+
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+/// The status of programs that panic, as `lamalang::backend::PANIC_EXIT_CODE`
+pub const PANIC_EXIT_CODE: i32 = 102;
+
+/// What running the compiler or a compiled program gave back
+#[derive(Debug)]
+pub struct Run {
+	pub stdout: String,
+	pub stderr: String,
+	pub code: i32,
+}
+
+fn run(command: &mut Command) -> Run {
+	let output = command.output().expect("the command runs");
+	Run {
+		stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+		stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+		code: output.status.code().unwrap_or(-1),
+	}
+}
+
+/// Run the compiler from the root of the repository, where it finds the libraries
+pub fn lamalang(args: &[&str]) -> Run {
+	run(&mut compiler(args))
+}
+
+fn compiler(args: &[&str]) -> Command {
+	let mut command = Command::new(env!("CARGO_BIN_EXE_lamalang"));
+	command.args(args).current_dir(env!("CARGO_MANIFEST_DIR"));
+	command
+}
+
+/// Run a program with the interpreter
+pub fn interpret(program: &Path, flags: &[&str]) -> Run {
+	let mut args = flags.to_vec();
+	args.push(program.to_str().unwrap());
+	lamalang(&args)
+}
+
+/// Whether node is installed to run the js backend
+pub fn has_node() -> bool {
+	Command::new("node").arg("--version").output().is_ok()
+}
+
+/// Compile a program to js and run it with node, or give back the compiler's output if it fails
+pub fn run_js(program: &Path, flags: &[&str]) -> Run {
+	match compile_js(program, flags) {
+		Ok(out) => run(Command::new("node").arg(&out)),
+		Err(compiled) => compiled,
+	}
+}
+
+/// Compile a program to js, giving back where it was written or the compiler's output if it fails
+fn compile_js(program: &Path, flags: &[&str]) -> Result<PathBuf, Run> {
+	let name = format!(
+		"{}{}.js",
+		program.file_stem().unwrap().to_str().unwrap(),
+		flags.concat()
+	);
+	let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+	let mut args = vec!["-b", "js", "-o", out.to_str().unwrap()];
+	args.extend_from_slice(flags);
+	args.push(program.to_str().unwrap());
+	let compiled = lamalang(&args);
+	if compiled.code != 0 {
+		return Err(compiled);
+	}
+	Ok(out)
+}
+
+/// Run a program with each backend that can run here, named by the backend
+pub fn runs(program: &Path, flags: &[&str]) -> Vec<(&'static str, Run)> {
+	runs_with_env(program, flags, &[])
+}
+
+/// Run a program with each backend that can run here, with the environment variables set for the program
+pub fn runs_with_env(program: &Path, flags: &[&str], vars: &[(&str, &str)]) -> Vec<(&'static str, Run)> {
+	let mut args = flags.to_vec();
+	args.push(program.to_str().unwrap());
+	let mut runs = vec![("interpreter", run(compiler(&args).envs(vars.iter().copied())))];
+	if has_node() {
+		let run = match compile_js(program, flags) {
+			Ok(out) => run(Command::new("node").arg(&out).envs(vars.iter().copied())),
+			Err(compiled) => compiled,
+		};
+		runs.push(("js", run));
+	}
+	runs
+}
+
+/// The `.lama` files of a directory of `tests`, relative to the root of the repository, in order
+pub fn lama_files(dir: &str) -> Vec<PathBuf> {
+	let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+	let mut programs: Vec<PathBuf> = fs::read_dir(root.join("tests").join(dir))
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|e| e == "lama"))
+		.map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+		.collect();
+	programs.sort();
+	programs
+}
+
+/// The expected output of a program, in the file with the same name ending in `.out`
+pub fn expected(program: &Path) -> String {
+	golden(program, "out")
+}
+
+/// The contents of the file with the same name as the program, ending in `extension` instead of `.lama`
+pub fn golden(program: &Path, extension: &str) -> String {
+	let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(program.with_extension(extension));
+	fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing {}", path.display()))
+}
+
+/// Fail with every mismatch found, so that one run shows all of them
+pub fn report(failures: Vec<String>) {
+	assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+/// A mismatch between what a program printed and what was expected
+pub fn mismatch(program: &Path, how: &str, expected: &str, found: &Run) -> String {
+	format!(
+		"{} ({}):\n--- expected\n{}--- found (exit code {})\n{}{}",
+		program.display(),
+		how,
+		expected,
+		found.code,
+		found.stdout,
+		found.stderr
+	)
+}
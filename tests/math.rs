@@ -0,0 +1,25 @@
+//! The functions of `std::math` give the same bits in both backends: the programs of `tests/math` print what their
+//! `.out` file has with the interpreter, and exactly the same with the js backend
+
+mod common;
+
+use common::{expected, has_node, interpret, lama_files, mismatch, report, run_js};
+
+#[test]
+fn same_output_in_both_backends() {
+	let mut failures = Vec::new();
+	for program in lama_files("math") {
+		let expected = expected(&program);
+		let interpreted = interpret(&program, &[]);
+		if interpreted.stdout != expected || interpreted.code != 0 {
+			failures.push(mismatch(&program, "interpreter", &expected, &interpreted));
+		}
+		if has_node() {
+			let js = run_js(&program, &[]);
+			if js.stdout != interpreted.stdout || js.code != interpreted.code {
+				failures.push(mismatch(&program, "js, against the interpreter", &interpreted.stdout, &js));
+			}
+		}
+	}
+	report(failures);
+}
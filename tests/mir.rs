@@ -0,0 +1,41 @@
+//! The optimisations of the MIR: the programs of `tests/mir`, one for each pass, give the dump of their
+//! `.O0.mir` and `.O3.mir` files with `--emit=mir`, and every test program prints the same at `-O0` and `-O3`
+
+mod common;
+
+use common::{golden, lama_files, lamalang, mismatch, report, runs, Run};
+
+#[test]
+fn golden_dumps() {
+	let mut failures = Vec::new();
+	for program in lama_files("mir") {
+		for level in ["-O0", "-O3"].iter() {
+			let expected = golden(&program, &format!("{}.mir", &level[1..]));
+			let dump = lamalang(&["--emit=mir", level, program.to_str().unwrap()]);
+			if dump.stdout != expected || dump.code != 0 {
+				failures.push(mismatch(&program, &format!("--emit=mir {}", level), &expected, &dump));
+			}
+		}
+	}
+	report(failures);
+}
+
+/// What a run shows, the panic message and where it happened included, but not the rest of the backtrace
+fn outcome(run: &Run) -> (&str, i32, Option<&str>) {
+	(&run.stdout, run.code, run.stderr.lines().next())
+}
+
+#[test]
+fn same_output_at_every_level() {
+	let mut failures = Vec::new();
+	let dirs = ["programs", "panics", "math", "mir"];
+	for program in dirs.iter().flat_map(|dir| lama_files(dir)) {
+		for ((backend, unoptimised), (_, optimised)) in runs(&program, &["-O0"]).iter().zip(runs(&program, &["-O3"])) {
+			if outcome(&optimised) != outcome(unoptimised) {
+				let expected = format!("{}{}", unoptimised.stdout, unoptimised.stderr);
+				failures.push(mismatch(&program, &format!("{} -O3, against -O0", backend), &expected, &optimised));
+			}
+		}
+	}
+	report(failures);
+}
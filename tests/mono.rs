@@ -0,0 +1,18 @@
+//! Monomorphisation: the programs of `tests/mono` give the instances of their `.mono` file with `--emit=mono`
+
+mod common;
+
+use common::{golden, lama_files, lamalang, mismatch, report};
+
+#[test]
+fn golden_dumps() {
+	let mut failures = Vec::new();
+	for program in lama_files("mono") {
+		let expected = golden(&program, "mono");
+		let dump = lamalang(&["--no-std", "--emit=mono", program.to_str().unwrap()]);
+		if dump.stdout != expected || dump.code != 0 {
+			failures.push(mismatch(&program, "--emit=mono", &expected, &dump));
+		}
+	}
+	report(failures);
+}
@@ -0,0 +1,140 @@
+//! The programs of `tests/programs` print what their `.out` file has, in both backends, and the ones of `tests/panics`
+//! also panic with the message at its end. The ones of `tests/errors` are rejected by the compiler with the diagnostic
+//! of their `.out` file
+
+mod common;
+
+use std::path::Path;
+
+use common::{expected, interpret, lama_files, mismatch, report, runs, runs_with_env, Run, PANIC_EXIT_CODE};
+
+fn check_runs(dir: &str, code: i32) {
+	let mut failures = Vec::new();
+	for program in lama_files(dir) {
+		let expected = expected(&program);
+		for (backend, run) in runs(&program, &[]) {
+			if run.stdout.clone() + &run.stderr != expected || run.code != code {
+				failures.push(mismatch(&program, backend, &expected, &run));
+			}
+		}
+	}
+	report(failures);
+}
+
+#[test]
+fn programs() {
+	check_runs("programs", 0);
+}
+
+#[test]
+fn panics() {
+	check_runs("panics", PANIC_EXIT_CODE);
+}
+
+#[test]
+fn errors() {
+	let mut failures = Vec::new();
+	for program in lama_files("errors") {
+		let expected = expected(&program);
+		let run = interpret(&program, &[]);
+		if run.stdout != expected || run.code == 0 || run.code == PANIC_EXIT_CODE {
+			failures.push(mismatch(&program, "compiler", &expected, &run));
+		}
+	}
+	report(failures);
+}
+
+#[test]
+fn release_wraps_around() {
+	let program = Path::new("tests/panics/overflow.lama");
+	let mut failures = Vec::new();
+	for (backend, run) in runs(program, &["--release"]) {
+		if run.stdout != "0\n" || run.code != 0 {
+			failures.push(mismatch(program, backend, "0\n", &run));
+		}
+	}
+	report(failures);
+}
+
+#[test]
+fn gc_stress() {
+	let mut failures = Vec::new();
+	for program in lama_files("programs") {
+		let expected = expected(&program);
+		let run = interpret(&program, &["--gc-stress"]);
+		if run.stdout.clone() + &run.stderr != expected || run.code != 0 {
+			failures.push(mismatch(&program, "interpreter --gc-stress", &expected, &run));
+		}
+	}
+	report(failures);
+}
+
+/// The program makes thousands of cycles of references, which only fit in the heap if they are collected
+#[test]
+fn gc_collects_cycles() {
+	let program = Path::new("tests/programs/refs.lama");
+	let run = interpret(program, &["--heap-limit", "150", "--gc-stats"]);
+	let expected = Run {
+		stdout: "(5, 2001000, 42, 100)\n".to_string(),
+		stderr: "gc: 27 collections, 4103 objects allocated, 3996 freed, at most 150 live\n".to_string(),
+		code: 0,
+	};
+	check_run(program, run, expected);
+}
+
+/// The program keeps a hundred references live at once
+#[test]
+fn heap_limit() {
+	let program = Path::new("tests/programs/refs.lama");
+	let run = interpret(program, &["--heap-limit", "50"]);
+	let expected = Run {
+		stdout: String::new(),
+		stderr: "thread 'main' panicked at 'out of memory: the heap limit of 50 objects was reached', \
+			tests/programs/refs.lama:24:10\n\
+			note: run with `LAMA_BACKTRACE=1` environment variable to display a backtrace\n"
+			.to_string(),
+		code: PANIC_EXIT_CODE,
+	};
+	check_run(program, run, expected);
+}
+
+/// The program reads and writes files, which all fail instead
+#[test]
+fn sandbox() {
+	let program = Path::new("tests/programs/io.lama");
+	let run = interpret(program, &["--sandbox"]);
+	let denied = "filesystem access is denied by --sandbox";
+	let expected = Run {
+		stdout: format!("no newline, then a newline\n(, {0}, {0}, {0}, false)\n", denied),
+		stderr: "to the standard error\n".to_string(),
+		code: 0,
+	};
+	check_run(program, run, expected);
+}
+
+/// The call stack goes through a library method and a closure, and starts at the builtin that panics
+#[test]
+fn backtrace() {
+	let program = Path::new("tests/panics/expect.lama");
+	let expected = "2 2\n\
+		thread 'main' panicked at 'no number', tests/panics/expect.lama:2:2\n\
+		stack backtrace:\n   \
+		0: core::panic::panic\n             at src/lamalib/core/panic.lama:4:2\n   \
+		1: Option<T>::expect\n             at src/lamalib/core/option.lama:35:20\n   \
+		2: crate::parse\n             at tests/panics/expect.lama:2:2\n   \
+		3: crate::main::{closure}\n             at tests/panics/expect.lama:6:41\n   \
+		4: crate::main\n             at tests/panics/expect.lama:9:17\n";
+	let mut failures = Vec::new();
+	for (backend, run) in runs_with_env(program, &[], &[("LAMA_BACKTRACE", "1")]) {
+		if run.stdout.clone() + &run.stderr != expected || run.code != PANIC_EXIT_CODE {
+			failures.push(mismatch(program, backend, expected, &run));
+		}
+	}
+	report(failures);
+}
+
+fn check_run(program: &Path, run: Run, expected: Run) {
+	if run.stdout != expected.stdout || run.stderr != expected.stderr || run.code != expected.code {
+		report(vec![mismatch(program, "interpreter", &(expected.stdout + &expected.stderr), &run)]);
+	}
+}